@@ -1,4 +1,3 @@
-
 # This file was @generated by crate2nix 0.15.0 with the command:
 #   "generate" "-n" "../nix/nixpkgs.nix" "-f" "./crate2nix/Cargo.toml" "-o" "./crate2nix/Cargo.nix"
 # See https://github.com/kolloch/crate2nix for more info.
@@ -26,12 +25,20 @@
 , extraTargetFlags ? {}
   # Whether to perform release builds: longer compile times, faster binaries.
 , release ? true
+  # The cargo profile to build with, e.g. "release", "dev" or a custom profile.
+  # Its `[profile.*]` settings from the workspace Cargo.toml are passed to rustc.
+, profile ? if release then "release" else "dev"
+  # The version of the rustc used by `buildRustCrateForPkgs`. Evaluation fails for crates
+  # with a newer `rust-version`. If not given, it is the version of `pkgs.rustc` for the
+  # default `buildRustCrateForPkgs` and the check is disabled for a custom one.
+  # Set it to null to disable the check.
+, rustcVersion ? null
   # Additional crate2nix configuration if it exists.
 , crateConfig
   ? if builtins.pathExists ./crate-config.nix
     then pkgs.callPackage ./crate-config.nix {}
     else {}
-}:
+}@callArgs:
 
 rec {
   #
@@ -91,17 +98,27 @@ rec {
     # * `resolvedDependencies`: the selected default features reported by cargo - only included for debugging.
     # * `devDependencies` as of now not used by `buildRustCrate` but used to
     #   inject test dependencies into the build
+    # * `lintFlags`: the rustc flags for the `[lints]` of workspace members.
 
     crates = {
       "aho-corasick" = rec {
         crateName = "aho-corasick";
         version = "1.1.3";
         edition = "2021";
+        rustVersion = "1.60.0";
         sha256 = "05mrpkvdgp5d20y2p989f187ry9diliijgwrs254fs9s1m1x6q4f";
         libName = "aho_corasick";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "Fast multiple substring searching.";
+          homepage = "https://github.com/BurntSushi/aho-corasick";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "memchr";
@@ -128,6 +145,13 @@ rec {
           "Ryan Scheel (Havvy) <ryan.havvy@gmail.com>"
           "Josh Triplett <josh@joshtriplett.org>"
         ];
+        meta = {
+          description = "Library for ANSI terminal colours and styles (bold, underline)";
+          homepage = "https://github.com/ogham/rust-ansi-term";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "winapi";
@@ -145,10 +169,19 @@ rec {
         crateName = "anyhow";
         version = "1.0.86";
         edition = "2018";
+        rustVersion = "1.39.0";
         sha256 = "1nk301x8qhpdaks6a9zvcp7yakjqnczjmqndbg7vk4494d3d1ldk";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Flexible concrete Error type built on std::error::Error";
+          homepage = "https://github.com/dtolnay/anyhow";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "backtrace" = [ "dep:backtrace" ];
           "default" = [ "std" ];
@@ -163,6 +196,13 @@ rec {
         authors = [
           "softprops <d.tangren@gmail.com>"
         ];
+        meta = {
+          description = "A simple interface for querying atty";
+          homepage = "https://github.com/softprops/atty";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "hermit-abi";
@@ -192,6 +232,14 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "A macro to generate structures which behave like bitflags.";
+          homepage = "https://github.com/bitflags/bitflags";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "compiler_builtins" = [ "dep:compiler_builtins" ];
           "core" = [ "dep:core" ];
@@ -203,10 +251,19 @@ rec {
         crateName = "bitflags";
         version = "2.6.0";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1pkidwzn3hnxlsl8zizh0bncgbjnw7c41cx7bby26ncbzmiznj5h";
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "A macro to generate structures which behave like bitflags.";
+          homepage = "https://github.com/bitflags/bitflags";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "arbitrary" = [ "dep:arbitrary" ];
           "bytemuck" = [ "dep:bytemuck" ];
@@ -225,6 +282,14 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        meta = {
+          description = "Buffer type for block processing of data";
+          homepage = "https://github.com/RustCrypto/utils";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "generic-array";
@@ -237,10 +302,19 @@ rec {
         crateName = "bstr";
         version = "1.9.1";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "01ipr5rncw3kf4dyc1p2g00njn1df2b0xpviwhb8830iv77wbvq5";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A string type that is not required to be valid UTF-8.";
+          homepage = "https://github.com/BurntSushi/bstr";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "memchr";
@@ -267,6 +341,7 @@ rec {
         crateName = "camino";
         version = "1.1.7";
         edition = "2018";
+        rustVersion = "1.34.0";
         sha256 = "0ff28kc3qjcrmi8k88b2j2p7mzrvbag20yqcrj9sl30n3fanpv70";
         authors = [
           "Without Boats <saoirse@without.boats>"
@@ -274,6 +349,14 @@ rec {
           "Steve Klabnik <steve@steveklabnik.com>"
           "Rain <rain@sunshowers.io>"
         ];
+        meta = {
+          description = "UTF-8 paths";
+          homepage = "https://github.com/camino-rs/camino";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -294,8 +377,17 @@ rec {
         crateName = "cargo-platform";
         version = "0.1.8";
         edition = "2021";
+        rustVersion = "1.73.0";
         sha256 = "1z5b7ivbj508wkqdg2vb0hw4vi1k1pyhcn6h1h1b8svcb8vg1c94";
         libName = "cargo_platform";
+        meta = {
+          description = "Cargo's representation of a target platform.";
+          homepage = "https://github.com/rust-lang/cargo";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -308,10 +400,18 @@ rec {
         crateName = "cargo_metadata";
         version = "0.18.1";
         edition = "2018";
+        rustVersion = "1.56.0";
         sha256 = "0drh0zndl4qgndy6kg6783cydbvhxgv0hcg7d9hhqx0zwi3nb21d";
         authors = [
           "Oliver Schneider <git-spam-no-reply9815368754983@oli-obk.de>"
         ];
+        meta = {
+          description = "structured access to the output of `cargo metadata`";
+          homepage = "https://github.com/oli-obk/cargo_metadata";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "camino";
@@ -357,6 +457,16 @@ rec {
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        meta = {
+          description = "A macro to ergonomically define an item depending on a large number of #[cfg]
+parameters. Structured like an if-else chain, the first matching branch is the
+item that gets emitted.";
+          homepage = "https://github.com/alexcrichton/cfg-if";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "compiler_builtins" = [ "dep:compiler_builtins" ];
           "core" = [ "dep:core" ];
@@ -371,6 +481,13 @@ rec {
         authors = [
           "Kevin K. <kbknapp@gmail.com>"
         ];
+        meta = {
+          description = "A simple to use, efficient, and full-featured Command Line Argument Parser";
+          homepage = "https://clap.rs/";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "ansi_term";
@@ -429,6 +546,14 @@ rec {
         edition = "2015";
         sha256 = "1dfwjxd13f8l8bdzm76kkp6cp4sr1pyc8lavp52avwy313mhh0j1";
         libName = "colored_diff";
+        meta = {
+          description = "Format the difference between two strings with ANSI colors";
+          homepage = "https://github.com/CAD97/colored-diff";
+          license = [
+            lib.licenses.mit
+            lib.licenses.unlicense
+          ];
+        };
         dependencies = [
           {
             name = "ansi_term";
@@ -454,6 +579,15 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        meta = {
+          description = "Lightweight runtime CPU feature detection for aarch64, loongarch64, and x86/x86_64 targets,
+with no_std support and support for mobile targets including Android and iOS";
+          homepage = "https://github.com/RustCrypto/utils";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "libc";
@@ -493,6 +627,14 @@ rec {
         authors = [
           "Peter Kolloch <info@eigenvalue.net>"
         ];
+        meta = {
+          description = "crate2nix generates nix (as in NixOS) build files for rust using cargo.";
+          homepage = "https://nix-community.github.io/crate2nix/";
+          license = [
+            lib.licenses.asl20
+          ];
+          mainProgram = "crate2nix";
+        };
         dependencies = [
           {
             name = "anyhow";
@@ -541,6 +683,10 @@ rec {
             packageId = "serde_json";
             features = [ "unbounded_depth" ];
           }
+          {
+            name = "sha2";
+            packageId = "sha2";
+          }
           {
             name = "structopt";
             packageId = "structopt";
@@ -580,8 +726,17 @@ rec {
         crateName = "crossbeam-deque";
         version = "0.8.5";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "03bp38ljx4wj6vvy4fbhx41q8f585zyqix6pncz1mkz93z08qgv1";
         libName = "crossbeam_deque";
+        meta = {
+          description = "Concurrent work-stealing deque";
+          homepage = "https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-deque";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "crossbeam-epoch";
@@ -604,8 +759,17 @@ rec {
         crateName = "crossbeam-epoch";
         version = "0.9.18";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "03j2np8llwf376m3fxqx859mgp9f83hj1w34153c7a9c7i5ar0jv";
         libName = "crossbeam_epoch";
+        meta = {
+          description = "Epoch-based garbage collection";
+          homepage = "https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-epoch";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "crossbeam-utils";
@@ -626,8 +790,17 @@ rec {
         crateName = "crossbeam-utils";
         version = "0.8.20";
         edition = "2021";
+        rustVersion = "1.60.0";
         sha256 = "100fksq5mm1n7zj242cclkw6yf7a4a8ix3lvpfkhxvdhbda9kv12";
         libName = "crossbeam_utils";
+        meta = {
+          description = "Utilities for concurrent programming";
+          homepage = "https://github.com/crossbeam-rs/crossbeam/tree/master/crossbeam-utils";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" ];
           "loom" = [ "dep:loom" ];
@@ -643,6 +816,14 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        meta = {
+          description = "Common cryptographic traits";
+          homepage = "https://github.com/RustCrypto/traits";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "generic-array";
@@ -658,6 +839,7 @@ rec {
           "getrandom" = [ "rand_core/getrandom" ];
           "rand_core" = [ "dep:rand_core" ];
         };
+        resolvedDefaultFeatures = [ "std" ];
       };
       "digest" = rec {
         crateName = "digest";
@@ -667,6 +849,14 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        meta = {
+          description = "Traits for cryptographic hash functions and message authentication codes";
+          homepage = "https://github.com/RustCrypto/traits";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "block-buffer";
@@ -691,26 +881,43 @@ rec {
           "std" = [ "alloc" "crypto-common/std" ];
           "subtle" = [ "dep:subtle" ];
         };
-        resolvedDefaultFeatures = [ "block-buffer" "core-api" "default" ];
+        resolvedDefaultFeatures = [ "alloc" "block-buffer" "core-api" "default" "std" ];
       };
       "dissimilar" = rec {
         crateName = "dissimilar";
         version = "1.0.9";
         edition = "2018";
+        rustVersion = "1.36.0";
         sha256 = "0bcn4s99ghigd3yadpd7i3gljv5z2hkr07ijvvxvsxmz3yfygy2r";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Diff library with semantic cleanup, based on Google's diff-match-patch";
+          homepage = "https://github.com/dtolnay/dissimilar";
+          license = [
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "either" = rec {
         crateName = "either";
         version = "1.13.0";
         edition = "2018";
+        rustVersion = "1.37.0";
         sha256 = "1w2c1mybrd7vljyxk77y9f4w9dyjrmp3yp82mk7bcm8848fazcb0";
         authors = [
           "bluss"
         ];
+        meta = {
+          description = "The enum `Either` with variants `Left` and `Right` is a general purpose sum type with two cases.";
+          homepage = "https://github.com/rayon-rs/either";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "use_std" ];
           "serde" = [ "dep:serde" ];
@@ -721,17 +928,35 @@ rec {
         crateName = "equivalent";
         version = "1.0.1";
         edition = "2015";
+        rustVersion = "1.6.0";
         sha256 = "1malmx5f4lkfvqasz319lq6gb3ddg19yzf9s8cykfsgzdmyq0hsl";
+        meta = {
+          description = "Traits for key comparison in maps.";
+          homepage = "https://github.com/cuviper/equivalent";
+          license = [
+            lib.licenses.asl20
+            lib.licenses.mit
+          ];
+        };
 
       };
       "form_urlencoded" = rec {
         crateName = "form_urlencoded";
         version = "1.2.1";
         edition = "2018";
+        rustVersion = "1.51.0";
         sha256 = "0milh8x7nl4f450s3ddhg57a3flcv6yq8hlkyk6fyr3mcb128dp1";
         authors = [
           "The rust-url developers"
         ];
+        meta = {
+          description = "Parser and serializer for the application/x-www-form-urlencoded syntax, as used by HTML forms.";
+          homepage = "https://github.com/servo/rust-url";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "percent-encoding";
@@ -754,6 +979,13 @@ rec {
         authors = [
           "Denis Kurilenko <webdesus@gmail.com>"
         ];
+        meta = {
+          description = "Expanding std::fs and std::io. Recursively copy folders with information about process and much more.";
+          homepage = "https://github.com/webdesus/fs_extra";
+          license = [
+            lib.licenses.mit
+          ];
+        };
 
       };
       "fuchsia-cprng" = rec {
@@ -765,6 +997,10 @@ rec {
         authors = [
           "Erick Tryzelaar <etryzelaar@google.com>"
         ];
+        meta = {
+          description = "Rust crate for the Fuchsia cryptographically secure pseudorandom number generator";
+          homepage = "https://fuchsia.googlesource.com/fuchsia/+/master/garnet/public/rust/fuchsia-cprng";
+        };
 
       };
       "generic-array" = rec {
@@ -777,6 +1013,13 @@ rec {
           "Bartłomiej Kamiński <fizyk20@gmail.com>"
           "Aaron Trent <novacrazy@gmail.com>"
         ];
+        meta = {
+          description = "Generic types implementing functionality of arrays";
+          homepage = "https://github.com/fizyk20/generic-array.git";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "typenum";
@@ -803,6 +1046,16 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "Cross platform single glob and glob set matching. Glob set matching is the
+process of matching one or more glob patterns against a single candidate path
+simultaneously, and returning all of the globs that matched.";
+          homepage = "https://github.com/BurntSushi/ripgrep/tree/master/crates/globset";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "aho-corasick";
@@ -848,6 +1101,13 @@ rec {
         authors = [
           "Gilad Naaman <gilad@naaman.io>"
         ];
+        meta = {
+          description = "Glob-matched recursive file system walking.";
+          homepage = "https://github.com/gilnaa/globwalk";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "bitflags";
@@ -868,10 +1128,19 @@ rec {
         crateName = "hashbrown";
         version = "0.14.5";
         edition = "2021";
+        rustVersion = "1.63.0";
         sha256 = "1wa1vy1xs3mp11bn3z9dv0jricgr6a2j0zkf1g19yz3vw4il89z5";
         authors = [
           "Amanieu d'Antras <amanieu@gmail.com>"
         ];
+        meta = {
+          description = "A Rust port of Google's SwissTable hash map";
+          homepage = "https://github.com/rust-lang/hashbrown";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "ahash" = [ "dep:ahash" ];
           "alloc" = [ "dep:alloc" ];
@@ -896,6 +1165,14 @@ rec {
         authors = [
           "Without Boats <woboats@gmail.com>"
         ];
+        meta = {
+          description = "heck is a case conversion library.";
+          homepage = "https://github.com/withoutboats/heck";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unicode-segmentation";
@@ -913,6 +1190,15 @@ rec {
         authors = [
           "Stefan Lankes"
         ];
+        meta = {
+          description = "hermit-abi is small interface to call functions from the unikernel RustyHermit.
+It is used to build the target `x86_64-unknown-hermit`.";
+          homepage = "https://github.com/hermitcore/libhermit-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "libc";
@@ -935,6 +1221,14 @@ rec {
         authors = [
           "KokaKiwi <kokakiwi@kokakiwi.net>"
         ];
+        meta = {
+          description = "Encoding and decoding data into/from hexadecimal representation.";
+          homepage = "https://github.com/KokaKiwi/rust-hex";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" ];
           "serde" = [ "dep:serde" ];
@@ -946,10 +1240,19 @@ rec {
         crateName = "idna";
         version = "0.5.0";
         edition = "2018";
+        rustVersion = "1.51.0";
         sha256 = "1xhjrcjqq0l5bpzvdgylvpkgk94panxgsirzhjnnqfdgc4a9nkb3";
         authors = [
           "The rust-url developers"
         ];
+        meta = {
+          description = "IDNA (Internationalizing Domain Names in Applications) and Punycode.";
+          homepage = "https://github.com/servo/rust-url/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unicode-bidi";
@@ -977,6 +1280,15 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A fast library for efficiently matching ignore files such as `.gitignore`
+against file paths.";
+          homepage = "https://github.com/BurntSushi/ripgrep/tree/master/crates/ignore";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "crossbeam-deque";
@@ -1021,7 +1333,16 @@ rec {
         crateName = "indexmap";
         version = "2.2.6";
         edition = "2021";
+        rustVersion = "1.63.0";
         sha256 = "09hgwi2ig0wyj5rjziia76zmhgfj95k0jb4ic3iiawm4vlavg3qn";
+        meta = {
+          description = "A hash table with consistent order and fast iteration.";
+          homepage = "https://github.com/indexmap-rs/indexmap";
+          license = [
+            lib.licenses.asl20
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "equivalent";
@@ -1054,6 +1375,14 @@ rec {
         authors = [
           "bluss"
         ];
+        meta = {
+          description = "Extra iterator adaptors, iterator methods, free functions, and macros.";
+          homepage = "https://github.com/rust-itertools/itertools";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "either";
@@ -1070,10 +1399,19 @@ rec {
         crateName = "itertools";
         version = "0.12.1";
         edition = "2018";
+        rustVersion = "1.43.1";
         sha256 = "0s95jbb3ndj1lvfxyq5wanc0fm0r6hg6q4ngb92qlfdxvci10ads";
         authors = [
           "bluss"
         ];
+        meta = {
+          description = "Extra iterator adaptors, iterator methods, free functions, and macros.";
+          homepage = "https://github.com/rust-itertools/itertools";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "either";
@@ -1091,10 +1429,19 @@ rec {
         crateName = "itoa";
         version = "1.0.11";
         edition = "2018";
+        rustVersion = "1.36.0";
         sha256 = "0nv9cqjwzr3q58qz84dcz63ggc54yhf1yqar1m858m1kfd4g3wa9";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Fast integer primitive to string conversion";
+          homepage = "https://github.com/dtolnay/itoa";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "no-panic" = [ "dep:no-panic" ];
         };
@@ -1107,6 +1454,14 @@ rec {
         authors = [
           "Marvin Löbel <loebel.marvin@gmail.com>"
         ];
+        meta = {
+          description = "A macro for declaring lazily evaluated statics in Rust.";
+          homepage = "https://github.com/rust-lang-nursery/lazy-static.rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "spin" = [ "dep:spin" ];
           "spin_no_std" = [ "spin" ];
@@ -1120,6 +1475,14 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "Raw FFI bindings to platform libraries like libc.";
+          homepage = "https://github.com/rust-lang/libc";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" ];
           "rustc-dep-of-std" = [ "align" "rustc-std-workspace-core" ];
@@ -1132,10 +1495,19 @@ rec {
         crateName = "log";
         version = "0.4.22";
         edition = "2021";
+        rustVersion = "1.60.0";
         sha256 = "093vs0wkm1rgyykk7fjbqp2lwizbixac1w52gv109p5r4jh0p9x7";
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "A lightweight logging facade for Rust";
+          homepage = "https://github.com/rust-lang/log";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "kv_serde" = [ "kv_std" "value-bag/serde" "serde" ];
           "kv_std" = [ "std" "kv" "value-bag/error" ];
@@ -1154,11 +1526,21 @@ rec {
         crateName = "memchr";
         version = "2.7.4";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "18z32bhxrax0fnjikv475z7ii718hq457qwmaryixfxsl2qrmjkq";
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
           "bluss"
         ];
+        meta = {
+          description = "Provides extremely fast (uses SIMD on x86_64, aarch64 and wasm32) routines for
+1, 2 or 3 byte search and single substring search.";
+          homepage = "https://github.com/BurntSushi/memchr";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         features = {
           "compiler_builtins" = [ "dep:compiler_builtins" ];
           "core" = [ "dep:core" ];
@@ -1179,16 +1561,32 @@ rec {
         authors = [
           "Peter Kolloch <info@eigenvalue.net>"
         ];
+        meta = {
+          description = "Provides a nix (as in NixOS) compatible base32 encoding.";
+          homepage = "https://github.com/kolloch/nix-base32";
+          license = [
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "once_cell" = rec {
         crateName = "once_cell";
         version = "1.19.0";
         edition = "2021";
+        rustVersion = "1.60.0";
         sha256 = "14kvw7px5z96dk4dwdm1r9cqhhy2cyj1l5n5b29mynbb8yr15nrz";
         authors = [
           "Aleksey Kladov <aleksey.kladov@gmail.com>"
         ];
+        meta = {
+          description = "Single assignment cells and lazy values.";
+          homepage = "https://github.com/matklad/once_cell";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "alloc" = [ "race" ];
           "atomic-polyfill" = [ "critical-section" ];
@@ -1208,6 +1606,14 @@ rec {
         authors = [
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        meta = {
+          description = "Library for diffing paths to obtain relative paths";
+          homepage = "https://github.com/Manishearth/pathdiff";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "camino" = [ "dep:camino" ];
         };
@@ -1216,11 +1622,20 @@ rec {
         crateName = "percent-encoding";
         version = "2.3.1";
         edition = "2018";
+        rustVersion = "1.51.0";
         sha256 = "0gi8wgx0dcy8rnv1kywdv98lwcx67hz0a0zwpib5v2i08r88y573";
         libName = "percent_encoding";
         authors = [
           "The rust-url developers"
         ];
+        meta = {
+          description = "Percent encoding and decoding";
+          homepage = "https://github.com/servo/rust-url/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" ];
           "std" = [ "alloc" ];
@@ -1231,10 +1646,19 @@ rec {
         crateName = "pest";
         version = "2.7.10";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "1s4fvis7h6l872g6nk17r130kcllj4c0hjvwkzd3hi196g3320an";
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        meta = {
+          description = "The Elegant Parser";
+          homepage = "https://pest.rs/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "memchr";
@@ -1264,11 +1688,20 @@ rec {
         crateName = "pest_derive";
         version = "2.7.10";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "0n8lsk9s21dp7958p9yarbk2gsc8wg0rvdzr7cd7pjpvjf8kqa96";
         procMacro = true;
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        meta = {
+          description = "pest's derive macro";
+          homepage = "https://pest.rs/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "pest";
@@ -1293,10 +1726,19 @@ rec {
         crateName = "pest_generator";
         version = "2.7.10";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "11s6q0vf25lckbzak0qndzpv87ksaxy6pa9cvn2hlizvsgvjmhiy";
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        meta = {
+          description = "pest code generator";
+          homepage = "https://pest.rs/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "pest";
@@ -1332,10 +1774,19 @@ rec {
         crateName = "pest_meta";
         version = "2.7.10";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "1kdxl164yyjsmn01lvllsll4sz3xbgy4dmkq33n63hrp5w1418np";
         authors = [
           "Dragoș Tiselice <dragostiselice@gmail.com>"
         ];
+        meta = {
+          description = "pest meta language parser and validator";
+          homepage = "https://pest.rs/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "once_cell";
@@ -1367,6 +1818,14 @@ rec {
         authors = [
           "CreepySkeleton <creepy-skeleton@yandex.ru>"
         ];
+        meta = {
+          description = "Almost drop-in replacement to panics in proc-macros";
+          homepage = "https://gitlab.com/CreepySkeleton/proc-macro-error";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro-error-attr";
@@ -1410,6 +1869,14 @@ rec {
         authors = [
           "CreepySkeleton <creepy-skeleton@yandex.ru>"
         ];
+        meta = {
+          description = "Attribute macro for proc-macro-error crate";
+          homepage = "https://gitlab.com/CreepySkeleton/proc-macro-error";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -1432,12 +1899,21 @@ rec {
         crateName = "proc-macro2";
         version = "1.0.86";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "0xrv22p8lqlfdf1w0pj4si8n2ws4aw0kilmziwf0vpv5ys6rwway";
         libName = "proc_macro2";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        meta = {
+          description = "A substitute implementation of the compiler's `proc_macro` API to decouple token-based libraries from the procedural macro use case.";
+          homepage = "https://github.com/dtolnay/proc-macro2";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unicode-ident";
@@ -1453,10 +1929,19 @@ rec {
         crateName = "quote";
         version = "1.0.36";
         edition = "2018";
+        rustVersion = "1.56.0";
         sha256 = "19xcmh445bg6simirnnd4fvkmp6v2qiwxh5f6rw4a70h76pnm9qg";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Quasi-quoting macro quote!(...)";
+          homepage = "https://github.com/dtolnay/quote";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -1478,6 +1963,14 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "Random number generators and other randomness functionality.";
+          homepage = "https://github.com/rust-lang-nursery/rand";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "fuchsia-cprng";
@@ -1525,6 +2018,14 @@ rec {
           "The Rand Project Developers"
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "Core random number generator traits and tools for implementation.";
+          homepage = "https://crates.io/crates/rand_core";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "rand_core";
@@ -1547,6 +2048,14 @@ rec {
           "The Rand Project Developers"
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "Core random number generator traits and tools for implementation.";
+          homepage = "https://crates.io/crates/rand_core";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "serde" = [ "dep:serde" ];
           "serde1" = [ "serde" "serde_derive" ];
@@ -1562,6 +2071,13 @@ rec {
         authors = [
           "Simonas Kazlauskas <rdrand@kazlauskas.me>"
         ];
+        meta = {
+          description = "An implementation of random number generator based on rdrand and rdseed instructions";
+          homepage = "https://github.com/nagisa/rust_rdrand/";
+          license = [
+            lib.licenses.isc
+          ];
+        };
         dependencies = [
           {
             name = "rand_core";
@@ -1578,11 +2094,21 @@ rec {
         crateName = "regex";
         version = "1.10.5";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "0zsiqk2sxc1kd46qw0yp87s2a14ialwyxinpl0k266ddkm1i64mr";
         authors = [
           "The Rust Project Developers"
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "An implementation of regular expressions for Rust. This implementation uses
+finite automata and guarantees linear time matching on all inputs.";
+          homepage = "https://github.com/rust-lang/regex";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "aho-corasick";
@@ -1636,12 +2162,21 @@ rec {
         crateName = "regex-automata";
         version = "0.4.7";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "1pwjdi4jckpbaivpl6x4v5g4crb37zr2wac93wlfsbzgqn6gbjiq";
         libName = "regex_automata";
         authors = [
           "The Rust Project Developers"
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "Automata construction and matching using regular expressions.";
+          homepage = "https://github.com/rust-lang/regex/tree/master/regex-automata";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "aho-corasick";
@@ -1697,12 +2232,21 @@ rec {
         crateName = "regex-syntax";
         version = "0.8.4";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "16r0kjy20vx33dr4mhasj5l1f87czas714x2fz6zl0f8wwxa0rks";
         libName = "regex_syntax";
         authors = [
           "The Rust Project Developers"
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A regular expression parser.";
+          homepage = "https://github.com/rust-lang/regex/tree/master/regex-syntax";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "arbitrary" = [ "dep:arbitrary" ];
           "default" = [ "std" "unicode" ];
@@ -1718,6 +2262,14 @@ rec {
         authors = [
           "Aaronepower <theaaronepower@gmail.com>"
         ];
+        meta = {
+          description = "A safe, reliable implementation of remove_dir_all for Windows";
+          homepage = "https://github.com/XAMPPRocky/remove_dir_all.git";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "winapi";
@@ -1732,10 +2284,19 @@ rec {
         crateName = "ryu";
         version = "1.0.18";
         edition = "2018";
+        rustVersion = "1.36.0";
         sha256 = "17xx2s8j1lln7iackzd9p0sv546vjq71i779gphjq923vjh5pjzk";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Fast floating point to string conversion";
+          homepage = "https://github.com/dtolnay/ryu";
+          license = [
+            lib.licenses.asl20
+            lib.licenses.boost
+          ];
+        };
         features = {
           "no-panic" = [ "dep:no-panic" ];
         };
@@ -1749,6 +2310,14 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A simple crate for determining whether two file paths point to the same file.";
+          homepage = "https://github.com/BurntSushi/same-file";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "winapi-util";
@@ -1762,10 +2331,19 @@ rec {
         crateName = "semver";
         version = "1.0.23";
         edition = "2018";
+        rustVersion = "1.31.0";
         sha256 = "12wqpxfflclbq4dv8sa6gchdh92ahhwn4ci1ls22wlby3h57wsb1";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Parser and evaluator for Cargo's flavor of Semantic Versioning";
+          homepage = "https://github.com/dtolnay/semver";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -1784,11 +2362,20 @@ rec {
         crateName = "serde";
         version = "1.0.203";
         edition = "2018";
+        rustVersion = "1.31.0";
         sha256 = "1500ghq198n6py5anvz5qbqagd9h1hq04f4qpsvjzrvix56snlvj";
         authors = [
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "A generic serialization/deserialization framework";
+          homepage = "https://serde.rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde_derive";
@@ -1818,12 +2405,21 @@ rec {
         crateName = "serde_derive";
         version = "1.0.203";
         edition = "2015";
+        rustVersion = "1.56.0";
         sha256 = "1fmmqmfza3mwxb1v80737dj01gznrh8mhgqgylkndx5npq7bq32h";
         procMacro = true;
         authors = [
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Macros 1.1 implementation of #[derive(Serialize, Deserialize)]";
+          homepage = "https://serde.rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -1852,11 +2448,20 @@ rec {
         crateName = "serde_json";
         version = "1.0.118";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1r7jpqdfnrv8skn5va1r202g6lhdhka0vyn42vm5g21x2srzciyr";
         authors = [
           "Erick Tryzelaar <erick.tryzelaar@gmail.com>"
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "A JSON serialization file format";
+          homepage = "https://github.com/serde-rs/json";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "itoa";
@@ -1892,7 +2497,16 @@ rec {
         crateName = "serde_spanned";
         version = "0.6.6";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "1839b6m5p9ijjmcwamiya2r612ks2vg6w2pp95yg76lr3zh79rkr";
+        meta = {
+          description = "Serde-compatible spanned Value";
+          homepage = "https://github.com/toml-rs/toml";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -1919,6 +2533,15 @@ rec {
         authors = [
           "RustCrypto Developers"
         ];
+        meta = {
+          description = "Pure Rust implementation of the SHA-2 hash function family
+including SHA-224, SHA-256, SHA-384, and SHA-512.";
+          homepage = "https://github.com/RustCrypto/hashes";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "cfg-if";
@@ -1949,6 +2572,7 @@ rec {
           "sha2-asm" = [ "dep:sha2-asm" ];
           "std" = [ "digest/std" ];
         };
+        resolvedDefaultFeatures = [ "default" "std" ];
       };
       "strsim" = rec {
         crateName = "strsim";
@@ -1958,6 +2582,14 @@ rec {
         authors = [
           "Danny Guo <dannyguo91@gmail.com>"
         ];
+        meta = {
+          description = "Implementations of string similarity metrics.
+Includes Hamming, Levenshtein, OSA, Damerau-Levenshtein, Jaro, and Jaro-Winkler.";
+          homepage = "https://github.com/dguo/strsim-rs";
+          license = [
+            lib.licenses.mit
+          ];
+        };
 
       };
       "structopt" = rec {
@@ -1969,6 +2601,14 @@ rec {
           "Guillaume Pinot <texitoi@texitoi.eu>"
           "others"
         ];
+        meta = {
+          description = "Parse command line argument by defining a struct.";
+          homepage = "https://github.com/TeXitoi/structopt";
+          license = [
+            lib.licenses.asl20
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "clap";
@@ -2009,6 +2649,14 @@ rec {
         authors = [
           "Guillaume Pinot <texitoi@texitoi.eu>"
         ];
+        meta = {
+          description = "Parse command line argument by defining a struct, derive crate.";
+          homepage = "https://github.com/TeXitoi/structopt";
+          license = [
+            lib.licenses.asl20
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "heck";
@@ -2039,10 +2687,19 @@ rec {
         crateName = "syn";
         version = "1.0.109";
         edition = "2018";
+        rustVersion = "1.31.0";
         sha256 = "0ds2if4600bd59wsv7jjgfkayfzy3hnazs394kz6zdkmna8l3dkj";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Parser for Rust source code";
+          homepage = "https://github.com/dtolnay/syn";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -2073,10 +2730,19 @@ rec {
         crateName = "syn";
         version = "2.0.68";
         edition = "2021";
+        rustVersion = "1.61.0";
         sha256 = "1sf1y2hajhjav38ipg63c934xrgkz4v42fz24a0ckmmri06sf7wh";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Parser for Rust source code";
+          homepage = "https://github.com/dtolnay/syn";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -2110,6 +2776,15 @@ rec {
         authors = [
           "The Rust Project Developers"
         ];
+        meta = {
+          description = "A library for managing a temporary directory and deleting all contents when it's
+dropped.";
+          homepage = "https://github.com/rust-lang/tempdir";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "rand";
@@ -2126,10 +2801,18 @@ rec {
         crateName = "tera";
         version = "1.20.0";
         edition = "2018";
+        rustVersion = "1.70.0";
         sha256 = "1vnj9imw2h9szkd1izsrhwrc9jvazvdsp84x65wg2rg88ldqb7db";
         authors = [
           "Vincent Prouillet <hello@prouilletvincent.com>"
         ];
+        meta = {
+          description = "Template engine based on Jinja2/Django templates";
+          homepage = "https://keats.github.io/tera/";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "globwalk";
@@ -2186,6 +2869,18 @@ rec {
         authors = [
           "Martin Geisler <martin@geisler.net>"
         ];
+        meta = {
+          description = "Textwrap is a small library for word wrapping, indenting, and
+dedenting strings.
+
+You can use it to format strings (such as help and error messages) for
+display in commandline applications. It is designed to be efficient
+and handle Unicode characters correctly.";
+          homepage = "https://github.com/mgeisler/textwrap";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "unicode-width";
@@ -2201,10 +2896,19 @@ rec {
         crateName = "thiserror";
         version = "1.0.61";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "028prh962l16cmjivwb1g9xalbpqip0305zhq006mg74dc6whin5";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "derive(Error)";
+          homepage = "https://github.com/dtolnay/thiserror";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "thiserror-impl";
@@ -2217,12 +2921,21 @@ rec {
         crateName = "thiserror-impl";
         version = "1.0.61";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "0cvm37hp0kbcyk1xac1z0chpbd9pbn2g456iyid6sah0a113ihs6";
         procMacro = true;
         libName = "thiserror_impl";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Implementation detail of the `thiserror` crate";
+          homepage = "https://github.com/dtolnay/thiserror";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "proc-macro2";
@@ -2247,6 +2960,15 @@ rec {
         authors = [
           "Lokathor <zefria@gmail.com>"
         ];
+        meta = {
+          description = "`tinyvec` provides 100% safe vec-like data structures.";
+          homepage = "https://github.com/Lokathor/tinyvec";
+          license = [
+            lib.licenses.zlib
+            lib.licenses.asl20
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "tinyvec_macros";
@@ -2273,16 +2995,36 @@ rec {
         authors = [
           "Soveu <marx.tomasz@gmail.com>"
         ];
+        meta = {
+          description = "Some macros for tiny containers";
+          homepage = "https://github.com/Soveu/tinyvec_macros";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+            lib.licenses.zlib
+          ];
+        };
 
       };
       "toml" = rec {
         crateName = "toml";
         version = "0.8.14";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "0dgk8bacrza09npifba1xsx7wyjjvhz3igxpdnyjcbqxn8mfnjbg";
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        meta = {
+          description = "A native Rust encoder and decoder of TOML-formatted files and streams. Provides
+implementations of the standard Serialize/Deserialize traits for TOML data to
+facilitate deserializing and serializing Rust structures.";
+          homepage = "https://github.com/toml-rs/toml";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -2326,10 +3068,19 @@ rec {
         crateName = "toml_datetime";
         version = "0.6.6";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "1grcrr3gh7id3cy3j700kczwwfbn04p5ncrrj369prjaj9bgvbab";
         authors = [
           "Alex Crichton <alex@alexcrichton.com>"
         ];
+        meta = {
+          description = "A TOML-compatible datetime type";
+          homepage = "https://github.com/toml-rs/toml";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "serde";
@@ -2346,11 +3097,20 @@ rec {
         crateName = "toml_edit";
         version = "0.22.14";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "0f2fw0viqvisjhqwjavgypz5mgbldh53przrsjlrrggijyppl77j";
         authors = [
           "Andronik Ordian <write@reusable.software>"
           "Ed Page <eopage@gmail.com>"
         ];
+        meta = {
+          description = "Yet another format-preserving TOML parser.";
+          homepage = "https://github.com/toml-rs/toml";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "indexmap";
@@ -2390,12 +3150,24 @@ rec {
         crateName = "typenum";
         version = "1.17.0";
         edition = "2018";
+        rustVersion = "1.37.0";
         sha256 = "09dqxv69m9lj9zvv6xw5vxaqx15ps0vxyy5myg33i0kbqvq0pzs2";
         build = "build/main.rs";
         authors = [
           "Paho Lurie-Gregg <paho@paholg.com>"
           "Andre Bogus <bogusandre@gmail.com>"
         ];
+        meta = {
+          description = "Typenum is a Rust library for type-level numbers evaluated at
+    compile time. It currently supports bits, unsigned integers, and signed
+    integers. It also provides a type-level array of type-level numbers, but its
+    implementation is incomplete.";
+          homepage = "https://github.com/paholg/typenum";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "scale-info" = [ "dep:scale-info" ];
           "scale_info" = [ "scale-info/derive" ];
@@ -2410,6 +3182,14 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A trie for storing Unicode codepoint sets and maps.";
+          homepage = "https://github.com/BurntSushi/ucd-generate";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" ];
         };
@@ -2424,6 +3204,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Unicode Character Tools — Character Property taxonomy, contracts and build macros";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unic-char-range";
@@ -2441,6 +3229,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Unicode Character Tools — Character Range and Iteration";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "rayon" = [ "dep:rayon" ];
           "unstable" = [ "exact-size-is-empty" "fused" "trusted-len" ];
@@ -2456,6 +3252,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Common Utilities";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
         };
         resolvedDefaultFeatures = [ "default" ];
@@ -2469,6 +3273,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Unicode Text Segmentation Algorithms";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unic-ucd-segment";
@@ -2486,6 +3298,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Unicode Character Database — Segmentation Properties";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unic-char-property";
@@ -2511,6 +3331,14 @@ rec {
         authors = [
           "The UNIC Project Developers"
         ];
+        meta = {
+          description = "UNIC — Unicode Character Database — Version";
+          homepage = "https://github.com/open-i18n/rust-unic/";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "unic-common";
@@ -2528,6 +3356,14 @@ rec {
         authors = [
           "The Servo Project Developers"
         ];
+        meta = {
+          description = "Implementation of the Unicode Bidirectional Algorithm";
+          homepage = "https://github.com/servo/unicode-bidi";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "default" = [ "std" "hardcoded-data" ];
           "flame" = [ "dep:flame" ];
@@ -2542,11 +3378,21 @@ rec {
         crateName = "unicode-ident";
         version = "1.0.12";
         edition = "2018";
+        rustVersion = "1.31.0";
         sha256 = "0jzf1znfpb2gx8nr8mvmyqs1crnv79l57nxnbiszc7xf7ynbjm1k";
         libName = "unicode_ident";
         authors = [
           "David Tolnay <dtolnay@gmail.com>"
         ];
+        meta = {
+          description = "Determine whether characters have the XID_Start or XID_Continue properties according to Unicode Standard Annex #31";
+          homepage = "https://github.com/dtolnay/unicode-ident";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+            lib.licenses.unicode-dfs-2016
+          ];
+        };
 
       };
       "unicode-normalization" = rec {
@@ -2559,6 +3405,17 @@ rec {
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        meta = {
+          description = "This crate provides functions for normalization of
+Unicode strings, including Canonical and Compatible
+Decomposition and Recomposition, as described in
+Unicode Standard Annex #15.";
+          homepage = "https://github.com/unicode-rs/unicode-normalization";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "tinyvec";
@@ -2581,6 +3438,15 @@ rec {
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        meta = {
+          description = "This crate provides Grapheme Cluster, Word and Sentence boundaries
+according to Unicode Standard Annex #29 rules.";
+          homepage = "https://github.com/unicode-rs/unicode-segmentation";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
         };
       };
@@ -2594,6 +3460,15 @@ rec {
           "kwantam <kwantam@gmail.com>"
           "Manish Goregaokar <manishsmail@gmail.com>"
         ];
+        meta = {
+          description = "Determine displayed width of `char` and `str` types
+according to Unicode Standard Annex #11 rules.";
+          homepage = "https://github.com/unicode-rs/unicode-width";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "compiler_builtins" = [ "dep:compiler_builtins" ];
           "core" = [ "dep:core" ];
@@ -2606,10 +3481,19 @@ rec {
         crateName = "url";
         version = "2.5.2";
         edition = "2018";
+        rustVersion = "1.56.0";
         sha256 = "0v2dx50mx7xzl9454cl5qmpjnhkbahmn59gd3apyipbgyyylsy12";
         authors = [
           "The rust-url developers"
         ];
+        meta = {
+          description = "URL library for Rust, based on the WHATWG URL Standard";
+          homepage = "https://github.com/servo/rust-url";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "form_urlencoded";
@@ -2675,6 +3559,14 @@ rec {
           "Sean McArthur <sean.monstar@gmail.com>"
           "Vadim Petrochenkov <>"
         ];
+        meta = {
+          description = "A simple map based on a vector for small integer keys";
+          homepage = "https://github.com/contain-rs/vec-map";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         features = {
           "eders" = [ "serde" ];
           "serde" = [ "dep:serde" ];
@@ -2688,6 +3580,14 @@ rec {
         authors = [
           "Sergio Benitez <sb@sergio.bz>"
         ];
+        meta = {
+          description = "Tiny crate to check the version of the installed/running rustc.";
+          homepage = "https://github.com/SergioBenitez/version_check";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "walkdir" = rec {
@@ -2698,6 +3598,14 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "Recursively walk a directory.";
+          homepage = "https://github.com/BurntSushi/walkdir";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "same-file";
@@ -2719,6 +3627,14 @@ rec {
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        meta = {
+          description = "Raw FFI bindings for all of Windows API.";
+          homepage = "https://github.com/retep998/winapi-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "winapi-i686-pc-windows-gnu";
@@ -2745,6 +3661,14 @@ rec {
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        meta = {
+          description = "Import libraries for the i686-pc-windows-gnu target. Please don't use this crate directly, depend on winapi instead.";
+          homepage = "https://github.com/retep998/winapi-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "winapi-util" = rec {
@@ -2756,6 +3680,14 @@ rec {
         authors = [
           "Andrew Gallant <jamslam@gmail.com>"
         ];
+        meta = {
+          description = "A dumping ground for high level safe wrappers over windows-sys.";
+          homepage = "https://github.com/BurntSushi/winapi-util";
+          license = [
+            lib.licenses.unlicense
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "windows-sys";
@@ -2775,17 +3707,34 @@ rec {
         authors = [
           "Peter Atashian <retep998@gmail.com>"
         ];
+        meta = {
+          description = "Import libraries for the x86_64-pc-windows-gnu target. Please don't use this crate directly, depend on winapi instead.";
+          homepage = "https://github.com/retep998/winapi-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows-sys" = rec {
         crateName = "windows-sys";
         version = "0.52.0";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "0gd3v4ji88490zgb6b5mq5zgbvwv7zx1ibn8v3x83rwcdbryaar8";
         libName = "windows_sys";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Rust for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "windows-targets";
@@ -3029,11 +3978,20 @@ rec {
         crateName = "windows-targets";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1sz7jrnkygmmlj1ia8fk85wbyil450kq5qkh5qh9sh2rcnj161vg";
         libName = "windows_targets";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import libs for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
         dependencies = [
           {
             name = "windows_aarch64_gnullvm";
@@ -3082,87 +4040,167 @@ rec {
         crateName = "windows_aarch64_gnullvm";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "0qrjimbj67nnyn7zqy15mzzmqg0mn5gsr2yciqjxm3cb3vbyx23h";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_aarch64_msvc" = rec {
         crateName = "windows_aarch64_msvc";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1dmga8kqlmln2ibckk6mxc9n59vdg8ziqa2zr8awcl720hazv1cr";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_i686_gnu" = rec {
         crateName = "windows_i686_gnu";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "0w4np3l6qwlra9s2xpflqrs60qk1pz6ahhn91rr74lvdy4y0gfl8";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_i686_gnullvm" = rec {
         crateName = "windows_i686_gnullvm";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1s9f4gff0cixd86mw3n63rpmsm4pmr4ffndl6s7qa2h35492dx47";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_i686_msvc" = rec {
         crateName = "windows_i686_msvc";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1gw7fklxywgpnwbwg43alb4hm0qjmx72hqrlwy5nanrxs7rjng6v";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_x86_64_gnu" = rec {
         crateName = "windows_x86_64_gnu";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1n8p2mcf3lw6300k77a0knksssmgwb9hynl793mhkzyydgvlchjf";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_x86_64_gnullvm" = rec {
         crateName = "windows_x86_64_gnullvm";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "15n56jrh4s5bz66zimavr1rmcaw6wa306myrvmbc6rydhbj9h8l5";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "windows_x86_64_msvc" = rec {
         crateName = "windows_x86_64_msvc";
         version = "0.52.5";
         edition = "2021";
+        rustVersion = "1.56.0";
         sha256 = "1w1bn24ap8dp9i85s8mlg8cim2bl2368bd6qyvm0xzqvzmdpxi5y";
         authors = [
           "Microsoft"
         ];
+        meta = {
+          description = "Import lib for Windows";
+          homepage = "https://github.com/microsoft/windows-rs";
+          license = [
+            lib.licenses.mit
+            lib.licenses.asl20
+          ];
+        };
 
       };
       "winnow" = rec {
         crateName = "winnow";
         version = "0.6.13";
         edition = "2021";
+        rustVersion = "1.65.0";
         sha256 = "189b0mrr9lkckdyr0177hwj1c59igxc2lsl71f4wg8wrqbvfbdar";
+        meta = {
+          description = "A byte-oriented, zero-copy, parser combinators library";
+          homepage = "https://github.com/winnow-rs/winnow";
+          license = [
+            lib.licenses.mit
+          ];
+        };
         dependencies = [
           {
             name = "memchr";
@@ -3182,10 +4220,71 @@ rec {
       };
     };
 
+    # The rustc flags for the `[profile.*]` settings of the workspace Cargo.toml.
+    # `crates` contains the flags of crates with package overrides.
+    cargoProfiles = {
+      "dev" = {
+        release = false;
+        rustcFlags = [ "-C opt-level=0" "-C debuginfo=2" ];
+      };
+      "release" = {
+        release = true;
+        rustcFlags = [ "-C opt-level=3" "-C debuginfo=0" ];
+      };
+    };
+
     #
 # crate2nix/default.nix (excerpt start)
 #
 
+  /*
+    The selected cargo profile from `cargoProfiles`: whether it is based on
+    `release` and the rustc flags for its settings.
+  */
+  cargoProfile =
+    if cargoProfiles == { } then
+      { }
+    else
+      cargoProfiles.${profile} or (throw ''
+        Unknown cargo profile '${profile}', available: ${lib.concatStringsSep ", " (builtins.attrNames cargoProfiles)}
+      '');
+
+  /*
+    The version of the rustc that `buildRustCrateForPkgs` uses for `pkgs`, checked
+    against the `rust-version` of the crates: the given `rustcVersion`, the version
+    of `pkgs.rustc` for the default `buildRustCrateForPkgs` and null, i.e. no
+    check, for a custom one.
+  */
+  rustcVersionFor =
+    pkgs:
+    if callArgs ? rustcVersion then
+      rustcVersion
+    else if callArgs ? buildRustCrateForPkgs || (callArgs.buildRustCrate or null) != null then
+      null
+    else
+      pkgs.rustc.version or null;
+
+  /*
+    A `preBuild` hook for crates with `lintFlags`.
+
+    `buildRustCrate` calls rustc with `--cap-lints allow`, which would turn the
+    lint flags into no-ops, and rustc ignores a second `--cap-lints`.
+  */
+  uncapLints = ''
+    rustc() {
+      local args=()
+      while [ $# -gt 0 ]; do
+        if [ "$1" = --cap-lints ]; then
+          shift 2
+          continue
+        fi
+        args+=("$1")
+        shift
+      done
+      command rustc "''${args[@]}"
+    }
+  '';
+
   /*
     Target (platform) data for conditional dependencies.
     This corresponds roughly to what buildRustCrate is setting.
@@ -3215,11 +4314,24 @@ rec {
     , url
     , crate
     , version
-    , sha256
+    , sha256 ? null
+    , # The SRI hash, instead of sha256 for `--hash-format sri`.
+      hash ? null
     ,
     }:
     let
       dl = registries.${url}.dl;
+      # Cargo fills in the hex checksum, older nix versions cannot convert it.
+      checksum =
+        if builtins ? convertHash then
+          builtins.convertHash {
+            hash = if hash != null then hash else "sha256:${sha256}";
+            toHashFormat = "base16";
+          }
+        else if hash != null then
+          hash
+        else
+          sha256;
       tmpl = [
         "{crate}"
         "{version}"
@@ -3244,7 +4356,7 @@ rec {
         version
         prefix
         (lib.strings.toLower prefix)
-        sha256
+        checksum
       ]
     else
       "${dl}/${crate}/${version}/download";
@@ -3425,14 +4537,18 @@ rec {
                     defaultCrateOverrides = crateOverrides;
                   }
               );
+          # The rustc of a custom `buildRustCrateForPkgsFunc` is unknown.
+          rustcVersionForPkgs = if buildRustCrateForPkgsFunc != null then _: null else rustcVersionFor;
           builtRustCrates = builtRustCratesWithFeatures {
             inherit packageId features;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
+            inherit rustcVersionForPkgs;
             runTests = false;
           };
           builtTestRustCrates = builtRustCratesWithFeatures {
             inherit packageId features;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
+            inherit rustcVersionForPkgs;
             runTests = true;
           };
           drv = builtRustCrates.crates.${packageId};
@@ -3476,6 +4592,8 @@ rec {
     , features
     , crateConfigs ? crates
     , buildRustCrateForPkgsFunc
+    , # The rustc version of buildRustCrateForPkgsFunc for pkgs, null to skip the check.
+      rustcVersionForPkgs ? rustcVersionFor
     , runTests
     , makeTarget ? makeDefaultTarget
     ,
@@ -3520,6 +4638,8 @@ rec {
             crateConfig = builtins.removeAttrs crateConfig' [
               "resolvedDefaultFeatures"
               "devDependencies"
+              "lintFlags"
+              "rustVersion"
             ];
             devDependencies = lib.optionals (runTests && packageId == rootPackageId) (
               crateConfig'.devDependencies or [ ]
@@ -3564,6 +4684,7 @@ rec {
             #    ];
             #    # ...
             # }
+            rustcVersion = rustcVersionForPkgs pkgs;
             crateRenames =
               let
                 grouped = lib.groupBy (dependency: dependency.name) dependenciesWithRenames;
@@ -3579,30 +4700,49 @@ rec {
               in
               lib.mapAttrs (name: builtins.map versionAndRename) grouped;
           in
+          assert lib.assertMsg
+            (
+              rustcVersion == null
+              || !(crateConfig' ? rustVersion)
+              || lib.versionAtLeast rustcVersion crateConfig'.rustVersion
+            )
+            "${crateConfig.crateName}-${crateConfig.version} requires rustc ${crateConfig'.rustVersion or ""} (rust-version) but rustc ${toString rustcVersion} is used. Use a newer toolchain or set `rustcVersion` if it is not detected correctly.";
           buildRustCrateForPkgsFunc pkgs (
             crateConfig
             // {
               src =
-                crateConfig.src or (fetchurl rec {
+                crateConfig.src or (fetchurl ({
                   name = "${crateConfig.crateName}-${crateConfig.version}.tar.gz";
                   # https://www.pietroalbini.org/blog/downloading-crates-io/
                   # Not rate-limited, CDN URL.
                   url = "https://static.crates.io/crates/${crateConfig.crateName}/${crateConfig.crateName}-${crateConfig.version}.crate";
-                  sha256 =
-                    assert (lib.assertMsg (crateConfig ? sha256) "Missing sha256 for ${name}");
-                    crateConfig.sha256;
-                });
+                } // (
+                  # SRI hashes are rendered as `hash`, see `--hash-format`.
+                  if crateConfig ? hash then
+                    { inherit (crateConfig) hash; }
+                  else
+                    {
+                      sha256 =
+                        assert (lib.assertMsg (crateConfig ? sha256) "Missing sha256 for ${crateConfig.crateName}-${crateConfig.version}");
+                        crateConfig.sha256;
+                    }
+                )));
               extraRustcOpts =
-                lib.lists.optional (targetFeatures != [ ])
+                (cargoProfile.crates.${packageId} or cargoProfile.rustcFlags or [ ])
+                ++ (crateConfig'.lintFlags or [ ])
+                ++ lib.lists.optional (targetFeatures != [ ])
                   "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
+              release = cargoProfile.release or release;
               inherit
                 features
                 dependencies
                 buildDependencies
                 crateRenames
-                release
                 ;
             }
+            // lib.optionalAttrs (crateConfig' ? lintFlags) {
+              preBuild = uncapLints + (crateConfig.preBuild or "");
+            }
           );
       in
       builtByPackageIdByPkgs;
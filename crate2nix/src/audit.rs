@@ -0,0 +1,387 @@
+//! Matching the resolved crates against a local checkout of the RustSec
+//! advisory database, see <https://github.com/rustsec/advisory-db>.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use anyhow::{bail, format_err, Context, Error};
+use itertools::Itertools;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::json_output::to_resolved_workspace;
+use crate::resolve::ResolvedSource;
+use crate::BuildInfo;
+
+/// A single advisory for a crates.io crate.
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    /// The advisory ID, e.g. "RUSTSEC-2021-0001".
+    pub id: String,
+    /// The name of the affected crate.
+    pub package: String,
+    /// The title of the advisory.
+    pub title: String,
+    /// Set for informational advisories, e.g. to "unmaintained" or "unsound".
+    pub informational: Option<String>,
+    /// Versions that contain the fix.
+    pub patched: Vec<VersionReq>,
+    /// Versions that were never affected.
+    pub unaffected: Vec<VersionReq>,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    #[serde(default)]
+    informational: Option<String>,
+    #[serde(default)]
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<VersionReq>,
+    #[serde(default)]
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Parses an advisory in the markdown format of the advisory-db: a TOML
+    /// front matter in a code block, followed by the title as a heading.
+    ///
+    /// Returns `None` for withdrawn advisories.
+    pub fn parse(contents: &str) -> Result<Option<Advisory>, Error> {
+        let front_matter = contents
+            .strip_prefix("```toml")
+            .and_then(|rest| rest.split_once("\n```"))
+            .map(|(front_matter, _)| front_matter)
+            .ok_or_else(|| format_err!("missing TOML front matter"))?;
+        let file: AdvisoryFile = toml::from_str(front_matter)?;
+        if file.advisory.withdrawn.is_some() {
+            return Ok(None);
+        }
+        let title = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        Ok(Some(Advisory {
+            id: file.advisory.id,
+            package: file.advisory.package,
+            title,
+            informational: file.advisory.informational,
+            patched: file.versions.patched,
+            unaffected: file.versions.unaffected,
+        }))
+    }
+
+    /// Whether the given version of the crate is affected.
+    pub fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+
+    /// "vulnerability" or the informational kind, e.g. "unmaintained".
+    pub fn kind(&self) -> &str {
+        self.informational.as_deref().unwrap_or("vulnerability")
+    }
+}
+
+/// All advisories of an advisory-db checkout by crate name.
+#[derive(Debug, Default)]
+pub struct AdvisoryDatabase {
+    advisories: BTreeMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDatabase {
+    /// Loads the advisories for crates.io crates from `crates/*/*.md`.
+    pub fn load(path: &Path) -> Result<AdvisoryDatabase, Error> {
+        let crates_dir = path.join("crates");
+        if !crates_dir.is_dir() {
+            bail!(
+                "{} does not look like an advisory-db checkout: missing 'crates' directory.",
+                path.to_string_lossy()
+            );
+        }
+        let mut db = AdvisoryDatabase::default();
+        for crate_dir in std::fs::read_dir(&crates_dir)? {
+            let crate_dir = crate_dir?.path();
+            if !crate_dir.is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(&crate_dir)? {
+                let file = file?.path();
+                if file.extension().is_none_or(|e| e != "md") {
+                    continue;
+                }
+                let contents = std::fs::read_to_string(&file)?;
+                let advisory = Advisory::parse(&contents)
+                    .with_context(|| format!("while parsing {}", file.to_string_lossy()))?;
+                if let Some(advisory) = advisory {
+                    db.add(advisory);
+                }
+            }
+        }
+        for advisories in db.advisories.values_mut() {
+            advisories.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+        Ok(db)
+    }
+
+    /// Adds an advisory.
+    pub fn add(&mut self, advisory: Advisory) {
+        self.advisories
+            .entry(advisory.package.clone())
+            .or_default()
+            .push(advisory);
+    }
+
+    /// The advisories affecting the given crates.io crate.
+    pub fn advisories_for<'a>(
+        &'a self,
+        crate_name: &str,
+        version: &'a Version,
+    ) -> impl Iterator<Item = &'a Advisory> + 'a {
+        self.advisories
+            .get(crate_name)
+            .into_iter()
+            .flatten()
+            .filter(move |advisory| advisory.affects(version))
+    }
+
+    /// Records the IDs and titles of the vulnerabilities of each crates.io
+    /// crate so that they are rendered as `meta.knownVulnerabilities`.
+    pub fn annotate(&self, build_info: &mut BuildInfo) {
+        for crate_derivation in build_info
+            .crates
+            .iter_mut()
+            .chain(build_info.feature_profiles.values_mut().flatten())
+        {
+            if !matches!(crate_derivation.source, ResolvedSource::CratesIo(_)) {
+                continue;
+            }
+            crate_derivation.known_vulnerabilities = self
+                .advisories_for(&crate_derivation.crate_name, &crate_derivation.version)
+                .filter(|advisory| advisory.informational.is_none())
+                .map(|advisory| format!("{}: {}", advisory.id, advisory.title))
+                .collect();
+        }
+    }
+}
+
+/// The advisories affecting the build of each workspace member.
+#[derive(Debug, Serialize)]
+pub struct AuditReport {
+    /// The findings by workspace member name.
+    pub members: BTreeMap<String, Vec<Finding>>,
+}
+
+/// A crate in the build of a workspace member with an advisory.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    /// The advisory ID.
+    pub id: String,
+    /// "vulnerability" or the informational kind, e.g. "unmaintained".
+    pub kind: String,
+    /// The title of the advisory.
+    pub title: String,
+    /// The affected crate.
+    pub crate_name: String,
+    /// The affected version.
+    pub version: String,
+    /// The crates from the workspace member to the affected crate, as "name version".
+    pub path: Vec<String>,
+}
+
+impl AuditReport {
+    /// Matches all crates.io crates needed to build each workspace member
+    /// against the advisories.
+    pub fn new(build_info: &BuildInfo, db: &AdvisoryDatabase) -> AuditReport {
+        let workspace = to_resolved_workspace(build_info);
+        let crates_io: BTreeMap<&str, &Version> = build_info
+            .crates
+            .iter()
+            .filter(|c| matches!(c.source, ResolvedSource::CratesIo(_)))
+            .map(|c| (c.package_id.repr.as_str(), &c.version))
+            .collect();
+        let describe = |package_id: &str| {
+            let resolved = &workspace.crates[package_id];
+            format!("{} {}", resolved.crate_name, resolved.version)
+        };
+
+        let members = workspace
+            .workspace_members
+            .iter()
+            .map(|(member, member_id)| {
+                let findings = workspace
+                    .closure_of([member_id.as_str()])
+                    .into_iter()
+                    .filter_map(|package_id| Some((package_id, *crates_io.get(package_id)?)))
+                    .flat_map(|(package_id, version)| {
+                        let crate_name = &workspace.crates[package_id].crate_name;
+                        let path = workspace
+                            .path_to(member_id, package_id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(describe)
+                            .collect::<Vec<_>>();
+                        db.advisories_for(crate_name, version)
+                            .map(move |advisory| Finding {
+                                id: advisory.id.clone(),
+                                kind: advisory.kind().to_string(),
+                                title: advisory.title.clone(),
+                                crate_name: crate_name.clone(),
+                                version: version.to_string(),
+                                path: path.clone(),
+                            })
+                    })
+                    .sorted_by(|a, b| (&a.crate_name, &a.id).cmp(&(&b.crate_name, &b.id)))
+                    .collect();
+                (member.clone(), findings)
+            })
+            .collect();
+        AuditReport { members }
+    }
+
+    /// Fails if any workspace member depends on a vulnerable crate.
+    /// Informational advisories are only reported.
+    pub fn check(&self) -> Result<(), Error> {
+        let vulnerable = self
+            .members
+            .values()
+            .flatten()
+            .filter(|f| f.kind == "vulnerability")
+            .map(|f| (&f.crate_name, &f.version))
+            .unique()
+            .count();
+        if vulnerable > 0 {
+            bail!("Found {} vulnerable crates.", vulnerable);
+        }
+        Ok(())
+    }
+}
+
+impl Display for AuditReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (member, findings) in &self.members {
+            if findings.is_empty() {
+                writeln!(f, "{}: no advisories", member)?;
+                continue;
+            }
+            writeln!(f, "{}:", member)?;
+            for finding in findings {
+                writeln!(
+                    f,
+                    "  {} {} ({}): {}\n    {}",
+                    finding.id,
+                    finding.crate_name,
+                    finding.kind,
+                    finding.title,
+                    finding.path.join(" -> ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+
+    const ADVISORY: &str = r#"```toml
+[advisory]
+id = "RUSTSEC-2020-0001"
+package = "vulnerable"
+date = "2020-01-01"
+categories = ["memory-corruption"]
+
+[versions]
+patched = [">= 0.2.0"]
+unaffected = ["< 0.1.0"]
+```
+
+# Use after free in vulnerable
+
+Details.
+"#;
+
+    #[test]
+    fn parses_and_matches_advisories() {
+        let advisory = Advisory::parse(ADVISORY).unwrap().unwrap();
+        assert_eq!(advisory.id, "RUSTSEC-2020-0001");
+        assert_eq!(advisory.title, "Use after free in vulnerable");
+        assert_eq!(advisory.kind(), "vulnerability");
+        assert!(advisory.affects(&Version::parse("0.1.5").unwrap()));
+        assert!(!advisory.affects(&Version::parse("0.2.0").unwrap()));
+        assert!(!advisory.affects(&Version::parse("0.0.9").unwrap()));
+
+        let withdrawn = ADVISORY.replace("[versions]", "withdrawn = \"2020-02-01\"\n[versions]");
+        assert!(withdrawn.contains("withdrawn"));
+        assert!(Advisory::parse(&withdrawn).unwrap().is_none());
+        assert!(Advisory::parse("# No front matter").is_err());
+    }
+
+    #[test]
+    fn reports_findings_with_paths() {
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.add_dependency("middle")
+            .update_package_and_node(|middle| {
+                middle
+                    .add_dependency("vulnerable")
+                    .version_and_package_id("0.1.0")
+                    .update_package(|p| {
+                        p.source = Some(cargo_metadata::Source {
+                            repr: "registry+https://github.com/rust-lang/crates.io-index"
+                                .to_string(),
+                        })
+                    });
+            });
+        let mut build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+
+        let mut db = AdvisoryDatabase::default();
+        db.add(Advisory::parse(ADVISORY).unwrap().unwrap());
+        let report = AuditReport::new(&build_info, &db);
+
+        assert_eq!(
+            report.to_string(),
+            "main:\n  RUSTSEC-2020-0001 vulnerable (vulnerability): Use after free in vulnerable\n    \
+             main 0.1.0 -> middle 0.1.0 -> vulnerable 0.1.0\n"
+        );
+        assert!(report.check().is_err());
+
+        db.annotate(&mut build_info);
+        let workspace = crate::json_output::to_resolved_workspace(&build_info);
+        assert_eq!(
+            workspace.crates["vulnerable"].known_vulnerabilities,
+            vec!["RUSTSEC-2020-0001: Use after free in vulnerable"]
+        );
+        assert!(workspace.crates["middle"].known_vulnerabilities.is_empty());
+
+        env.close();
+    }
+}
@@ -0,0 +1,395 @@
+//! Summarizing the differences between two generated workspaces.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::json_output::{ResolvedCrate, ResolvedWorkspace, SourceInfo};
+
+/// The crates that were added, removed or changed between two generations.
+#[derive(Debug, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceChanges {
+    /// Crates that were not part of the old workspace.
+    pub added: Vec<CrateVersion>,
+    /// Crates that are not part of the new workspace anymore.
+    pub removed: Vec<CrateVersion>,
+    /// Crates with a different version or different build information.
+    pub changed: Vec<CrateChange>,
+    /// Crates with a sha256 that was not pinned in the old workspace.
+    pub new_hashes: Vec<CrateHash>,
+}
+
+/// A crate name and version.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CrateVersion {
+    /// The crate name.
+    pub name: String,
+    /// The crate version.
+    pub version: String,
+}
+
+/// A crate that exists in both generations but changed.
+#[derive(Debug, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CrateChange {
+    /// The crate name.
+    pub name: String,
+    /// The version in the old workspace.
+    pub old_version: String,
+    /// The version in the new workspace, equal to `old_version` if only the
+    /// build information changed.
+    pub new_version: String,
+    /// The old and new kind of source, e.g. "crates-io" and "git", if it changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_kind: Option<(String, String)>,
+    /// Newly enabled features of a workspace member.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_features: Vec<String>,
+    /// Features of a workspace member that are not enabled anymore.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_features: Vec<String>,
+}
+
+/// A pinned source hash.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CrateHash {
+    /// The crate name.
+    pub name: String,
+    /// The crate version.
+    pub version: String,
+    /// The sha256 of the crate source.
+    pub sha256: String,
+}
+
+impl WorkspaceChanges {
+    /// Compares the crates of two workspaces.
+    ///
+    /// Crates are matched by name. If a name occurs with exactly one version on
+    /// each side, a different version is reported as a change. Otherwise, all
+    /// versions that only occur on one side are reported as added or removed.
+    pub fn between(old: &ResolvedWorkspace, new: &ResolvedWorkspace) -> WorkspaceChanges {
+        let old_by_name = crates_by_name(old);
+        let new_by_name = crates_by_name(new);
+        let names: BTreeSet<&str> = old_by_name
+            .keys()
+            .chain(new_by_name.keys())
+            .copied()
+            .collect();
+        let members: BTreeSet<&String> = new.workspace_members.values().collect();
+
+        let mut changes = WorkspaceChanges::default();
+        for name in names {
+            let no_versions = BTreeMap::new();
+            let old_versions = old_by_name.get(name).unwrap_or(&no_versions);
+            let new_versions = new_by_name.get(name).unwrap_or(&no_versions);
+
+            let single_version_pair = match (
+                &old_versions.values().collect::<Vec<_>>()[..],
+                &new_versions.iter().collect::<Vec<_>>()[..],
+            ) {
+                ([old_crate], [(package_id, new_crate)]) => {
+                    Some((*old_crate, *new_crate, members.contains(*package_id)))
+                }
+                _ => None,
+            };
+
+            if let Some((old_crate, new_crate, is_member)) = single_version_pair {
+                changes
+                    .changed
+                    .extend(CrateChange::between(old_crate, new_crate, is_member));
+            } else {
+                let old_crates: BTreeMap<&str, &ResolvedCrate> =
+                    old_versions.values().map(|c| (&*c.version, *c)).collect();
+                let mut matched = BTreeSet::new();
+                for (package_id, new_crate) in new_versions {
+                    match old_crates.get(&*new_crate.version) {
+                        Some(old_crate) => {
+                            matched.insert(&*new_crate.version);
+                            changes.changed.extend(CrateChange::between(
+                                old_crate,
+                                new_crate,
+                                members.contains(package_id),
+                            ));
+                        }
+                        None => changes.added.push(CrateVersion::of(new_crate)),
+                    }
+                }
+                changes.removed.extend(
+                    old_crates
+                        .iter()
+                        .filter(|(version, _)| !matched.contains(*version))
+                        .map(|(_, old_crate)| CrateVersion::of(old_crate)),
+                );
+            }
+
+            // Compare in nix-base32 so that switching the hash format is not a change.
+            let source_hash = |c: &ResolvedCrate| {
+                c.hash
+                    .as_ref()
+                    .or(c.sha256.as_ref())
+                    .map(|hash| crate::hashes::to_nix_base32(hash).unwrap_or_else(|_| hash.clone()))
+            };
+            for new_crate in new_versions.values() {
+                let old_sha256 = old_versions
+                    .values()
+                    .find(|c| c.version == new_crate.version)
+                    .and_then(|c| source_hash(c));
+                match new_crate.hash.as_ref().or(new_crate.sha256.as_ref()) {
+                    Some(sha256) if source_hash(new_crate) != old_sha256 => {
+                        changes.new_hashes.push(CrateHash {
+                            name: new_crate.crate_name.clone(),
+                            version: new_crate.version.clone(),
+                            sha256: sha256.clone(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        changes
+    }
+
+    /// Returns true if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.new_hashes.is_empty()
+    }
+}
+
+impl CrateVersion {
+    fn of(resolved_crate: &ResolvedCrate) -> CrateVersion {
+        CrateVersion {
+            name: resolved_crate.crate_name.clone(),
+            version: resolved_crate.version.clone(),
+        }
+    }
+}
+
+impl CrateChange {
+    /// Returns the change between the two crates or `None` if they are equal.
+    ///
+    /// Feature changes are only reported for workspace members since the
+    /// features of dependencies follow from them.
+    fn between(old: &ResolvedCrate, new: &ResolvedCrate, is_member: bool) -> Option<CrateChange> {
+        if old == new {
+            return None;
+        }
+
+        let old_kind = source_kind(old.source.as_ref());
+        let new_kind = source_kind(new.source.as_ref());
+        let mut change = CrateChange {
+            name: new.crate_name.clone(),
+            old_version: old.version.clone(),
+            new_version: new.version.clone(),
+            source_kind: (old_kind != new_kind)
+                .then(|| (old_kind.to_string(), new_kind.to_string())),
+            ..CrateChange::default()
+        };
+        if is_member {
+            let old_features: BTreeSet<&String> = old.resolved_default_features.iter().collect();
+            let new_features: BTreeSet<&String> = new.resolved_default_features.iter().collect();
+            change.added_features = new_features
+                .difference(&old_features)
+                .map(|f| f.to_string())
+                .collect();
+            change.removed_features = old_features
+                .difference(&new_features)
+                .map(|f| f.to_string())
+                .collect();
+        }
+        Some(change)
+    }
+}
+
+fn source_kind(source: Option<&SourceInfo>) -> &'static str {
+    match source {
+        Some(SourceInfo::CratesIo) => "crates-io",
+        Some(SourceInfo::Local { .. }) => "local",
+        Some(SourceInfo::Git { .. }) => "git",
+        Some(SourceInfo::Tarball { .. }) => "tarball",
+        None => "nix",
+    }
+}
+
+/// Crates by name and package ID.
+fn crates_by_name(
+    workspace: &ResolvedWorkspace,
+) -> BTreeMap<&str, BTreeMap<&String, &ResolvedCrate>> {
+    let mut by_name: BTreeMap<&str, BTreeMap<&String, &ResolvedCrate>> = BTreeMap::new();
+    for (package_id, resolved_crate) in &workspace.crates {
+        by_name
+            .entry(&resolved_crate.crate_name)
+            .or_default()
+            .insert(package_id, resolved_crate);
+    }
+    by_name
+}
+
+impl Display for WorkspaceChanges {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No crates changed.");
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+        for added in &self.added {
+            write!(f, "\n  + {} {}", added.name, added.version)?;
+        }
+        for removed in &self.removed {
+            write!(f, "\n  - {} {}", removed.name, removed.version)?;
+        }
+        for changed in &self.changed {
+            write!(f, "\n  ~ {}", changed)?;
+        }
+        for hash in &self.new_hashes {
+            write!(f, "\n  # {} {}: {}", hash.name, hash.version, hash.sha256)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CrateChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.old_version)?;
+        if self.old_version != self.new_version {
+            write!(f, " -> {}", self.new_version)?;
+        }
+        if let Some((old_kind, new_kind)) = &self.source_kind {
+            write!(f, " (source: {} -> {})", old_kind, new_kind)?;
+        }
+        if !self.added_features.is_empty() || !self.removed_features.is_empty() {
+            let features = self
+                .added_features
+                .iter()
+                .map(|f| format!("+{}", f))
+                .chain(self.removed_features.iter().map(|f| format!("-{}", f)))
+                .collect::<Vec<_>>();
+            write!(f, " (features: {})", features.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(crates: Vec<serde_json::Value>) -> ResolvedWorkspace {
+        let crates: BTreeMap<String, ResolvedCrate> = crates
+            .into_iter()
+            .map(|json| {
+                let resolved_crate: ResolvedCrate = serde_json::from_value(json).unwrap();
+                (
+                    format!("{} {}", resolved_crate.crate_name, resolved_crate.version),
+                    resolved_crate,
+                )
+            })
+            .collect();
+        ResolvedWorkspace {
+            generator: "@generated by crate2nix".to_string(),
+            root: None,
+            workspace_members: vec![("member".to_string(), "member 0.1.0".to_string())]
+                .into_iter()
+                .collect(),
+            resolvable: false,
+            crates,
+            profiles: BTreeMap::new(),
+            ..ResolvedWorkspace::default()
+        }
+    }
+
+    fn krate(name: &str, version: &str) -> serde_json::Value {
+        serde_json::json!({
+            "crateName": name,
+            "version": version,
+            "edition": "2021",
+            "source": { "type": "crates-io" },
+        })
+    }
+
+    #[test]
+    fn detects_added_removed_and_changed_crates() {
+        let mut edited = krate("edited", "0.1.0");
+        edited["edition"] = "2018".into();
+        let old = workspace(vec![
+            krate("bumped", "1.0.0"),
+            edited,
+            krate("removed", "1.0.0"),
+            krate("same", "1.0.0"),
+            krate("two", "1.0.0"),
+            krate("two", "2.0.0"),
+        ]);
+        let new = workspace(vec![
+            krate("added", "0.1.0"),
+            krate("bumped", "1.1.0"),
+            krate("edited", "0.1.0"),
+            krate("same", "1.0.0"),
+            krate("two", "2.0.0"),
+            krate("two", "3.0.0"),
+        ]);
+
+        let changes = WorkspaceChanges::between(&old, &new);
+
+        assert_eq!(
+            changes.to_string(),
+            "2 added, 2 removed, 2 changed\n  \
+             + added 0.1.0\n  \
+             + two 3.0.0\n  \
+             - removed 1.0.0\n  \
+             - two 1.0.0\n  \
+             ~ bumped 1.0.0 -> 1.1.0\n  \
+             ~ edited 0.1.0"
+        );
+        assert!(WorkspaceChanges::between(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn detects_source_feature_and_hash_changes() {
+        let mut old_member = krate("member", "0.1.0");
+        old_member["source"] = serde_json::json!({ "type": "local", "path": "." });
+        old_member["resolvedDefaultFeatures"] = serde_json::json!(["default", "old"]);
+        let mut new_member = old_member.clone();
+        new_member["resolvedDefaultFeatures"] = serde_json::json!(["default", "new"]);
+        let mut old_dep = krate("dep", "1.0.0");
+        old_dep["sha256"] = "aaaa".into();
+        let mut new_dep = krate("dep", "1.0.0");
+        new_dep["source"] = serde_json::json!({
+            "type": "git",
+            "url": "https://example.com/dep.git",
+            "rev": "abc",
+        });
+        new_dep["sha256"] = "bbbb".into();
+
+        let changes = WorkspaceChanges::between(
+            &workspace(vec![old_member, old_dep]),
+            &workspace(vec![new_member, new_dep]),
+        );
+
+        assert_eq!(
+            changes.to_string(),
+            "0 added, 0 removed, 2 changed\n  \
+             ~ dep 1.0.0 (source: crates-io -> git)\n  \
+             ~ member 0.1.0 (features: +new -old)\n  \
+             # dep 1.0.0: bbbb"
+        );
+        assert_eq!(
+            serde_json::to_value(&changes).unwrap()["changed"][1],
+            serde_json::json!({
+                "name": "member",
+                "oldVersion": "0.1.0",
+                "newVersion": "0.1.0",
+                "addedFeatures": ["new"],
+                "removedFeatures": ["old"],
+            })
+        );
+    }
+}
@@ -1,20 +1,24 @@
 //! Utilities for dealing with spawned commands.
 
-use anyhow::{bail, format_err, Error};
+use crate::error::CommandFailure;
+use crate::report::{Event, Reporter};
+use anyhow::{format_err, Error};
 use std::process::{Child, Stdio};
 use std::thread;
-use std::{
-    io::{BufRead, Read},
-    io::{BufReader, Cursor},
-    sync::mpsc,
-};
+use std::{io::Read, sync::mpsc};
 
 /// Runs the given command with output capturing.
 ///
-/// The output will be printed indented if and only if the command does not
-/// return succesfully.
-pub fn run(caption: &str, command: &mut std::process::Command) -> Result<(), Error> {
-    eprint!("{}: ", caption);
+/// If the command does not return succesfully, the error is a
+/// [`CommandFailure`] with the combined output of the command.
+pub fn run(
+    reporter: &dyn Reporter,
+    caption: &str,
+    command: &mut std::process::Command,
+) -> Result<(), Error> {
+    reporter.report(&Event::TaskStarted {
+        caption: caption.to_string(),
+    });
 
     let mut spawned: Child = command
         .stdin(Stdio::null())
@@ -37,26 +41,23 @@ pub fn run(caption: &str, command: &mut std::process::Command) -> Result<(), Err
         .wait()
         .map_err(|e| format_err!("while waiting for the {:?} to finish: {}", command, e))?;
 
+    reporter.report(&Event::TaskFinished {
+        caption: caption.to_string(),
+        success: status.success(),
+    });
     if status.success() {
-        eprintln!("done.");
         return Ok(());
     }
 
-    eprintln!();
-    eprintln!("  {:?}", command);
-    let line_reader = BufReader::new(Cursor::new(out));
-    for line in line_reader.lines() {
-        println!(
-            "  {}",
-            line.map_err(|e| format_err!("while processing output lines: {}", e))?
-        );
-    }
-
-    bail!(
-        "{:?}\n=> exited with: {}",
+    Err(CommandFailure::new(
         command,
-        status.code().unwrap_or(-1)
-    );
+        &std::process::Output {
+            status,
+            stdout: Vec::new(),
+            stderr: out,
+        },
+    )
+    .into())
 }
 
 fn pass_through(mut read: impl Read + Send + 'static, sender: mpsc::Sender<Vec<u8>>) {
@@ -38,7 +38,77 @@ impl Config {
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
     /// Out of tree sources.
+    #[serde(default)]
     pub sources: BTreeMap<String, Source>,
+    /// Named feature profiles that are resolved in addition to the main feature
+    /// selection for JSON output.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, FeatureProfile>,
+    /// The license policy checked by `crate2nix licenses`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub licenses: Option<LicensePolicy>,
+}
+
+/// Which licenses are acceptable for the crates of the build.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LicensePolicy {
+    /// Allowed SPDX license identifiers. If empty, all licenses that are not
+    /// denied are allowed.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Denied SPDX license identifiers.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Names of crates that are accepted regardless of their license.
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    /// Whether `crate2nix generate` fails if the policy is violated.
+    #[serde(default)]
+    pub enforce: bool,
+}
+
+/// A named feature selection, e.g. "minimal" or "full".
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureProfile {
+    /// Features to enable by workspace member name.
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Whether to enable the default features of the workspace members.
+    #[serde(default = "default_true")]
+    pub default_features: bool,
+    /// Whether to enable all features. Overrides the other settings.
+    #[serde(default)]
+    pub all_features: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl FeatureProfile {
+    /// The `cargo metadata` options to resolve this profile.
+    pub fn metadata_options(&self) -> Vec<String> {
+        if self.all_features {
+            return vec!["--all-features".to_string()];
+        }
+
+        let mut options = Vec::new();
+        if !self.default_features {
+            options.push("--no-default-features".to_string());
+        }
+        let features: Vec<String> = self
+            .features
+            .iter()
+            .flat_map(|(member, features)| features.iter().map(move |f| format!("{member}/{f}")))
+            .collect();
+        if !features.is_empty() {
+            options.push("--features".to_string());
+            options.push(features.join(" "));
+        }
+        options
+    }
 }
 
 impl Config {
@@ -113,9 +183,34 @@ pub enum Source {
         url: url::Url,
         /// The revision hash.
         rev: String,
+        /// The branch or tag that `crate2nix source update` moves `rev` to.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        r#ref: Option<String>,
         /// The sha256 of the fetched result.
         sha256: String,
     },
+    /// Use a local directory, e.g. a sibling checkout.
+    Path {
+        /// The directory, relative to `crate2nix.json` or absolute.
+        path: String,
+    },
+    /// Get the source from a tarball, e.g. a release archive.
+    Tarball {
+        /// The URL of the tarball.
+        url: url::Url,
+        /// The sha256 of the unpacked tarball, as printed by `nix-prefetch-url --unpack`.
+        sha256: String,
+        /// The directory within the unpacked tarball that contains the sources.
+        ///
+        /// Like `nix-prefetch-url --unpack`, a single top-level directory is
+        /// already stripped.
+        #[serde(
+            rename = "stripPrefix",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        strip_prefix: Option<String>,
+    },
     /// Get the source from a nix expression.
     Nix {
         /// The nixfile to include.
@@ -170,6 +265,16 @@ impl Source {
                     .unwrap_or(after_last_slash);
                 Some(without_dot_git)
             }
+            Source::Path { path } => Path::new(path.trim_end_matches('/'))
+                .file_name()
+                .and_then(|name| name.to_str()),
+            Source::Tarball { url, .. } => {
+                let file_name = url.path_segments()?.next_back()?;
+                [".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".tar", ".zip"]
+                    .iter()
+                    .find_map(|extension| file_name.strip_suffix(extension))
+                    .or(Some(file_name))
+            }
             Source::Nix {
                 attr: Some(attr), ..
             } => attr.split('.').next_back().or(if attr.trim().is_empty() {
@@ -197,7 +302,29 @@ impl Display for Source {
                 registry,
                 ..
             } => write!(f, "{} {} from {}: {}", name, version, registry, sha256),
-            Source::Git { url, rev, sha256 } => write!(f, "{}#{} via git: {}", url, rev, sha256),
+            Source::Git {
+                url,
+                rev,
+                r#ref: None,
+                sha256,
+            } => write!(f, "{}#{} via git: {}", url, rev, sha256),
+            Source::Git {
+                url,
+                rev,
+                r#ref: Some(r#ref),
+                sha256,
+            } => write!(f, "{}#{} ({}) via git: {}", url, rev, r#ref, sha256),
+            Source::Path { path } => write!(f, "{} (local directory)", path),
+            Source::Tarball {
+                url,
+                sha256,
+                strip_prefix: None,
+            } => write!(f, "{} via tarball: {}", url, sha256),
+            Source::Tarball {
+                url,
+                sha256,
+                strip_prefix: Some(strip_prefix),
+            } => write!(f, "{} ({}) via tarball: {}", url, strip_prefix, sha256),
             Source::Nix { file, attr: None } => write!(f, "{}", file),
             Source::Nix {
                 file,
@@ -225,9 +352,30 @@ impl Source {
                 "registry --registry '{}' --name '{}' '{}' '{}'",
                 registry, name, crate_name, version
             ),
+            Source::Git {
+                url,
+                r#ref: Some(r#ref),
+                ..
+            } => {
+                format!("git --name '{}' '{}' --ref '{}'", name, url, r#ref)
+            }
             Source::Git { url, rev, .. } => {
                 format!("git --name '{}' '{}' --rev {}", name, url, rev)
             }
+            Source::Path { path } => format!("path --name '{}' '{}'", name, path),
+            Source::Tarball {
+                url,
+                strip_prefix: None,
+                ..
+            } => format!("tarball --name '{}' '{}'", name, url),
+            Source::Tarball {
+                url,
+                strip_prefix: Some(strip_prefix),
+                ..
+            } => format!(
+                "tarball --name '{}' '{}' --strip-prefix '{}'",
+                name, url, strip_prefix
+            ),
             Source::Nix { file, attr: None } => {
                 format!("nix --name '{}' {}", name, file.as_command())
             }
@@ -238,3 +386,65 @@ impl Source {
         }
     }
 }
+
+#[test]
+fn feature_profile_metadata_options() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "profiles": {
+                "default": {},
+                "minimal": { "defaultFeatures": false },
+                "full": { "allFeatures": true },
+                "tls": { "features": { "server": ["tls", "http2"], "client": ["tls"] } }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert!(config.sources.is_empty());
+    let options = |name: &str| config.profiles[name].metadata_options();
+    assert_eq!(options("default"), Vec::<String>::new());
+    assert_eq!(options("minimal"), vec!["--no-default-features"]);
+    assert_eq!(options("full"), vec!["--all-features"]);
+    assert_eq!(
+        options("tls"),
+        vec!["--features", "client/tls server/tls server/http2"]
+    );
+}
+
+#[test]
+fn path_and_tarball_sources() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "sources": {
+                "local": { "type": "Path", "path": "../vendor/local/" },
+                "remote": {
+                    "type": "Tarball",
+                    "url": "https://example.com/archives/remote-1.0.tar.gz",
+                    "sha256": "0ysj00x31q08vxsznqd9pmvwa0rrzza8qqjy3hcvhallzm054cxb",
+                    "stripPrefix": "remote-1.0"
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let local = &config.sources["local"];
+    assert_eq!(local.name(), Some("local"));
+    assert_eq!(
+        local.as_command("local"),
+        "path --name 'local' '../vendor/local/'"
+    );
+
+    let remote = &config.sources["remote"];
+    assert_eq!(remote.name(), Some("remote-1.0"));
+    assert_eq!(
+        remote.as_command("remote"),
+        "tarball --name 'remote' 'https://example.com/archives/remote-1.0.tar.gz' \
+         --strip-prefix 'remote-1.0'"
+    );
+
+    let json = serde_json::to_value(&config).unwrap();
+    assert_eq!(json["sources"]["remote"]["stripPrefix"], "remote-1.0");
+    assert!(json["sources"]["local"].get("stripPrefix").is_none());
+}
@@ -0,0 +1,559 @@
+//! Diagnosing common problems with the environment and the project.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Error};
+use cargo_metadata::Metadata;
+use semver::Version;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::lock::{EncodableDependency, EncodableResolve};
+use crate::prefetch::parse_package_id_components;
+use crate::render::{check_generated_by_crate2nix, read_generate_info};
+use crate::{GenerateConfig, GenerateInfo};
+
+/// How bad a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Good to know.
+    Info,
+    /// Might cause problems or unnecessary work.
+    Warning,
+    /// `crate2nix generate` or the build will fail.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found by [`Diagnosis::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    /// How bad the problem is.
+    pub severity: Severity,
+    /// The check that found the problem, e.g. "tools".
+    pub check: &'static str,
+    /// What is wrong.
+    pub message: String,
+    /// How to fix it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+/// The findings of all checks, the most severe first.
+#[derive(Debug, Default, Serialize)]
+pub struct Diagnosis {
+    /// The problems found.
+    pub findings: Vec<Finding>,
+}
+
+impl Diagnosis {
+    /// Checks the environment and the project of the given configuration.
+    pub fn new(config: &GenerateConfig) -> Diagnosis {
+        Diagnosis::with_path(config, std::env::var_os("PATH").as_deref())
+    }
+
+    /// Like [`Diagnosis::new`] but looks up the tools in the given `PATH`.
+    fn with_path(config: &GenerateConfig, path: Option<&OsStr>) -> Diagnosis {
+        let mut diagnosis = Diagnosis::default();
+        diagnosis.check_crate2nix_json(&config.crate2nix_json);
+
+        let mut metadatas = Vec::new();
+        let mut lock_files = Vec::new();
+        for cargo_toml in &config.cargo_toml {
+            let metadata = diagnosis.check_metadata(cargo_toml);
+            let lock_file = metadata
+                .as_ref()
+                .map(|m| m.workspace_root.as_std_path().join("Cargo.lock"))
+                .unwrap_or_else(|| cargo_toml.with_file_name("Cargo.lock"));
+            lock_files.extend(diagnosis.check_lock_file(&lock_file));
+            metadatas.extend(metadata);
+        }
+        let crate_hashes = diagnosis.read_crate_hashes(&config.crate_hashes_json);
+
+        diagnosis.check_tools(path, &lock_files, &crate_hashes);
+        diagnosis.check_package_ids(&metadatas, &crate_hashes, &config.crate_hashes_json);
+        diagnosis.check_stale_hashes(&lock_files, &crate_hashes, &config.crate_hashes_json);
+        diagnosis.check_cargo_nix(&config.output);
+
+        diagnosis.findings.sort_by_key(|f| Reverse(f.severity));
+        diagnosis
+    }
+
+    /// The number of findings with [`Severity::Error`].
+    pub fn errors(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+            .count()
+    }
+
+    /// Fails if there are findings with [`Severity::Error`].
+    pub fn check(&self) -> Result<(), Error> {
+        match self.errors() {
+            0 => Ok(()),
+            1 => bail!("Found 1 error."),
+            errors => bail!("Found {} errors.", errors),
+        }
+    }
+
+    fn add(
+        &mut self,
+        severity: Severity,
+        check: &'static str,
+        message: impl Into<String>,
+        fix: Option<String>,
+    ) {
+        self.findings.push(Finding {
+            severity,
+            check,
+            message: message.into(),
+            fix,
+        });
+    }
+
+    fn check_crate2nix_json(&mut self, crate2nix_json: &Path) {
+        if let Err(e) = Config::read_from_or_default(crate2nix_json) {
+            self.add(
+                Severity::Error,
+                "crate2nix-json",
+                format!("{:#}", e),
+                Some(format!(
+                    "Fix or remove {}.",
+                    crate2nix_json.to_string_lossy()
+                )),
+            );
+        }
+    }
+
+    fn check_metadata(&mut self, cargo_toml: &Path) -> Option<Metadata> {
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(cargo_toml)
+            .no_deps()
+            .exec();
+        match metadata {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                self.add(
+                    Severity::Error,
+                    "cargo",
+                    format!(
+                        "cargo metadata failed for {}: {}",
+                        cargo_toml.to_string_lossy(),
+                        e
+                    ),
+                    Some("Check that cargo is installed and the manifest is valid.".to_string()),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_lock_file(&mut self, lock_file: &Path) -> Option<EncodableResolve> {
+        if !lock_file.exists() {
+            self.add(
+                Severity::Error,
+                "cargo-lock",
+                format!(
+                    "{} does not exist but crate2nix calls cargo with --locked.",
+                    lock_file.to_string_lossy()
+                ),
+                Some("Run `cargo generate-lockfile` and commit Cargo.lock.".to_string()),
+            );
+            return None;
+        }
+        match EncodableResolve::load_lock_file(lock_file) {
+            Ok(lock_file) => Some(lock_file),
+            Err(e) => {
+                self.add(
+                    Severity::Error,
+                    "cargo-lock",
+                    format!("{:#}", e),
+                    Some("Run `cargo update --workspace` to rewrite Cargo.lock.".to_string()),
+                );
+                None
+            }
+        }
+    }
+
+    fn read_crate_hashes(&mut self, crate_hashes_json: &Path) -> BTreeMap<String, String> {
+        if !crate_hashes_json.exists() {
+            return BTreeMap::new();
+        }
+        let hashes = std::fs::read_to_string(crate_hashes_json)
+            .map_err(Error::from)
+            .and_then(|content| Ok(serde_json::from_str(&content)?));
+        hashes.unwrap_or_else(|e| {
+            self.add(
+                Severity::Error,
+                "crate-hashes",
+                format!(
+                    "while reading {}: {:#}",
+                    crate_hashes_json.to_string_lossy(),
+                    e
+                ),
+                Some(format!(
+                    "Remove {} and run `crate2nix generate` to prefetch the hashes again.",
+                    crate_hashes_json.to_string_lossy()
+                )),
+            );
+            BTreeMap::new()
+        })
+    }
+
+    fn check_tools(
+        &mut self,
+        path: Option<&OsStr>,
+        lock_files: &[EncodableResolve],
+        crate_hashes: &BTreeMap<String, String>,
+    ) {
+        let hashed: BTreeSet<(String, String)> = crate_hashes
+            .keys()
+            .filter_map(|id| parse_package_id_components(id))
+            .collect();
+        let unhashed = |needs_prefetch: fn(&EncodableDependency) -> bool| {
+            lock_files
+                .iter()
+                .flat_map(EncodableResolve::packages)
+                .filter(|p| {
+                    needs_prefetch(p) && !hashed.contains(&(p.name.clone(), p.version.clone()))
+                })
+                .count()
+        };
+        let unhashed_git = unhashed(|p| {
+            p.source
+                .as_ref()
+                .is_some_and(|source| source.starts_with("git+"))
+        });
+        let unhashed_registry = unhashed(|p| {
+            p.source.as_ref().is_some_and(|source| {
+                source.starts_with("registry+") || source.starts_with("sparse+")
+            }) && p
+                .checksum
+                .as_ref()
+                .is_none_or(|checksum| checksum == "<none>")
+        });
+
+        for (tool, needed_for, unhashed) in [
+            ("nix-prefetch-git", "git dependencies", unhashed_git),
+            (
+                "nix-prefetch-url",
+                "crates without a checksum",
+                unhashed_registry,
+            ),
+            ("nix", "building the generated Cargo.nix", 0),
+        ] {
+            if find_in_path(path, tool).is_some() {
+                continue;
+            }
+            let fix = Some(format!(
+                "Install {}, e.g. with `nix-shell -p {}`.",
+                tool,
+                if tool == "nix" {
+                    "nix"
+                } else {
+                    "nix-prefetch-scripts"
+                }
+            ));
+            if unhashed > 0 && tool != "nix" {
+                self.add(
+                    Severity::Error,
+                    "tools",
+                    format!(
+                        "{} is not on the PATH but is needed to prefetch {} dependencies.",
+                        tool, unhashed
+                    ),
+                    fix,
+                );
+            } else {
+                self.add(
+                    Severity::Warning,
+                    "tools",
+                    format!(
+                        "{} is not on the PATH. It is needed for {}.",
+                        tool, needed_for
+                    ),
+                    fix,
+                );
+            }
+        }
+    }
+
+    fn check_package_ids(
+        &mut self,
+        metadatas: &[Metadata],
+        crate_hashes: &BTreeMap<String, String>,
+        crate_hashes_json: &Path,
+    ) {
+        let Some(package) = metadatas.iter().flat_map(|m| &m.packages).next() else {
+            return;
+        };
+        let cargo_uses_old_format = is_old_package_id(&package.id.repr);
+        if cargo_uses_old_format {
+            let cargo_version = Command::new("cargo")
+                .arg("--version")
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .unwrap_or_else(|| "cargo".to_string());
+            self.add(
+                Severity::Warning,
+                "package-ids",
+                format!(
+                    "{} uses the package ID format from before cargo 1.77. \
+                     The keys of crate-hashes.json change when you upgrade cargo.",
+                    cargo_version
+                ),
+                Some("Upgrade cargo to 1.77 or newer.".to_string()),
+            );
+        }
+
+        let other_format = crate_hashes
+            .keys()
+            .filter(|id| is_old_package_id(id) != cargo_uses_old_format)
+            .count();
+        if other_format > 0 {
+            self.add(
+                Severity::Warning,
+                "package-ids",
+                format!(
+                    "{} of {} entries in {} use the {} package ID format, but cargo uses the {} one. \
+                     Their hashes are prefetched again.",
+                    other_format,
+                    crate_hashes.len(),
+                    crate_hashes_json.to_string_lossy(),
+                    if cargo_uses_old_format { "new" } else { "old" },
+                    if cargo_uses_old_format { "old" } else { "new" },
+                ),
+                Some(
+                    "Run `crate2nix generate` with the current cargo and then \
+                     `crate2nix hashes verify --fix` to remove the old entries."
+                        .to_string(),
+                ),
+            );
+        }
+    }
+
+    fn check_stale_hashes(
+        &mut self,
+        lock_files: &[EncodableResolve],
+        crate_hashes: &BTreeMap<String, String>,
+        crate_hashes_json: &Path,
+    ) {
+        if lock_files.is_empty() {
+            return;
+        }
+        let locked: BTreeSet<(&str, &str)> = lock_files
+            .iter()
+            .flat_map(EncodableResolve::packages)
+            .map(|p| (p.name.as_str(), p.version.as_str()))
+            .collect();
+        let stale: Vec<&str> = crate_hashes
+            .keys()
+            .filter(|id| match parse_package_id_components(id) {
+                Some((name, version)) => !locked.contains(&(name.as_str(), version.as_str())),
+                None => true,
+            })
+            .map(String::as_str)
+            .collect();
+        if !stale.is_empty() {
+            self.add(
+                Severity::Warning,
+                "crate-hashes",
+                format!(
+                    "{} entries in {} are not in Cargo.lock anymore, e.g. {}.",
+                    stale.len(),
+                    crate_hashes_json.to_string_lossy(),
+                    stale[0]
+                ),
+                Some("Run `crate2nix hashes verify --fix` to remove them.".to_string()),
+            );
+        }
+    }
+
+    fn check_cargo_nix(&mut self, cargo_nix: &Path) {
+        if !cargo_nix.exists() {
+            self.add(
+                Severity::Info,
+                "cargo-nix",
+                format!("{} does not exist yet.", cargo_nix.to_string_lossy()),
+                Some("Run `crate2nix generate`.".to_string()),
+            );
+            return;
+        }
+        if check_generated_by_crate2nix(cargo_nix).is_err() {
+            self.add(
+                Severity::Error,
+                "cargo-nix",
+                format!(
+                    "{} was not generated by crate2nix and will not be overwritten.",
+                    cargo_nix.to_string_lossy()
+                ),
+                Some("Move it away or generate to another file with -o.".to_string()),
+            );
+            return;
+        }
+
+        let Ok(Some(info)) = read_generate_info(cargo_nix) else {
+            return;
+        };
+        let current = GenerateInfo::default().crate2nix_version;
+        let (Ok(generated_version), Ok(current_version)) = (
+            Version::parse(&info.crate2nix_version),
+            Version::parse(&current),
+        ) else {
+            return;
+        };
+        if generated_version < current_version {
+            self.add(
+                Severity::Warning,
+                "cargo-nix",
+                format!(
+                    "{} was generated by crate2nix {}, this is crate2nix {}.",
+                    cargo_nix.to_string_lossy(),
+                    generated_version,
+                    current_version
+                ),
+                Some("Run `crate2nix generate` to pick up fixes of the newer version.".to_string()),
+            );
+        } else if generated_version > current_version {
+            self.add(
+                Severity::Warning,
+                "cargo-nix",
+                format!(
+                    "{} was generated by the newer crate2nix {}, this is crate2nix {}.",
+                    cargo_nix.to_string_lossy(),
+                    generated_version,
+                    current_version
+                ),
+                Some(format!(
+                    "Upgrade crate2nix to {} before regenerating.",
+                    generated_version
+                )),
+            );
+        }
+    }
+}
+
+impl Display for Diagnosis {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.findings.is_empty() {
+            return writeln!(f, "No problems found.");
+        }
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "{:<8} [{}] {}",
+                finding.severity.to_string().to_uppercase(),
+                finding.check,
+                finding.message
+            )?;
+            if let Some(fix) = &finding.fix {
+                writeln!(f, "{:<8} fix: {}", "", fix)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the package ID uses the format from before cargo 1.77, e.g.
+/// `"serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)"`.
+fn is_old_package_id(id: &str) -> bool {
+    id.contains(' ')
+}
+
+/// The path of the executable `tool` in the directories of `path`.
+fn find_in_path(path: Option<&OsStr>, tool: &str) -> Option<PathBuf> {
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(tool))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn finds_stale_hashes_and_old_cargo_nix() {
+        let dir = TempDir::new("crate2nix_doctor").unwrap();
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .output(dir.path().join("Cargo.nix"))
+            .build()
+            .unwrap();
+        std::fs::write(
+            &config.crate_hashes_json,
+            r#"{
+                "registry+https://github.com/rust-lang/crates.io-index#cfg-if@1.0.0": "0",
+                "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)": "0"
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &config.output,
+            "# This file was @generated by crate2nix 0.1.0 with the command:\n\
+             #   \"generate\"\n",
+        )
+        .unwrap();
+
+        let diagnosis = Diagnosis::with_path(&config, Some(dir.path().as_os_str()));
+        let checks = diagnosis
+            .findings
+            .iter()
+            .map(|f| (f.severity, f.check))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            checks,
+            vec![
+                (Severity::Warning, "tools"),
+                (Severity::Warning, "tools"),
+                (Severity::Warning, "tools"),
+                (Severity::Warning, "package-ids"),
+                (Severity::Warning, "crate-hashes"),
+                (Severity::Warning, "cargo-nix"),
+            ]
+        );
+        assert!(diagnosis.findings[4].message.starts_with("1 entries"));
+        assert!(diagnosis.check().is_ok());
+    }
+
+    #[test]
+    fn refuses_foreign_cargo_nix_and_missing_tools() {
+        let dir = TempDir::new("crate2nix_doctor").unwrap();
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .output(dir.path().join("Cargo.nix"))
+            .build()
+            .unwrap();
+        std::fs::write(&config.output, "{ }\n").unwrap();
+
+        let diagnosis = Diagnosis::with_path(&config, Some(dir.path().as_os_str()));
+        assert_eq!(diagnosis.findings[0].severity, Severity::Error);
+        assert_eq!(diagnosis.findings[0].check, "cargo-nix");
+        // cfg-if has a checksum in Cargo.lock, so nothing needs to be prefetched.
+        assert_eq!(
+            diagnosis
+                .findings
+                .iter()
+                .filter(|f| f.severity == Severity::Error)
+                .count(),
+            1
+        );
+        assert!(diagnosis.check().is_err());
+    }
+}
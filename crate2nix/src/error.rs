@@ -0,0 +1,287 @@
+//! The errors of the crate2nix library.
+
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+/// An error of the crate2nix library.
+///
+/// The variants carry the context of the failure so that callers can handle
+/// them without matching on messages. [`Error::exit_code`] is the exit code of
+/// the `crate2nix` binary for the error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// `cargo metadata` failed or returned unusable metadata.
+    Metadata {
+        /// The manifest that `cargo metadata` was called for.
+        manifest_path: PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+    /// A `Cargo.lock` could not be read or parsed.
+    Lockfile {
+        /// The path of the lock file.
+        path: PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+    /// A source could not be prefetched, e.g. to fill in or verify its hash.
+    Prefetch {
+        /// The name of a crate from the source, if the source belongs to a crate.
+        crate_name: Option<String>,
+        /// The version of that crate.
+        version: Option<String>,
+        /// The source, e.g. its URL.
+        source: String,
+        /// The failed command, if any.
+        command: Option<Box<CommandFailure>>,
+        /// What went wrong.
+        message: String,
+    },
+    /// An out-of-tree source from `crate2nix.json` could not be fetched.
+    SourceFetch {
+        /// The name of the source.
+        name: String,
+        /// The failed command, if any.
+        command: Option<Box<CommandFailure>>,
+        /// What went wrong.
+        message: String,
+    },
+    /// A template could not be rendered.
+    Render {
+        /// The name of the template.
+        template: String,
+        /// What went wrong.
+        message: String,
+    },
+    /// A file would have been overwritten that was not generated by crate2nix.
+    OverwriteGuard {
+        /// The existing file.
+        path: PathBuf,
+    },
+    /// The configuration is invalid.
+    InvalidConfig {
+        /// What is wrong.
+        message: String,
+    },
+    /// Any other error.
+    Other(anyhow::Error),
+}
+
+/// A command that exited unsuccessfully.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandFailure {
+    /// The command line.
+    pub command: String,
+    /// The exit code, `None` if the command was terminated by a signal.
+    pub exit_code: Option<i32>,
+    /// The error output of the command.
+    pub stderr: String,
+}
+
+impl Error {
+    /// The exit code of the `crate2nix` binary for this error.
+    ///
+    /// These are stable: `1` for other errors, `10` for metadata, `11` for
+    /// lockfile, `12` for prefetch, `13` for source fetch, `14` for render, `15`
+    /// for overwrite guard and `16` for configuration errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Metadata { .. } => 10,
+            Error::Lockfile { .. } => 11,
+            Error::Prefetch { .. } => 12,
+            Error::SourceFetch { .. } => 13,
+            Error::Render { .. } => 14,
+            Error::OverwriteGuard { .. } => 15,
+            Error::InvalidConfig { .. } => 16,
+            Error::Other(_) => 1,
+        }
+    }
+
+    /// The failed command of the error, if any.
+    pub fn command(&self) -> Option<&CommandFailure> {
+        match self {
+            Error::Prefetch { command, .. } | Error::SourceFetch { command, .. } => {
+                command.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// An [`Error::Prefetch`] for the given source from the error of the
+    /// prefetch command.
+    pub(crate) fn prefetch(
+        crate_name_and_version: Option<(String, String)>,
+        source: impl Display,
+        error: anyhow::Error,
+    ) -> Error {
+        let (crate_name, version) = crate_name_and_version.unzip();
+        Error::Prefetch {
+            crate_name,
+            version,
+            source: source.to_string(),
+            command: CommandFailure::find(&error),
+            message: format!("{:#}", error),
+        }
+    }
+
+    /// An [`Error::SourceFetch`] for the given source.
+    pub(crate) fn source_fetch(name: impl Into<String>, error: anyhow::Error) -> Error {
+        Error::SourceFetch {
+            name: name.into(),
+            command: CommandFailure::find(&error),
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Metadata {
+                manifest_path,
+                message,
+            } => write!(
+                f,
+                "while retrieving metadata about {}: {}",
+                manifest_path.to_string_lossy(),
+                message
+            ),
+            Error::Lockfile { path, message } => {
+                write!(f, "while reading {}: {}", path.to_string_lossy(), message)
+            }
+            Error::Prefetch {
+                crate_name: Some(crate_name),
+                version: Some(version),
+                source,
+                message,
+                ..
+            } => write!(
+                f,
+                "while prefetching {} for {} {}: {}",
+                source, crate_name, version, message
+            ),
+            Error::Prefetch {
+                source, message, ..
+            } => write!(f, "while prefetching {}: {}", source, message),
+            Error::SourceFetch { name, message, .. } => {
+                write!(f, "while fetching source {}: {}", name, message)
+            }
+            Error::Render { template, message } => {
+                write!(f, "while rendering {}: {}", template, message)
+            }
+            Error::OverwriteGuard { path } => write!(
+                f,
+                "Cowardly refusing to overwrite {} without generated marker.",
+                path.to_string_lossy()
+            ),
+            Error::InvalidConfig { message } => write!(f, "{}", message),
+            Error::Other(error) => write!(f, "{:#}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<anyhow::Error> for Error {
+    /// Recovers a typed error that was passed through `anyhow`.
+    fn from(error: anyhow::Error) -> Error {
+        error.downcast::<Error>().unwrap_or_else(Error::Other)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Error {
+        Error::Other(error.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::Other(error.into())
+    }
+}
+
+impl Display for CommandFailure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.exit_code {
+            Some(exit_code) => write!(f, "'{}' exited with {}", self.command, exit_code)?,
+            None => write!(f, "'{}' was terminated", self.command)?,
+        }
+        if !self.stderr.trim().is_empty() {
+            write!(f, ": {}", self.stderr.trim())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CommandFailure {}
+
+impl CommandFailure {
+    /// The command failure that caused the error, if any.
+    fn find(error: &anyhow::Error) -> Option<Box<CommandFailure>> {
+        error
+            .chain()
+            .find_map(|e| e.downcast_ref::<CommandFailure>())
+            .map(|failure| Box::new(failure.clone()))
+    }
+
+    /// The failure of the given command with its output.
+    pub(crate) fn new(
+        command: &std::process::Command,
+        output: &std::process::Output,
+    ) -> CommandFailure {
+        let program = command.get_program().to_string_lossy();
+        let args = command.get_args().map(|arg| arg.to_string_lossy());
+        CommandFailure {
+            command: std::iter::once(program)
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join(" "),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_typed_errors_from_anyhow() {
+        let error = anyhow::Error::from(Error::OverwriteGuard {
+            path: PathBuf::from("Cargo.nix"),
+        })
+        .context("while generating");
+        let error = Error::from(error);
+        assert!(matches!(error, Error::OverwriteGuard { .. }));
+        assert_eq!(error.exit_code(), 15);
+
+        let error = Error::from(anyhow::format_err!("untyped"));
+        assert!(matches!(error, Error::Other(_)));
+        assert_eq!(error.exit_code(), 1);
+    }
+
+    #[test]
+    fn carries_failed_commands() {
+        let failure = CommandFailure {
+            command: "nix-prefetch-url https://example.com/foo.tar.gz".to_string(),
+            exit_code: Some(1),
+            stderr: "error: unable to download\n".to_string(),
+        };
+        let error = Error::prefetch(
+            Some(("foo".to_string(), "1.0.0".to_string())),
+            "https://example.com/foo.tar.gz",
+            anyhow::Error::from(failure.clone()).context("while prefetching"),
+        );
+        assert_eq!(error.command(), Some(&failure));
+        assert_eq!(error.exit_code(), 12);
+        assert_eq!(
+            error.to_string(),
+            "while prefetching https://example.com/foo.tar.gz for foo 1.0.0: \
+             while prefetching: 'nix-prefetch-url https://example.com/foo.tar.gz' \
+             exited with 1: error: unable to download"
+        );
+    }
+}
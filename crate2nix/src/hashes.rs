@@ -0,0 +1,800 @@
+//! Hash formats and verifying the prefetched hashes in `crate-hashes.json` and
+//! `registry-hashes.json`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err, Error};
+use cargo_metadata::PackageId;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Source};
+use crate::prefetch::{self, HashSource, HashWithSource, PrefetchableSource, SourcePrefetchBundle};
+use crate::report::{Event, OutputKind, Reporter};
+use crate::resolve::{RegistrySource, ResolvedSource};
+use crate::{Artifact, BuildInfo};
+
+/// The format in which sha256 hashes are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashFormat {
+    /// The nix-base32 encoding of the digest, rendered as `sha256 = "...";`.
+    #[default]
+    NixBase32,
+    /// An SRI hash like `sha256-<base64>`, rendered as `hash = "...";`.
+    Sri,
+}
+
+impl HashFormat {
+    /// Converts a sha256 hash in any supported format to this format.
+    pub fn convert(self, hash: &str) -> Result<String, Error> {
+        let digest = sha256_digest(hash)?;
+        Ok(match self {
+            HashFormat::NixBase32 => nix_base32::to_nix_base32(&digest),
+            HashFormat::Sri => format!("sha256-{}", to_base64(&digest)),
+        })
+    }
+}
+
+impl FromStr for HashFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nix-base32" => Ok(HashFormat::NixBase32),
+            "sri" => Ok(HashFormat::Sri),
+            _ => bail!(
+                "unknown hash format '{}', expected 'nix-base32' or 'sri'",
+                s
+            ),
+        }
+    }
+}
+
+impl Display for HashFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HashFormat::NixBase32 => write!(f, "nix-base32"),
+            HashFormat::Sri => write!(f, "sri"),
+        }
+    }
+}
+
+/// Converts a sha256 hash to nix-base32, the format used internally.
+///
+/// Accepts SRI hashes (`sha256-<base64>`), nix-base32 and hex, optionally
+/// prefixed with `sha256:`.
+///
+/// ```
+/// use crate2nix::hashes::to_nix_base32;
+///
+/// let nix_base32 = "0ysj00x31q08vxsznqd9pmvwa0rrzza8qqjy3hcvhallzm054cxb";
+/// assert_eq!(
+///     to_nix_base32("sha256-qzNSQP2UKrgZHF5ijNT/OQPFd72pYft13wjgMDoAUns=").unwrap(),
+///     nix_base32
+/// );
+/// assert_eq!(
+///     to_nix_base32("ab335240fd942ab8191c5e628cd4ff3903c577bda961fb75df08e0303a00527b").unwrap(),
+///     nix_base32
+/// );
+/// assert_eq!(to_nix_base32(nix_base32).unwrap(), nix_base32);
+/// assert!(to_nix_base32("sha256-tooshort").is_err());
+/// ```
+pub fn to_nix_base32(hash: &str) -> Result<String, Error> {
+    HashFormat::NixBase32.convert(hash)
+}
+
+/// Decodes a sha256 hash in any of the supported formats.
+pub(crate) fn sha256_digest(hash: &str) -> Result<Vec<u8>, Error> {
+    let hash = hash.trim();
+    let digest = if let Some(base64) = hash.strip_prefix("sha256-") {
+        from_base64(base64)
+    } else {
+        let encoded = hash.strip_prefix("sha256:").unwrap_or(hash);
+        match encoded.len() {
+            64 => hex::decode(encoded).ok(),
+            52 => crate::util::from_nix_base32(encoded),
+            _ => None,
+        }
+    };
+    match digest {
+        Some(digest) if digest.len() == 32 => Ok(digest),
+        _ => bail!("'{}' is not a valid sha256 hash", hash),
+    }
+}
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut buf = [0u8; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = (buf[0] as u32) << 16 | (buf[1] as u32) << 8 | buf[2] as u32;
+        for k in 0..4 {
+            if k <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * k) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn from_base64(encoded: &str) -> Option<Vec<u8>> {
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for chunk in encoded.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            n = n << 6 | BASE64_CHARS.iter().position(|&x| x == c)? as u32;
+        }
+        n <<= 6 * padding;
+        bytes.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+/// Fetches sources to determine their actual hashes.
+pub trait Fetcher {
+    /// Returns the hash of a crate source.
+    fn fetch_source(&mut self, source: &ResolvedSource) -> Result<String, Error>;
+    /// Returns the hash of the `config.json` of a registry.
+    fn fetch_registry(&mut self, registry: &str) -> Result<String, Error>;
+}
+
+/// Fetches with `nix-prefetch-url` and `nix-prefetch-git`.
+#[derive(Debug, Default)]
+pub struct NixPrefetch;
+
+impl Fetcher for NixPrefetch {
+    fn fetch_source(&mut self, source: &ResolvedSource) -> Result<String, Error> {
+        source.prefetch()
+    }
+
+    fn fetch_registry(&mut self, registry: &str) -> Result<String, Error> {
+        prefetch::prefetch_registry_config(registry)
+    }
+}
+
+/// A stored hash that does not match the fetched source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashMismatch {
+    /// The source (or registry) that was fetched.
+    pub source: String,
+    /// The hash in the hash file.
+    pub stored: String,
+    /// The hash of the fetched source.
+    pub fetched: String,
+}
+
+/// The result of [`verify`].
+#[derive(Debug, Default)]
+pub struct HashVerification {
+    /// The number of sources and registries with a correct hash.
+    pub verified: usize,
+    /// Sources and registries with a wrong hash.
+    pub mismatches: Vec<HashMismatch>,
+    /// Entries of the hash files that are not used by the build.
+    pub unused: Vec<String>,
+    /// Sources and registries that could not be fetched, as
+    /// [`crate::Error::Prefetch`].
+    pub failed: Vec<crate::Error>,
+    crate_hashes: BTreeMap<PackageId, String>,
+    registry_hashes: BTreeMap<String, String>,
+    changed: bool,
+}
+
+/// Re-fetches all sources with a hash in `crate-hashes.json` (and the registries in
+/// `registry-hashes.json`) and compares the hashes.
+///
+/// Only crates for which `selected` returns true are fetched. Unused entries are
+/// always reported.
+///
+/// Use a [`BuildInfo`] from [`BuildInfo::for_config_without_hashes`] so that the hash
+/// files are not updated before they are verified.
+pub fn verify(
+    build_info: &BuildInfo,
+    selected: impl Fn(&str) -> bool,
+    fetcher: &mut dyn Fetcher,
+) -> Result<HashVerification, Error> {
+    let config = &build_info.config;
+    let id_shortener = &build_info.indexed_metadata.id_shortener;
+    let stored_crate_hashes: BTreeMap<PackageId, String> = normalize_hashes(
+        read_json(&config.crate_hashes_json)?,
+        &config.crate_hashes_json,
+    )?;
+    let stored_registry_hashes: BTreeMap<String, String> = normalize_hashes(
+        read_json(&config.registry_hashes_json)?,
+        &config.registry_hashes_json,
+    )?;
+
+    let mut verification = HashVerification {
+        crate_hashes: stored_crate_hashes.clone(),
+        registry_hashes: stored_registry_hashes.clone(),
+        ..HashVerification::default()
+    };
+
+    let stored_by_name_version: HashMap<(String, String), &PackageId> = stored_crate_hashes
+        .keys()
+        .filter_map(|id| prefetch::parse_package_id_components(&id.repr).map(|key| (key, id)))
+        .collect();
+
+    let packages_by_source = prefetch::packages_by_source(&build_info.crates);
+    let mut used_keys = BTreeSet::new();
+    let mut bundles = Vec::new();
+    for (source, packages) in &packages_by_source {
+        // Registry hashes are verified per registry below.
+        if matches!(source, ResolvedSource::Registry(_)) || !source.needs_prefetch() {
+            continue;
+        }
+        let keys: Vec<&PackageId> = packages
+            .iter()
+            .filter_map(|p| {
+                let id = id_shortener.lengthen_ref(&p.package_id);
+                if stored_crate_hashes.contains_key(id) {
+                    Some(id)
+                } else {
+                    stored_by_name_version
+                        .get(&(p.crate_name.clone(), p.version.to_string()))
+                        .copied()
+                }
+            })
+            .collect();
+        used_keys.extend(keys.iter().copied());
+        let Some(key) = keys.first() else {
+            continue;
+        };
+        if !packages.iter().any(|p| selected(&p.crate_name)) {
+            continue;
+        }
+        bundles.push((
+            SourcePrefetchBundle {
+                source,
+                packages,
+                hash: Some(HashWithSource {
+                    sha256: stored_crate_hashes[key].clone(),
+                    source: HashSource::Prefetched,
+                }),
+            },
+            keys,
+        ));
+    }
+    bundles.sort_by_key(|(bundle, _)| bundle.source.to_string());
+
+    let reporter = &config.reporter;
+    for (idx, (bundle, keys)) in bundles.iter().enumerate() {
+        let stored = bundle
+            .hash
+            .as_ref()
+            .map(|h| h.sha256.clone())
+            .unwrap_or_default();
+        let caption = format!(
+            "Verifying {:>4}/{}: {}",
+            idx + 1,
+            bundles.len(),
+            bundle.source
+        );
+        reporter.report(&Event::TaskStarted {
+            caption: caption.clone(),
+        });
+        let fetched = fetcher.fetch_source(bundle.source);
+        reporter.report(&Event::TaskFinished {
+            caption,
+            success: fetched.is_ok(),
+        });
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                let crate_name_and_version = bundle
+                    .packages
+                    .first()
+                    .map(|p| (p.crate_name.clone(), p.version.to_string()));
+                verification.failed.push(crate::Error::prefetch(
+                    crate_name_and_version,
+                    bundle.source,
+                    e,
+                ));
+                continue;
+            }
+        };
+        if fetched == stored {
+            verification.verified += 1;
+            continue;
+        }
+        verification.mismatches.push(HashMismatch {
+            source: bundle.source.to_string(),
+            stored,
+            fetched: fetched.clone(),
+        });
+        for key in keys {
+            verification
+                .crate_hashes
+                .insert((*key).clone(), fetched.clone());
+        }
+        verification.changed = true;
+    }
+
+    // Registries by whether any selected crate uses them.
+    let mut registries: BTreeMap<&str, bool> = BTreeMap::new();
+    for c in &build_info.crates {
+        if let ResolvedSource::Registry(RegistrySource { registry, .. }) = &c.source {
+            *registries.entry(registry.as_str()).or_default() |= selected(&c.crate_name);
+        }
+    }
+    for (registry, selected) in &registries {
+        let Some(stored) = stored_registry_hashes.get(*registry) else {
+            continue;
+        };
+        if !selected {
+            continue;
+        }
+        match fetcher.fetch_registry(registry) {
+            Ok(fetched) if fetched == *stored => verification.verified += 1,
+            Ok(fetched) => {
+                verification.mismatches.push(HashMismatch {
+                    source: format!("{} (registry config)", registry),
+                    stored: stored.clone(),
+                    fetched: fetched.clone(),
+                });
+                verification
+                    .registry_hashes
+                    .insert(registry.to_string(), fetched);
+                verification.changed = true;
+            }
+            Err(e) => verification
+                .failed
+                .push(crate::Error::prefetch(None, registry, e)),
+        }
+    }
+
+    for key in stored_crate_hashes.keys() {
+        if !used_keys.contains(key) {
+            verification.unused.push(key.repr.clone());
+            verification.crate_hashes.remove(key);
+            verification.changed = true;
+        }
+    }
+    for registry in stored_registry_hashes.keys() {
+        if !registries.contains_key(registry.as_str()) {
+            verification.unused.push(registry.clone());
+            verification.registry_hashes.remove(registry);
+            verification.changed = true;
+        }
+    }
+
+    Ok(verification)
+}
+
+impl HashVerification {
+    /// Whether all verified hashes are correct and all entries are used.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty() && self.unused.is_empty() && self.failed.is_empty()
+    }
+
+    /// The error of the first source or registry that could not be fetched.
+    pub fn into_fetch_error(self) -> Option<crate::Error> {
+        self.failed.into_iter().next()
+    }
+
+    /// Rewrites the hash files with the fetched hashes and without unused entries.
+    pub fn fix(&self, build_info: &BuildInfo) -> Result<(), Error> {
+        if !self.changed {
+            return Ok(());
+        }
+        let config = &build_info.config;
+        update_hashes(
+            &config.crate_hashes_json,
+            &self.crate_hashes,
+            config.hash_format,
+        )?;
+        if config.registry_hashes_json.exists() || !self.registry_hashes.is_empty() {
+            update_hashes(
+                &config.registry_hashes_json,
+                &self.registry_hashes,
+                config.hash_format,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HashVerification {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Verified {} hashes.", self.verified)?;
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "MISMATCH {}: stored {}, fetched {}",
+                mismatch.source, mismatch.stored, mismatch.fetched
+            )?;
+        }
+        for error in &self.failed {
+            writeln!(f, "FAILED   {}", error)?;
+        }
+        for unused in &self.unused {
+            writeln!(f, "UNUSED   {}", unused)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites all hashes in the hash files and the sources in `crate2nix.json`
+/// in the given format.
+///
+/// Returns the number of converted hashes.
+pub fn migrate(
+    crate_hashes_json: &Path,
+    registry_hashes_json: &Path,
+    crate2nix_json: &Path,
+    format: HashFormat,
+    reporter: &dyn Reporter,
+) -> Result<usize, Error> {
+    let mut converted = 0;
+    for path in [crate_hashes_json, registry_hashes_json] {
+        let hashes: BTreeMap<String, String> = read_json(path)?;
+        let migrated = hashes
+            .iter()
+            .map(|(key, hash)| Ok((key.clone(), format.convert(hash)?)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()
+            .map_err(|e| format_err!("while reading {}: {}", path.to_string_lossy(), e))?;
+        let changed = hashes
+            .values()
+            .zip(migrated.values())
+            .filter(|(old, new)| old != new)
+            .count();
+        if changed > 0 {
+            format_hashes(path, &migrated, format)?.write()?;
+            reporter.report(&Event::OutputWritten {
+                kind: OutputKind::Hashes,
+                path: path.to_path_buf(),
+            });
+            converted += changed;
+        }
+    }
+
+    let mut config = Config::read_from_or_default(crate2nix_json)?;
+    let mut changed = 0;
+    for (name, source) in config.sources.iter_mut() {
+        let sha256 = match source {
+            Source::CratesIo { sha256, .. }
+            | Source::Registry { sha256, .. }
+            | Source::Git { sha256, .. }
+            | Source::Tarball { sha256, .. } => sha256,
+            Source::Path { .. } | Source::Nix { .. } => continue,
+        };
+        let migrated = format
+            .convert(sha256)
+            .map_err(|e| format_err!("while converting the hash of source '{}': {}", name, e))?;
+        if *sha256 != migrated {
+            *sha256 = migrated;
+            changed += 1;
+        }
+    }
+    if changed > 0 {
+        config.write_to(crate2nix_json)?;
+        reporter.report(&Event::OutputWritten {
+            kind: OutputKind::Hashes,
+            path: crate2nix_json.to_path_buf(),
+        });
+        converted += changed;
+    }
+
+    Ok(converted)
+}
+
+fn read_json<T: serde::de::DeserializeOwned + Default>(path: &Path) -> Result<T, Error> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let contents = std::fs::read_to_string(path)?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format_err!("while parsing {}: {}", path.to_string_lossy(), e))
+}
+
+/// Converts the hashes read from the given hash file to nix-base32.
+pub(crate) fn normalize_hashes<K: Ord>(
+    hashes: BTreeMap<K, String>,
+    path: &Path,
+) -> Result<BTreeMap<K, String>, Error> {
+    hashes
+        .into_iter()
+        .map(|(key, hash)| Ok((key, to_nix_base32(&hash)?)))
+        .collect::<Result<_, Error>>()
+        .map_err(|e| format_err!("while reading {}: {}", path.to_string_lossy(), e))
+}
+
+/// Writes the hashes to the given hash file, keeping the format of the existing
+/// hashes in it. `format` is used for new files.
+pub(crate) fn update_hashes<K: Ord + Serialize>(
+    path: &Path,
+    hashes: &BTreeMap<K, String>,
+    format: HashFormat,
+) -> Result<(), Error> {
+    Ok(hash_file(path, hashes, format)?.write()?)
+}
+
+/// The given hash file with the hashes, keeping the format of the existing
+/// hashes in it. `format` is used for new files.
+pub(crate) fn hash_file<K: Ord + Serialize>(
+    path: &Path,
+    hashes: &BTreeMap<K, String>,
+    format: HashFormat,
+) -> Result<Artifact, Error> {
+    let existing: BTreeMap<String, String> = read_json(path).unwrap_or_default();
+    let format = if existing.values().any(|hash| hash.starts_with("sha256-")) {
+        HashFormat::Sri
+    } else if existing.is_empty() {
+        format
+    } else {
+        HashFormat::NixBase32
+    };
+    format_hashes(path, hashes, format)
+}
+
+/// The hash file with the hashes in the given format.
+fn format_hashes<K: Ord + Serialize>(
+    path: &Path,
+    hashes: &BTreeMap<K, String>,
+    format: HashFormat,
+) -> Result<Artifact, Error> {
+    let formatted = hashes
+        .iter()
+        .map(|(key, hash)| Ok((key, format.convert(hash)?)))
+        .collect::<Result<BTreeMap<_, _>, Error>>()?;
+    Ok(Artifact {
+        path: path.to_path_buf(),
+        content: serde_json::to_string_pretty(&formatted)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+    use tempdir::TempDir;
+
+    const NIX_BASE32: &str = "0ysj00x31q08vxsznqd9pmvwa0rrzza8qqjy3hcvhallzm054cxb";
+    const SRI: &str = "sha256-qzNSQP2UKrgZHF5ijNT/OQPFd72pYft13wjgMDoAUns=";
+    const STALE: &str = "0000000000000000000000000000000000000000000000000000";
+
+    #[derive(Default)]
+    struct FakeFetcher {
+        fetched: Vec<String>,
+        offline: bool,
+    }
+
+    impl Fetcher for FakeFetcher {
+        fn fetch_source(&mut self, source: &ResolvedSource) -> Result<String, Error> {
+            self.fetched.push(source.to_string());
+            if self.offline {
+                bail!("network is unreachable");
+            }
+            Ok(NIX_BASE32.to_string())
+        }
+
+        fn fetch_registry(&mut self, registry: &str) -> Result<String, Error> {
+            Err(format_err!("unexpected registry {}", registry))
+        }
+    }
+
+    #[test]
+    fn verifies_and_fixes_crate_hashes() {
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        for dependency in &["good", "bad"] {
+            main.add_dependency(dependency)
+                .version_and_package_id("1.0.0")
+                .update_package(|p| {
+                    p.source = Some(cargo_metadata::Source {
+                        repr: "registry+https://github.com/rust-lang/crates.io-index".to_string(),
+                    })
+                });
+        }
+
+        let temp_dir = TempDir::new("crate2nix_hashes").unwrap();
+        let mut config = test::generate_config();
+        config.crate_hashes_json = temp_dir.path().join("crate-hashes.json");
+        let build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &config,
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        let id_of = |name: &str| {
+            let c = build_info
+                .crates
+                .iter()
+                .find(|c| c.crate_name == name)
+                .unwrap();
+            build_info
+                .indexed_metadata
+                .id_shortener
+                .lengthen_ref(&c.package_id)
+                .repr
+                .clone()
+        };
+        let stored = serde_json::json!({
+            id_of("good"): SRI,
+            id_of("bad"): STALE,
+            "gone 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)": STALE,
+        });
+        std::fs::write(&config.crate_hashes_json, stored.to_string()).unwrap();
+
+        let mut fetcher = FakeFetcher::default();
+        let verification = verify(&build_info, |_| true, &mut fetcher).unwrap();
+        assert_eq!(fetcher.fetched.len(), 2);
+        assert_eq!(verification.verified, 1);
+        assert_eq!(verification.mismatches.len(), 1);
+        assert_eq!(verification.mismatches[0].stored, STALE);
+        assert_eq!(
+            verification.unused,
+            vec!["gone 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)"]
+        );
+        assert!(!verification.is_ok());
+
+        verification.fix(&build_info).unwrap();
+        let fixed: BTreeMap<String, String> =
+            read_json(&build_info.config.crate_hashes_json).unwrap();
+        assert_eq!(
+            fixed,
+            BTreeMap::from([
+                (id_of("bad"), SRI.to_string()),
+                (id_of("good"), SRI.to_string()),
+            ])
+        );
+
+        let mut fetcher = FakeFetcher::default();
+        let verification = verify(&build_info, |name| name == "good", &mut fetcher).unwrap();
+        assert_eq!(fetcher.fetched.len(), 1);
+        assert!(verification.is_ok());
+
+        let mut fetcher = FakeFetcher {
+            offline: true,
+            ..FakeFetcher::default()
+        };
+        let verification = verify(&build_info, |name| name == "good", &mut fetcher).unwrap();
+        assert!(!verification.is_ok());
+        let error = verification.into_fetch_error().unwrap();
+        assert!(matches!(
+            &error,
+            crate::Error::Prefetch { crate_name: Some(name), .. } if name == "good"
+        ));
+        assert_eq!(error.exit_code(), 12);
+
+        env.close();
+    }
+
+    #[test]
+    fn verifies_tarball_hashes() {
+        let mut env = test::MetadataEnv::default();
+        env.add_package_and_node("main").make_root();
+        let temp_dir = TempDir::new("crate2nix_hashes").unwrap();
+        let mut config = test::generate_config();
+        config.crate_hashes_json = temp_dir.path().join("crate-hashes.json");
+        let mut build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &config,
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        build_info.crates[0].source = ResolvedSource::Tarball(crate::resolve::TarballSource {
+            url: "https://example.com/main-0.1.0.tar.gz".parse().unwrap(),
+            sha256: None,
+            strip_prefix: None,
+        });
+        let id = build_info
+            .indexed_metadata
+            .id_shortener
+            .lengthen_ref(&build_info.crates[0].package_id)
+            .repr
+            .clone();
+        std::fs::write(
+            &config.crate_hashes_json,
+            serde_json::json!({ &id: STALE }).to_string(),
+        )
+        .unwrap();
+
+        let mut fetcher = FakeFetcher::default();
+        let verification = verify(&build_info, |_| true, &mut fetcher).unwrap();
+        assert_eq!(
+            fetcher.fetched,
+            vec!["https://example.com/main-0.1.0.tar.gz"]
+        );
+        assert_eq!(verification.mismatches.len(), 1);
+        assert!(verification.unused.is_empty());
+
+        verification.fix(&build_info).unwrap();
+        let fixed: BTreeMap<String, String> =
+            read_json(&build_info.config.crate_hashes_json).unwrap();
+        assert_eq!(fixed, BTreeMap::from([(id, NIX_BASE32.to_string())]));
+
+        env.close();
+    }
+
+    #[test]
+    fn converts_between_hash_formats() {
+        assert_eq!(HashFormat::Sri.convert(NIX_BASE32).unwrap(), SRI);
+        assert_eq!(HashFormat::Sri.convert(SRI).unwrap(), SRI);
+        assert_eq!(HashFormat::NixBase32.convert(SRI).unwrap(), NIX_BASE32);
+        assert_eq!(
+            HashFormat::NixBase32
+                .convert(&format!("sha256:{}", NIX_BASE32))
+                .unwrap(),
+            NIX_BASE32
+        );
+        assert!(HashFormat::Sri.convert("123").is_err());
+        assert!(HashFormat::Sri
+            .convert(&NIX_BASE32.replace('0', "e"))
+            .is_err());
+        assert!(HashFormat::Sri.convert("sha512-qzNSQP2UKrgZ").is_err());
+        assert_eq!("sri".parse::<HashFormat>().unwrap(), HashFormat::Sri);
+        assert!("base64".parse::<HashFormat>().is_err());
+    }
+
+    #[test]
+    fn migrates_hash_files_and_config() {
+        let temp_dir = TempDir::new("crate2nix_hashes").unwrap();
+        let crate_hashes_json = temp_dir.path().join("crate-hashes.json");
+        let registry_hashes_json = temp_dir.path().join("registry-hashes.json");
+        let crate2nix_json = temp_dir.path().join("crate2nix.json");
+        std::fs::write(
+            &crate_hashes_json,
+            serde_json::json!({ "some 0.1.0": NIX_BASE32 }).to_string(),
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.upsert_source(
+            Some("some".to_string()),
+            Source::Git {
+                url: "https://github.com/some/some".parse().unwrap(),
+                rev: "abcdef".to_string(),
+                r#ref: None,
+                sha256: NIX_BASE32.to_string(),
+            },
+        );
+        config.write_to(&crate2nix_json).unwrap();
+
+        let migrate = |format| {
+            migrate(
+                &crate_hashes_json,
+                &registry_hashes_json,
+                &crate2nix_json,
+                format,
+                &crate::report::TerminalReporter,
+            )
+            .unwrap()
+        };
+        assert_eq!(migrate(HashFormat::Sri), 2);
+        assert_eq!(migrate(HashFormat::Sri), 0);
+        assert!(!registry_hashes_json.exists());
+
+        let crate_hashes: BTreeMap<String, String> = read_json(&crate_hashes_json).unwrap();
+        assert_eq!(crate_hashes["some 0.1.0"], SRI);
+        let config = Config::read_from_or_default(&crate2nix_json).unwrap();
+        assert!(matches!(
+            &config.sources["some"],
+            Source::Git { sha256, .. } if sha256 == SRI
+        ));
+
+        // Newly prefetched hashes keep the format of the file.
+        update_hashes(
+            &crate_hashes_json,
+            &BTreeMap::from([("other 0.2.0", NIX_BASE32.to_string())]),
+            HashFormat::NixBase32,
+        )
+        .unwrap();
+        let crate_hashes: BTreeMap<String, String> = read_json(&crate_hashes_json).unwrap();
+        assert_eq!(crate_hashes["other 0.2.0"], SRI);
+    }
+}
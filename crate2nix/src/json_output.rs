@@ -4,31 +4,173 @@
 //! filtered by target platform and optional dep activation. This eliminates
 //! the O(n*m) feature resolution that the Nix template output requires at
 //! eval time.
+//!
+//! The opt-in "resolvable" variant (see [`to_resolvable_workspace`]) keeps the
+//! feature rules and optional dependencies instead, so that consumers can
+//! choose a different feature set at eval time.
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
 
+use anyhow::Error;
 use serde::{Deserialize, Serialize};
 
-use crate::resolve::{ResolvedDependency, ResolvedSource};
-use crate::BuildInfo;
+use crate::resolve::{CrateDerivation, ResolvedDependency, ResolvedSource};
+use crate::{BuildInfo, GenerateInfo};
 
 /// The pre-resolved workspace, ready for consumption by a thin Nix wrapper.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResolvedWorkspace {
     /// Generator marker so we can identify the file and avoid accidental overwrites.
     /// Always set to "@generated by crate2nix".
     pub generator: String,
+    /// The version of crate2nix that generated the file.
+    #[serde(default)]
+    pub crate2nix_version: String,
+    /// The arguments that crate2nix was called with.
+    #[serde(default)]
+    pub crate2nix_arguments: Vec<String>,
     /// Package ID of the root crate, or null for pure workspaces.
     pub root: Option<String>,
     /// Workspace member name → packageId.
     pub workspace_members: BTreeMap<String, String>,
+    /// The names of the workspace members in `workspace.default-members`.
+    /// Omitted if the workspace does not declare any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_members: Vec<String>,
+    /// Whether feature rules and inactive optional dependencies are preserved,
+    /// see [`to_resolvable_workspace`].
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub resolvable: bool,
     /// Package ID → resolved crate info.
     pub crates: BTreeMap<String, ResolvedCrate>,
+    /// Named feature profiles, see [`ResolvedWorkspace::add_profile`].
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub profiles: BTreeMap<String, ResolvedProfile>,
+}
+
+/// Reads the crate2nix version and arguments recorded in a generated
+/// `Cargo.json`, `None` if the file was generated by an older crate2nix.
+pub fn read_generate_info(path: &Path) -> Result<Option<GenerateInfo>, Error> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Recorded {
+        #[serde(default)]
+        crate2nix_version: String,
+        #[serde(default)]
+        crate2nix_arguments: Vec<String>,
+    }
+
+    let recorded: Recorded = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if recorded.crate2nix_version.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(GenerateInfo {
+        crate2nix_version: recorded.crate2nix_version,
+        crate2nix_arguments: recorded.crate2nix_arguments,
+    }))
+}
+
+/// The crates of a named feature profile.
+///
+/// Only crates that differ from the top-level `crates` are included. A consumer
+/// selects a profile by overlaying these crates over the top-level ones.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedProfile {
+    /// Package ID → resolved crate info for crates that differ in this profile.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub crates: BTreeMap<String, ResolvedCrate>,
+}
+
+impl ResolvedWorkspace {
+    /// Adds a named feature profile for the crates that cargo resolved for it,
+    /// see [`BuildInfo::feature_profiles`].
+    ///
+    /// Crates that are not activated in the profile are left out since they are
+    /// not reachable from the profile's dependencies anyway.
+    pub fn add_profile(&mut self, name: &str, build_info: &BuildInfo, crates: &[CrateDerivation]) {
+        let workspace_root = build_info.workspace_root.clone().unwrap_or_default();
+        let mut profile_crates = BTreeMap::new();
+        for crate_deriv in crates {
+            let resolved = convert_crate(
+                crate_deriv,
+                &crate_deriv.resolved_default_features,
+                &workspace_root,
+                self.resolvable,
+            );
+            if self.crates.get(&crate_deriv.package_id.repr) != Some(&resolved) {
+                profile_crates.insert(crate_deriv.package_id.repr.clone(), resolved);
+            }
+        }
+
+        self.profiles.insert(
+            name.to_string(),
+            ResolvedProfile {
+                crates: profile_crates,
+            },
+        );
+    }
+
+    /// The package IDs of the given crates and of all crates that they depend on
+    /// via normal or build dependencies, i.e. everything that goes into building
+    /// them.
+    pub fn closure_of<'a>(&'a self, roots: impl IntoIterator<Item = &'a str>) -> BTreeSet<&'a str> {
+        let mut closure = BTreeSet::new();
+        let mut queue: Vec<&str> = roots.into_iter().collect();
+        while let Some(package_id) = queue.pop() {
+            let Some(resolved_crate) = self.crates.get(package_id) else {
+                continue;
+            };
+            if closure.insert(package_id) {
+                queue.extend(
+                    resolved_crate
+                        .dependencies
+                        .iter()
+                        .chain(&resolved_crate.build_dependencies)
+                        .map(|d| d.package_id.as_str()),
+                );
+            }
+        }
+        closure
+    }
+
+    /// A shortest chain of normal or build dependencies from `root` to `target`,
+    /// as package IDs including both ends.
+    pub fn path_to<'a>(&'a self, root: &'a str, target: &str) -> Option<Vec<&'a str>> {
+        let mut parents: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue = std::collections::VecDeque::from([root]);
+        let mut visited = BTreeSet::from([root]);
+        while let Some(package_id) = queue.pop_front() {
+            if package_id == target {
+                let mut path = vec![package_id];
+                while let Some(parent) = parents.get(path[path.len() - 1]) {
+                    path.push(parent);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let Some(resolved_crate) = self.crates.get(package_id) else {
+                continue;
+            };
+            for dep in resolved_crate
+                .dependencies
+                .iter()
+                .chain(&resolved_crate.build_dependencies)
+            {
+                if visited.insert(&dep.package_id) {
+                    parents.insert(&dep.package_id, package_id);
+                    queue.push_back(&dep.package_id);
+                }
+            }
+        }
+        None
+    }
 }
 
 /// A single crate with pre-resolved dependencies.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ResolvedCrate {
     /// Crate name (as used in Cargo.toml).
@@ -37,53 +179,85 @@ pub struct ResolvedCrate {
     pub version: String,
     /// Rust edition (e.g. "2021").
     pub edition: String,
-    /// SHA-256 hash for crates.io or git sources.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// SHA-256 hash for crates.io or git sources in nix-base32.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub sha256: Option<String>,
+    /// SRI hash (`sha256-...`) for crates.io or git sources, instead of `sha256`
+    /// if generated with `--hash-format sri`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub hash: Option<String>,
     /// Source information (crates-io, local, git).
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub source: Option<SourceInfo>,
     /// Normal dependencies, already filtered by platform and optional dep activation.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub dependencies: Vec<DepInfo>,
     /// Build dependencies, already filtered by platform and optional dep activation.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub build_dependencies: Vec<DepInfo>,
     /// Dev dependencies (for tests/benches/examples). Only populated for
     /// workspace members since transitive deps' tests are never built.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub dev_dependencies: Vec<DepInfo>,
     /// The resolved features for this crate.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub resolved_default_features: Vec<String>,
+    /// Feature rules. Which feature (key) enables which other features (values).
+    /// Only populated in resolvable output.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub features: BTreeMap<String, Vec<String>>,
     /// Whether this is a proc-macro crate.
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
     pub proc_macro: bool,
     /// Build script path (relative to crate root), if non-default.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub build: Option<String>,
     /// Library source path (relative to crate root), if non-default.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub lib_path: Option<String>,
     /// Library name, if different from crate name.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub lib_name: Option<String>,
     /// Binary targets.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub crate_bin: Vec<BinTarget>,
     /// Library crate types (e.g. \["lib"\], \["cdylib", "rlib"\]).
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub lib_crate_types: Vec<String>,
     /// Native library this crate links to (the `links` field in Cargo.toml).
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub links: Option<String>,
+    /// The rustc flags for the `[lints]` of workspace members.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub lint_flags: Vec<String>,
+    /// The minimum supported rust version from `package.rust-version`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub rust_version: Option<String>,
     /// Crate authors.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub authors: Vec<String>,
+    /// Package description.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
+    /// Package homepage.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub homepage: Option<String>,
+    /// Package repository.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub repository: Option<String>,
+    /// SPDX license expression.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub license: Option<String>,
+    /// The binary for `nix run`, only set for workspace members with a single binary.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub main_program: Option<String>,
+    /// The RustSec advisories affecting this crate, see `generate --advisory-db`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub known_vulnerabilities: Vec<String>,
 }
 
 /// A resolved dependency reference.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct DepInfo {
     /// Package name of the dependency.
@@ -91,16 +265,56 @@ pub struct DepInfo {
     /// Shortened package ID of the resolved dependency.
     pub package_id: String,
     /// Rename (extern crate name), if different from package name.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub rename: Option<String>,
     /// Platform condition string (e.g. `cfg(unix)`, `x86_64-unknown-linux-gnu`).
     /// Absent for unconditional deps.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     pub target: Option<String>,
+    /// Whether this dependency needs to be enabled via a feature.
+    /// Only set in resolvable output, inactive optional deps are dropped otherwise.
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub optional: bool,
+    /// Whether the dependency is used with its default features.
+    /// Only written in resolvable output.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub uses_default_features: Option<bool>,
+    /// Features enabled on the dependency. Only written in resolvable output.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub features: Vec<String>,
+}
+
+impl DepInfo {
+    /// The name under which the dependency is referenced by features,
+    /// i.e. the rename if present, otherwise the package name.
+    pub fn effective_name(&self) -> String {
+        self.rename
+            .as_ref()
+            .map(|r| normalize_name(r))
+            .unwrap_or_else(|| normalize_name(&self.name))
+    }
+}
+
+impl ResolvedCrate {
+    /// Expands `features` through the feature rules of a resolvable crate and
+    /// returns the effective names of the activated optional dependencies.
+    ///
+    /// This mirrors what [`to_resolved_workspace`] does ahead of time.
+    pub fn activated_optional_deps(&self, features: &[String]) -> HashSet<String> {
+        let optional_dep_effective_names = self
+            .dependencies
+            .iter()
+            .chain(self.build_dependencies.iter())
+            .chain(self.dev_dependencies.iter())
+            .filter(|d| d.optional)
+            .map(DepInfo::effective_name)
+            .collect();
+        expand_optional_deps(&self.features, &optional_dep_effective_names, features)
+    }
 }
 
 /// Source type for a crate.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type")]
 #[serde(rename_all = "kebab-case")]
 pub enum SourceInfo {
@@ -117,11 +331,40 @@ pub enum SourceInfo {
         url: String,
         /// Git revision.
         rev: String,
+        /// The branch or tag that the revision was resolved from.
+        #[serde(rename = "ref", skip_serializing_if = "Option::is_none", default)]
+        git_ref: Option<String>,
+        /// The nix-base32 sha256 of the checkout for a fixed-output fetch.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        sha256: Option<String>,
+        /// Like `sha256` but as SRI hash for `--hash-format sri`.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        hash: Option<String>,
+        /// Whether the checkout includes submodules, as cargo's does. The hash
+        /// covers the submodules.
+        #[serde(default)]
+        submodules: bool,
+        /// The directory of the crate within the repository.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        subdir: Option<String>,
+    },
+    /// Tarball from an out of tree source in `crate2nix.json`. The hash is the
+    /// crate's `sha256` or `hash`.
+    Tarball {
+        /// Tarball URL.
+        url: String,
+        /// The directory within the unpacked tarball.
+        #[serde(
+            rename = "stripPrefix",
+            skip_serializing_if = "Option::is_none",
+            default
+        )]
+        strip_prefix: Option<String>,
     },
 }
 
 /// A binary target within a crate.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct BinTarget {
     /// Binary name.
     pub name: String,
@@ -160,6 +403,20 @@ fn activated_optional_deps(
         })
         .collect();
 
+    expand_optional_deps(
+        features_map,
+        &optional_dep_effective_names,
+        resolved_features,
+    )
+}
+
+/// The feature expansion behind [`activated_optional_deps`], independent of
+/// the dependency representation.
+fn expand_optional_deps(
+    features_map: &BTreeMap<String, Vec<String>>,
+    optional_dep_effective_names: &HashSet<String>,
+    resolved_features: &[String],
+) -> HashSet<String> {
     let mut seen = HashSet::new();
     let mut queue: Vec<String> = resolved_features.to_vec();
     let mut activated = HashSet::new();
@@ -226,12 +483,31 @@ fn resolve_deps(deps: &[ResolvedDependency], activated: &HashSet<String>) -> Vec
             package_id: dep.package_id.repr.clone(),
             rename: dep.rename.as_ref().map(|r| normalize_name(r)),
             target: dep.target.as_ref().map(|p| p.to_string()),
+            optional: false,
+            uses_default_features: None,
+            features: vec![],
         })
         .collect()
 }
 
-fn convert_source(source: &ResolvedSource, workspace_root: &str) -> Option<SourceInfo> {
-    match source {
+/// Serialize all deps including optional ones together with the
+/// information needed to resolve features at eval time.
+fn resolvable_deps(deps: &[ResolvedDependency]) -> Vec<DepInfo> {
+    deps.iter()
+        .map(|dep| DepInfo {
+            name: dep.name.clone(),
+            package_id: dep.package_id.repr.clone(),
+            rename: dep.rename.as_ref().map(|r| normalize_name(r)),
+            target: dep.target.as_ref().map(|p| p.to_string()),
+            optional: dep.optional,
+            uses_default_features: Some(dep.uses_default_features),
+            features: dep.features.clone(),
+        })
+        .collect()
+}
+
+fn convert_source(crate_deriv: &CrateDerivation, workspace_root: &str) -> Option<SourceInfo> {
+    match &crate_deriv.source {
         ResolvedSource::CratesIo(_) => Some(SourceInfo::CratesIo),
         ResolvedSource::Registry(_) => {
             // TODO: handle non-crates.io registries
@@ -251,9 +527,26 @@ fn convert_source(source: &ResolvedSource, workspace_root: &str) -> Option<Sourc
                 path: path.to_string(),
             })
         }
-        ResolvedSource::Git(git) => Some(SourceInfo::Git {
-            url: git.url.to_string(),
-            rev: git.rev.clone(),
+        ResolvedSource::Git(git) => {
+            let is_sri = git
+                .sha256
+                .as_deref()
+                .is_some_and(|h| h.starts_with("sha256-"));
+            Some(SourceInfo::Git {
+                url: git.url.to_string(),
+                rev: git.rev.clone(),
+                git_ref: git.r#ref.clone(),
+                sha256: git.sha256.clone().filter(|_| !is_sri),
+                hash: git.sha256.clone().filter(|_| is_sri),
+                // Cargo checks out submodules and `nix-prefetch-git` is called with
+                // `--fetch-submodules`.
+                submodules: true,
+                subdir: crate_deriv.git_subdirectory.clone(),
+            })
+        }
+        ResolvedSource::Tarball(tarball) => Some(SourceInfo::Tarball {
+            url: tarball.url.to_string(),
+            strip_prefix: tarball.strip_prefix.clone(),
         }),
         ResolvedSource::Nix(_) => None,
     }
@@ -268,93 +561,36 @@ fn convert_source(source: &ResolvedSource, workspace_root: &str) -> Option<Sourc
 /// Local source paths are made relative to the workspace root so the
 /// consumer can reconstruct them as `src + "/${relativePath}"`.
 pub fn to_resolved_workspace(build_info: &BuildInfo) -> ResolvedWorkspace {
-    let workspace_root = build_info.workspace_root.clone().unwrap_or_default();
-    let mut crates = BTreeMap::new();
-
-    for crate_deriv in &build_info.crates {
-        let id = &crate_deriv.package_id.repr;
-
-        // All deps (normal + build + dev) for optional dep activation
-        let all_deps: Vec<&ResolvedDependency> = crate_deriv
-            .dependencies
-            .iter()
-            .chain(crate_deriv.build_dependencies.iter())
-            .chain(crate_deriv.dev_dependencies.iter())
-            .collect();
-
-        // Compute activated optional deps from resolved features
-        let activated = activated_optional_deps(
-            &crate_deriv.features,
-            &all_deps,
-            &crate_deriv.resolved_default_features,
-        );
+    to_workspace(build_info, false)
+}
 
-        let dependencies = resolve_deps(&crate_deriv.dependencies, &activated);
-        let build_dependencies = resolve_deps(&crate_deriv.build_dependencies, &activated);
-        // Only emit dev-deps for workspace members — transitive deps' tests
-        // are never built, so their dev-deps would just bloat the JSON.
-        let dev_dependencies = if crate_deriv.is_root_or_workspace_member {
-            resolve_deps(&crate_deriv.dev_dependencies, &activated)
-        } else {
-            vec![]
-        };
+/// Convert a BuildInfo into a "resolvable" workspace JSON.
+///
+/// Like [`to_resolved_workspace`] but optional dependencies are kept and
+/// marked as such, and the feature rules as well as per-dependency features
+/// are included. `resolvedDefaultFeatures` still describes the feature set
+/// that the JSON was generated for.
+pub fn to_resolvable_workspace(build_info: &BuildInfo) -> ResolvedWorkspace {
+    to_workspace(build_info, true)
+}
 
-        let lib_name = crate_deriv.lib.as_ref().map(|l| normalize_name(&l.name));
-
-        crates.insert(
-            id.clone(),
-            ResolvedCrate {
-                crate_name: crate_deriv.crate_name.clone(),
-                version: crate_deriv.version.to_string(),
-                edition: crate_deriv.edition.clone(),
-                sha256: crate_deriv.source.sha256().cloned(),
-                source: convert_source(&crate_deriv.source, &workspace_root),
-                dependencies,
-                build_dependencies,
-                dev_dependencies,
-                resolved_default_features: crate_deriv.resolved_default_features.clone(),
-                proc_macro: crate_deriv.proc_macro,
-                build: crate_deriv.build.as_ref().and_then(|b| {
-                    let s = b.src_path.to_string_lossy().to_string();
-                    if s == "build.rs" {
-                        None
-                    } else {
-                        Some(s)
-                    }
-                }),
-                lib_path: crate_deriv.lib.as_ref().and_then(|l| {
-                    let s = l.src_path.to_string_lossy().to_string();
-                    if s == "src/lib.rs" {
-                        None
-                    } else {
-                        Some(s)
-                    }
-                }),
-                lib_name: lib_name.as_ref().and_then(|n| {
-                    if *n == normalize_name(&crate_deriv.crate_name) {
-                        None
-                    } else {
-                        Some(n.clone())
-                    }
-                }),
-                crate_bin: if crate_deriv.is_root_or_workspace_member {
-                    crate_deriv
-                        .binaries
-                        .iter()
-                        .map(|b| BinTarget {
-                            name: b.name.clone(),
-                            path: b.src_path.to_string_lossy().to_string(),
-                        })
-                        .collect()
-                } else {
-                    vec![]
-                },
-                lib_crate_types: crate_deriv.lib_crate_types.clone(),
-                links: crate_deriv.links.clone(),
-                authors: crate_deriv.authors.clone(),
-            },
-        );
-    }
+fn to_workspace(build_info: &BuildInfo, resolvable: bool) -> ResolvedWorkspace {
+    let workspace_root = build_info.workspace_root.clone().unwrap_or_default();
+    let crates = build_info
+        .crates
+        .iter()
+        .map(|crate_deriv| {
+            (
+                crate_deriv.package_id.repr.clone(),
+                convert_crate(
+                    crate_deriv,
+                    &crate_deriv.resolved_default_features,
+                    &workspace_root,
+                    resolvable,
+                ),
+            )
+        })
+        .collect();
 
     let workspace_members = build_info
         .workspace_members
@@ -369,9 +605,123 @@ pub fn to_resolved_workspace(build_info: &BuildInfo) -> ResolvedWorkspace {
 
     ResolvedWorkspace {
         generator: "@generated by crate2nix".to_string(),
+        crate2nix_version: build_info.info.crate2nix_version.clone(),
+        crate2nix_arguments: build_info.info.crate2nix_arguments.clone(),
         root,
         workspace_members,
+        default_members: build_info.default_members.clone(),
+        resolvable,
         crates,
+        profiles: BTreeMap::new(),
+    }
+}
+
+/// Converts a crate for the given resolved features.
+fn convert_crate(
+    crate_deriv: &CrateDerivation,
+    resolved_features: &[String],
+    workspace_root: &str,
+    resolvable: bool,
+) -> ResolvedCrate {
+    // All deps (normal + build + dev) for optional dep activation
+    let all_deps: Vec<&ResolvedDependency> = crate_deriv
+        .dependencies
+        .iter()
+        .chain(crate_deriv.build_dependencies.iter())
+        .chain(crate_deriv.dev_dependencies.iter())
+        .collect();
+
+    // Compute activated optional deps from resolved features
+    let activated = activated_optional_deps(&crate_deriv.features, &all_deps, resolved_features);
+
+    let convert_deps = |deps: &[ResolvedDependency]| {
+        if resolvable {
+            resolvable_deps(deps)
+        } else {
+            resolve_deps(deps, &activated)
+        }
+    };
+
+    let dependencies = convert_deps(&crate_deriv.dependencies);
+    let build_dependencies = convert_deps(&crate_deriv.build_dependencies);
+    // Only emit dev-deps for workspace members — transitive deps' tests
+    // are never built, so their dev-deps would just bloat the JSON.
+    let dev_dependencies = if crate_deriv.is_root_or_workspace_member {
+        convert_deps(&crate_deriv.dev_dependencies)
+    } else {
+        vec![]
+    };
+
+    let lib_name = crate_deriv.lib.as_ref().map(|l| normalize_name(&l.name));
+    // Hashes were converted to SRI for `--hash-format sri`.
+    let source_hash = crate_deriv.source.sha256().cloned();
+    let is_sri = source_hash
+        .as_deref()
+        .is_some_and(|h| h.starts_with("sha256-"));
+
+    ResolvedCrate {
+        crate_name: crate_deriv.crate_name.clone(),
+        version: crate_deriv.version.to_string(),
+        edition: crate_deriv.edition.clone(),
+        sha256: source_hash.clone().filter(|_| !is_sri),
+        hash: source_hash.filter(|_| is_sri),
+        source: convert_source(crate_deriv, workspace_root),
+        dependencies,
+        build_dependencies,
+        dev_dependencies,
+        resolved_default_features: resolved_features.to_vec(),
+        features: if resolvable {
+            crate_deriv.features.clone()
+        } else {
+            BTreeMap::new()
+        },
+        proc_macro: crate_deriv.proc_macro,
+        build: crate_deriv.build.as_ref().and_then(|b| {
+            let s = b.src_path.to_string_lossy().to_string();
+            if s == "build.rs" {
+                None
+            } else {
+                Some(s)
+            }
+        }),
+        lib_path: crate_deriv.lib.as_ref().and_then(|l| {
+            let s = l.src_path.to_string_lossy().to_string();
+            if s == "src/lib.rs" {
+                None
+            } else {
+                Some(s)
+            }
+        }),
+        lib_name: lib_name.as_ref().and_then(|n| {
+            if *n == normalize_name(&crate_deriv.crate_name) {
+                None
+            } else {
+                Some(n.clone())
+            }
+        }),
+        crate_bin: if crate_deriv.is_root_or_workspace_member {
+            crate_deriv
+                .binaries
+                .iter()
+                .map(|b| BinTarget {
+                    name: b.name.clone(),
+                    path: b.src_path.to_string_lossy().to_string(),
+                })
+                .collect()
+        } else {
+            vec![]
+        },
+        lib_crate_types: crate_deriv.lib_crate_types.clone(),
+        links: crate_deriv.links.clone(),
+        lint_flags: crate_deriv.lint_flags.clone(),
+        rust_version: crate_deriv.rust_version.as_ref().map(|v| v.to_string()),
+        authors: crate_deriv.authors.clone(),
+        description: crate_deriv.description.clone(),
+        homepage: crate_deriv.homepage.clone(),
+        repository: crate_deriv.repository.clone(),
+        license: crate_deriv.license.clone(),
+        main_program: crate_deriv.main_program.clone(),
+        known_vulnerabilities: crate_deriv.known_vulnerabilities.clone(),
     }
 }
 
@@ -507,4 +857,262 @@ mod tests {
         );
         assert_eq!(result[1].target.as_deref(), Some("cfg(unix)"));
     }
+
+    /// Expands a resolvable crate for its own `resolvedDefaultFeatures` and
+    /// checks that this selects exactly the deps of the pre-resolved crate.
+    fn assert_resolvable_reproduces_resolved(crate_deriv: &CrateDerivation) {
+        let features = &crate_deriv.resolved_default_features;
+        let resolved = convert_crate(crate_deriv, features, "", false);
+        let resolvable = convert_crate(crate_deriv, features, "", true);
+        let activated = resolvable.activated_optional_deps(&resolvable.resolved_default_features);
+
+        let expand = |deps: &[DepInfo]| -> Vec<String> {
+            deps.iter()
+                .filter(|d| !d.optional || activated.contains(&d.effective_name()))
+                .map(|d| d.package_id.clone())
+                .collect()
+        };
+        let ids = |deps: &[DepInfo]| -> Vec<String> {
+            deps.iter().map(|d| d.package_id.clone()).collect()
+        };
+
+        assert_eq!(
+            expand(&resolvable.dependencies),
+            ids(&resolved.dependencies),
+            "dependencies of {}",
+            crate_deriv.crate_name
+        );
+        assert_eq!(
+            expand(&resolvable.build_dependencies),
+            ids(&resolved.build_dependencies),
+            "build dependencies of {}",
+            crate_deriv.crate_name
+        );
+        assert_eq!(
+            expand(&resolvable.dev_dependencies),
+            ids(&resolved.dev_dependencies),
+            "dev dependencies of {}",
+            crate_deriv.crate_name
+        );
+    }
+
+    #[test]
+    fn resolvable_expansion_reproduces_default_resolution() {
+        use crate::test;
+        use cargo_metadata::DependencyKind;
+
+        let mut env = test::MetadataEnv::default();
+        let config = test::generate_config();
+
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.update_package(|p| {
+            p.features = BTreeMap::from([
+                ("default".to_string(), vec!["json".to_string()]),
+                ("json".to_string(), vec!["dep:serde_json".to_string()]),
+                ("tls".to_string(), vec!["rustls/ring".to_string()]),
+                ("compat".to_string(), vec!["futures01".to_string()]),
+                ("tokio".to_string(), vec![]),
+            ])
+        });
+        main.update_node(|n| {
+            n.features = vec!["default".to_string(), "json".to_string()];
+        });
+        main.add_dependency("log");
+        main.add_dependency("serde_json")
+            .update_package_dep(|d| d.optional = true);
+        main.add_dependency("rustls").update_package_dep(|d| {
+            d.optional = true;
+            d.uses_default_features = false;
+        });
+        main.add_dependency("tokio")
+            .update_package_dep(|d| d.optional = true);
+        main.add_dependency("futures")
+            .update_package_dep(|d| {
+                d.optional = true;
+                d.rename = Some("futures01".to_string());
+            })
+            .update_node_dep(|n| n.name = "futures01".to_string());
+        main.add_dependency("cc").update_package_dep(|d| {
+            d.kind = DependencyKind::Build;
+            d.optional = true;
+        });
+        main.add_dependency("proptest")
+            .update_package_dep(|d| d.kind = DependencyKind::Development);
+
+        let indexed = env.indexed_metadata();
+        let crate2nix_json = crate::config::Config::default();
+        for package in indexed.pkgs_by_id.values() {
+            let crate_deriv =
+                CrateDerivation::resolve(&config, &crate2nix_json, &indexed, package).unwrap();
+            assert_resolvable_reproduces_resolved(&crate_deriv);
+        }
+
+        let root_package = indexed.root_package().expect("root package");
+        let main =
+            CrateDerivation::resolve(&config, &crate2nix_json, &indexed, root_package).unwrap();
+        let resolvable = convert_crate(&main, &main.resolved_default_features, "", true);
+        assert_eq!(resolvable.features, main.features);
+        assert_eq!(resolvable.dependencies.len(), 5);
+        let rustls = resolvable
+            .dependencies
+            .iter()
+            .find(|d| d.name == "rustls")
+            .unwrap();
+        assert!(rustls.optional);
+        assert_eq!(rustls.uses_default_features, Some(false));
+
+        // Other feature selections expand as the pre-resolved path would.
+        let deps: Vec<&ResolvedDependency> = main
+            .dependencies
+            .iter()
+            .chain(main.build_dependencies.iter())
+            .chain(main.dev_dependencies.iter())
+            .collect();
+        for features in [
+            vec![],
+            vec!["tls".to_string()],
+            vec!["compat".to_string(), "tokio".to_string()],
+        ] {
+            assert_eq!(
+                resolvable.activated_optional_deps(&features),
+                activated_optional_deps(&main.features, &deps, &features),
+                "features: {features:?}"
+            );
+        }
+
+        env.close();
+    }
+
+    #[test]
+    fn resolved_output_omits_resolvable_fields() {
+        let dep = ResolvedDependency {
+            name: "libc".to_string(),
+            rename: None,
+            package_id: cargo_metadata::PackageId {
+                repr: "libc 0.2.0".to_string(),
+            },
+            target: None,
+            optional: true,
+            uses_default_features: false,
+            features: vec!["std".to_string()],
+        };
+        let activated = HashSet::from(["libc".to_string()]);
+        let json =
+            serde_json::to_value(resolve_deps(std::slice::from_ref(&dep), &activated)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{ "name": "libc", "packageId": "libc 0.2.0" }])
+        );
+
+        let json = serde_json::to_value(resolvable_deps(std::slice::from_ref(&dep))).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "name": "libc",
+                "packageId": "libc 0.2.0",
+                "optional": true,
+                "usesDefaultFeatures": false,
+                "features": ["std"],
+            }])
+        );
+    }
+
+    #[test]
+    fn profiles_only_contain_differing_crates() {
+        use crate::test;
+
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.add_dependency("log");
+        let build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        let mut workspace = to_resolved_workspace(&build_info);
+        assert_eq!(workspace.crates["main"].dependencies.len(), 1);
+        workspace.add_profile("minimal", &build_info, &build_info.crates);
+
+        // The profile's own resolution activates serde_json.
+        let mut main = env.package_and_node("main");
+        main.update_node(|n| n.features = vec!["json".to_string()]);
+        main.add_dependency("serde_json");
+        let json_build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        workspace.add_profile("json", &build_info, &json_build_info.crates);
+
+        assert!(workspace.profiles["minimal"].crates.is_empty());
+        let json = &workspace.profiles["json"];
+        assert_eq!(
+            json.crates.keys().collect::<Vec<_>>(),
+            vec!["main", "serde_json"]
+        );
+        let main = &json.crates["main"];
+        assert_eq!(main.resolved_default_features, vec!["json"]);
+        assert_eq!(
+            main.dependencies
+                .iter()
+                .map(|d| d.package_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["log", "serde_json"]
+        );
+
+        env.close();
+    }
+
+    #[test]
+    fn git_source_is_pinned() {
+        use crate::test;
+
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.add_dependency("from_git").update_package(|p| {
+            p.source = Some(cargo_metadata::Source {
+                repr: "git+https://github.com/some/repo?branch=main#abc123".to_string(),
+            })
+        });
+
+        let mut build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        let from_git = build_info
+            .crates
+            .iter_mut()
+            .find(|c| c.crate_name == "from_git")
+            .unwrap();
+        from_git.source = from_git.source.with_sha256("0123".to_string());
+        from_git.git_subdirectory = Some("crates/from_git".to_string());
+
+        let workspace = to_resolved_workspace(&build_info);
+        let from_git = workspace
+            .crates
+            .values()
+            .find(|c| c.crate_name == "from_git")
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&from_git.source).unwrap(),
+            serde_json::json!({
+                "type": "git",
+                "url": "https://github.com/some/repo",
+                "rev": "abc123",
+                "ref": "main",
+                "sha256": "0123",
+                "submodules": true,
+                "subdir": "crates/from_git",
+            })
+        );
+
+        env.close();
+    }
 }
@@ -1,7 +1,39 @@
 //! # crate2nix
 //!
-//! Internal library for the crate2nix binary. This is not meant to be used separately, I just enjoy
-//! writing doc tests ;)
+//! The library behind the crate2nix binary.
+//!
+//! Generating a `Cargo.nix` in memory:
+//!
+//! ```no_run
+//! use crate2nix::{GenerateConfig, GenerateInfo};
+//!
+//! # fn main() -> Result<(), anyhow::Error> {
+//! let config = GenerateConfig::builder()
+//!     .cargo_toml("./Cargo.toml")
+//!     .output("./Cargo.nix")
+//!     .build()?;
+//! let artifacts = crate2nix::generate(&GenerateInfo::new(["generate"]), &config)?;
+//! println!("{}", artifacts.output.content);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`generate`] does not write any files: [`Artifacts`] contains the rendered
+//! output and the hash files with new prefetched hashes. Use
+//! [`Artifacts::write`] to write them.
+//!
+//! ## Stability
+//!
+//! [`generate`], [`Artifacts`], [`Artifact`], [`Error`], [`GenerateConfig`]
+//! with its [`GenerateConfigBuilder`], [`GenerateInfo`], [`OutputFormat`] and
+//! the [`report`] module follow semantic versioning: breaking changes to them
+//! only happen with a new major version (or minor version before 1.0). New
+//! configuration options are only added to the builder. All other modules and
+//! items are used by the binary and may change in any release.
+//!
+//! The library does not print to stdout. Progress and warnings are reported to
+//! the [`report::Reporter`] of the configuration, by default a
+//! [`report::TerminalReporter`] that prints to stderr.
 //!
 //! [Repository](https://github.com/kolloch/crate2nix)
 
@@ -10,6 +42,7 @@
 
 use std::env;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::{
     collections::{BTreeMap, HashMap, HashSet, VecDeque},
     path::Path,
@@ -17,31 +50,47 @@ use std::{
 
 use anyhow::format_err;
 use anyhow::Context;
-use anyhow::Error;
 use cargo_metadata::Metadata;
 use cargo_metadata::PackageId;
 use metadata::MergedMetadata;
 use serde::Deserialize;
 use serde::Serialize;
 
+pub use crate::error::Error;
+
+use crate::config::FeatureProfile;
 use crate::metadata::IndexedMetadata;
+use crate::report::{Event, OutputKind, Phase, Reporter};
 use crate::resolve::{CrateDerivation, ResolvedSource};
 use itertools::Itertools;
 use resolve::CratesIoSource;
 
+pub mod audit;
+pub mod changes;
 mod command;
 pub mod config;
+pub mod doctor;
+pub mod error;
+pub mod hashes;
 pub mod json_output;
+pub mod licenses;
 mod lock;
 mod metadata;
+mod metadata_cache;
+pub mod msrv;
 pub mod nix_build;
 mod prefetch;
+pub mod profiles;
 pub mod render;
+pub mod report;
 mod resolve;
+pub mod sbom;
+mod source_cache;
 pub mod sources;
 #[cfg(test)]
 pub mod test;
 pub mod util;
+pub mod watch;
 
 /// The resolved build info and the input for rendering the build.nix.tera template.
 #[derive(Debug, Deserialize, Serialize)]
@@ -50,6 +99,9 @@ pub struct BuildInfo {
     pub root_package_id: Option<PackageId>,
     /// Workspaces member package IDs by package names.
     pub workspace_members: BTreeMap<String, PackageId>,
+    /// The names of the workspace members in `workspace.default-members`, empty
+    /// if the workspace does not declare any.
+    pub default_members: Vec<String>,
     /// Registries used by the crates.
     pub registries: BTreeMap<String, String>,
     /// Build info for all crates needed for this build.
@@ -62,39 +114,375 @@ pub struct BuildInfo {
     pub config: GenerateConfig,
     /// Workspace root directory path (from cargo metadata).
     pub workspace_root: Option<String>,
+    /// The rustc flags for the `[profile.*]` settings of the workspace by profile name.
+    pub cargo_profiles: BTreeMap<String, profiles::ProfileFlags>,
+    /// The crates of each feature profile from `crate2nix.json` by profile name,
+    /// as resolved by cargo for that profile. Only resolved for JSON output.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub feature_profiles: BTreeMap<String, Vec<CrateDerivation>>,
+    /// The prefetched hashes by package ID, as written to `crate-hashes.json`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub crate_hashes: BTreeMap<PackageId, String>,
 }
 
 impl BuildInfo {
     /// Return the `NixBuildInfo` data ready for rendering the nix build file.
+    ///
+    /// Writes newly prefetched hashes to the hash files.
     pub fn for_config(info: &GenerateInfo, config: &GenerateConfig) -> Result<BuildInfo, Error> {
-        let merged = {
-            let mut metadatas = Vec::new();
-            for cargo_toml in &config.cargo_toml {
-                metadatas.push(cargo_metadata(config, cargo_toml)?);
+        let (default_nix, hash_files) = BuildInfo::resolve_and_prefetch(info, config)?;
+        for hash_file in hash_files {
+            hash_file.write()?;
+            config.reporter.report(&Event::OutputWritten {
+                kind: OutputKind::Hashes,
+                path: hash_file.path,
+            });
+        }
+        Ok(default_nix)
+    }
+
+    /// Like [`BuildInfo::for_config`] but returns the changed hash files instead
+    /// of writing them.
+    fn resolve_and_prefetch(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+    ) -> Result<(BuildInfo, Vec<Artifact>), Error> {
+        let (mut default_nix, merged) = BuildInfo::resolve(info, config)?;
+        let mut hash_files = Vec::new();
+
+        config.reporter.report(&Event::PhaseStarted {
+            phase: Phase::Prefetch,
+        });
+
+        // Crates that only feature profiles activate are prefetched with the others.
+        let main_crates = default_nix.crates.len();
+        let mut known: HashSet<PackageId> = default_nix
+            .crates
+            .iter()
+            .map(|c| c.package_id.clone())
+            .collect();
+        let profile_only = default_nix
+            .feature_profiles
+            .values()
+            .flatten()
+            .filter(|c| known.insert(c.package_id.clone()))
+            .cloned()
+            .collect_vec();
+        default_nix.crates.extend(profile_only);
+
+        hash_files.extend(prefetch_and_fill_crates_sha256(
+            config,
+            &merged,
+            &mut default_nix,
+        )?);
+
+        hash_files.extend(prefetch_and_fill_registries(config, &mut default_nix)?);
+
+        let profile_only = default_nix.crates.split_off(main_crates);
+        let sources: HashMap<PackageId, ResolvedSource> = default_nix
+            .crates
+            .iter()
+            .chain(&profile_only)
+            .map(|c| (c.package_id.clone(), c.source.clone()))
+            .collect();
+        for crate_derivation in default_nix.feature_profiles.values_mut().flatten() {
+            crate_derivation.source = sources[&crate_derivation.package_id].clone();
+        }
+
+        if config.hash_format != hashes::HashFormat::NixBase32 {
+            default_nix.convert_hashes(config.hash_format)?;
+        }
+        config.reporter.report(&Event::PhaseFinished {
+            phase: Phase::Prefetch,
+        });
+
+        Ok((default_nix, hash_files))
+    }
+
+    /// Renders the output for [`GenerateConfig::output_format`].
+    pub fn render(&self) -> Result<Artifact, Error> {
+        let reporter = &self.config.reporter;
+        reporter.report(&Event::PhaseStarted {
+            phase: Phase::Render,
+        });
+        let content = match self.config.output_format {
+            OutputFormat::Nix => render::CARGO_NIX.render(self)?,
+            OutputFormat::Json | OutputFormat::ResolvableJson => {
+                let mut resolved = if self.config.output_format == OutputFormat::ResolvableJson {
+                    json_output::to_resolvable_workspace(self)
+                } else {
+                    json_output::to_resolved_workspace(self)
+                };
+                for (name, crates) in &self.feature_profiles {
+                    resolved.add_profile(name, self, crates);
+                }
+                serde_json::to_string_pretty(&resolved)?
             }
-            metadata::MergedMetadata::merge(metadatas)?
         };
+        reporter.report(&Event::PhaseFinished {
+            phase: Phase::Render,
+        });
+        Ok(Artifact {
+            path: self.config.output.clone(),
+            content,
+        })
+    }
 
-        let indexed_metadata = IndexedMetadata::new_from_merged(&merged).map_err(|e| {
-            format_err!(
-                "while indexing metadata for {:#?}: {}",
-                config
-                    .cargo_toml
-                    .iter()
-                    .map(|p| p.to_string_lossy())
-                    .collect::<Vec<_>>(),
-                e
-            )
-        })?;
-        let mut default_nix = BuildInfo::new(info, config, indexed_metadata)?;
+    /// Converts the hashes of all sources and registries to the given format.
+    fn convert_hashes(&mut self, format: hashes::HashFormat) -> Result<(), Error> {
+        for crate_derivation in self
+            .crates
+            .iter_mut()
+            .chain(self.feature_profiles.values_mut().flatten())
+        {
+            if let Some(sha256) = crate_derivation.source.sha256() {
+                let converted = format.convert(sha256).map_err(|e| {
+                    format_err!(
+                        "while converting hash of {}: {}",
+                        crate_derivation.source,
+                        e
+                    )
+                })?;
+                crate_derivation.source = crate_derivation.source.with_sha256(converted);
+            }
+        }
+        for hash in self.registries.values_mut() {
+            *hash = format.convert(hash)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`BuildInfo::for_config`] but without filling in the hashes of the
+    /// sources. Neither prefetches anything nor touches the hash files.
+    pub fn for_config_without_hashes(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+    ) -> Result<BuildInfo, Error> {
+        Ok(BuildInfo::resolve(info, config)?.0)
+    }
+
+    fn resolve(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+    ) -> Result<(BuildInfo, MergedMetadata), Error> {
+        let reporter = &config.reporter;
+        reporter.report(&Event::PhaseStarted {
+            phase: Phase::Metadata,
+        });
+        let mut merged = merged_metadata(config)?;
+        reporter.report(&Event::PhaseFinished {
+            phase: Phase::Metadata,
+        });
 
-        default_nix.prune_unneeded_crates();
+        reporter.report(&Event::PhaseStarted {
+            phase: Phase::Resolve,
+        });
+        let mut default_nix = BuildInfo::from_merged(info, config, &merged)?;
+        default_nix.resolve_cargo_profiles()?;
+        if config.output_format != OutputFormat::Nix {
+            let crate2nix_json = config::Config::read_from_or_default(&config.crate2nix_json)?;
+            for (name, profile) in &crate2nix_json.profiles {
+                let crates = default_nix
+                    .resolve_feature_profile(profile, &mut merged)
+                    .map_err(|e| match e {
+                        Error::InvalidConfig { message } => {
+                            invalid_config(format!("in feature profile '{}': {}", name, message))
+                        }
+                        e => e,
+                    })?;
+                default_nix.feature_profiles.insert(name.clone(), crates);
+            }
+        }
+        reporter.report(&Event::PhaseFinished {
+            phase: Phase::Resolve,
+        });
 
-        prefetch_and_fill_crates_sha256(config, &merged, &mut default_nix)?;
+        Ok((default_nix, merged))
+    }
 
-        prefetch_and_fill_registries(config, &mut default_nix)?;
+    /// Builds the build info for the merged metadata of `config`, restricted to
+    /// the selected workspace members and the crates they need.
+    fn from_merged(
+        info: &GenerateInfo,
+        config: &GenerateConfig,
+        merged: &MergedMetadata,
+    ) -> Result<BuildInfo, Error> {
+        let indexed_metadata = IndexedMetadata::new_from_merged(merged, &*config.reporter)
+            .map_err(|e| Error::Metadata {
+                manifest_path: config.cargo_toml[0].clone(),
+                message: format!("while indexing metadata: {:#}", e),
+            })?;
+        let mut build_info = BuildInfo::new(info, config, indexed_metadata)?;
 
-        Ok(default_nix)
+        build_info.select_workspace_members(&config.packages, &config.exclude)?;
+        if config.no_dev_dependencies {
+            for crate_derivation in &mut build_info.crates {
+                crate_derivation.dev_dependencies.clear();
+            }
+        }
+        build_info.prune_unneeded_crates();
+        Ok(build_info)
+    }
+
+    /// Resolves the given feature profile with its own `cargo metadata` and
+    /// returns the crates that the profile needs, including the crates and
+    /// dependencies that only the profile activates.
+    ///
+    /// The package IDs are shortened consistently with the main resolution and
+    /// the packages that are new in the profile are added to `merged`, so that
+    /// their hashes can be taken from `Cargo.lock`.
+    pub(crate) fn resolve_feature_profile(
+        &mut self,
+        profile: &FeatureProfile,
+        merged: &mut MergedMetadata,
+    ) -> Result<Vec<CrateDerivation>, Error> {
+        if let Some(unknown) = profile
+            .features
+            .keys()
+            .find(|member| !self.workspace_members.contains_key(*member))
+        {
+            return Err(Error::InvalidConfig {
+                message: format!(
+                    "'{}' is not a workspace member, expected one of: {}",
+                    unknown,
+                    self.workspace_members.keys().join(", ")
+                ),
+            });
+        }
+
+        let config = GenerateConfig {
+            other_metadata_options: profile.metadata_options(),
+            ..self.config.clone()
+        };
+        let profile_merged = merged_metadata(&config)?;
+        let profile_build = BuildInfo::from_merged(&self.info, &config, &profile_merged)?;
+
+        let known: HashSet<PackageId> = merged.packages.iter().map(|p| p.id.clone()).collect();
+        merged.packages.extend(
+            profile_merged
+                .packages
+                .into_iter()
+                .filter(|p| !known.contains(&p.id)),
+        );
+
+        let id_shortener = &mut self.indexed_metadata.id_shortener;
+        let profile_shortener = &profile_build.indexed_metadata.id_shortener;
+        let mut to_main_id = |package_id: &PackageId| {
+            id_shortener.shorten_or_add(profile_shortener.lengthen_ref(package_id), package_id)
+        };
+        Ok(profile_build
+            .crates
+            .into_iter()
+            .map(|mut crate_derivation| {
+                crate_derivation.package_id = to_main_id(&crate_derivation.package_id);
+                for dependency in crate_derivation
+                    .dependencies
+                    .iter_mut()
+                    .chain(&mut crate_derivation.build_dependencies)
+                    .chain(&mut crate_derivation.dev_dependencies)
+                {
+                    dependency.package_id = to_main_id(&dependency.package_id);
+                }
+                crate_derivation
+            })
+            .collect())
+    }
+
+    /// The files which influence the result of [`BuildInfo::for_config`]: the
+    /// manifests of all local packages, `Cargo.lock` and the cargo
+    /// configuration files (whether they exist or not).
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .indexed_metadata
+            .pkgs_by_id
+            .values()
+            .filter(|package| package.source.is_none())
+            .map(|package| package.manifest_path.clone().into_std_path_buf())
+            .collect();
+        for cargo_toml in &self.config.cargo_toml {
+            let cargo_toml = cargo_toml
+                .canonicalize()
+                .unwrap_or_else(|_| cargo_toml.clone());
+            let dir = cargo_toml.parent().expect("Cargo.toml has parent");
+            files.push(dir.join("Cargo.lock"));
+            files.extend(metadata_cache::cargo_config_files(dir));
+            files.push(cargo_toml);
+        }
+        if let Some(workspace_root) = &self.indexed_metadata.workspace_root {
+            files.push(Path::new(workspace_root).join("Cargo.lock"));
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Restricts the workspace members to `packages` (all if empty) without
+    /// `exclude`. If the root crate is not selected, the only selected
+    /// (default) member becomes the root crate.
+    fn select_workspace_members(
+        &mut self,
+        packages: &[String],
+        exclude: &[String],
+    ) -> Result<(), Error> {
+        if let Some(unknown) = packages
+            .iter()
+            .chain(exclude)
+            .find(|name| !self.workspace_members.contains_key(*name))
+        {
+            return Err(invalid_config(format!(
+                "'{}' is not a workspace member, expected one of: {}",
+                unknown,
+                self.workspace_members.keys().join(", ")
+            )));
+        }
+        if packages.is_empty() && exclude.is_empty() {
+            return Ok(());
+        }
+
+        self.workspace_members.retain(|name, _| {
+            (packages.is_empty() || packages.contains(name)) && !exclude.contains(name)
+        });
+        let workspace_members = &self.workspace_members;
+        self.default_members
+            .retain(|name| workspace_members.contains_key(name));
+        let root_selected = self
+            .root_package_id
+            .as_ref()
+            .is_some_and(|root| workspace_members.values().any(|id| id == root));
+        if !root_selected {
+            self.root_package_id = match (
+                workspace_members.values().exactly_one(),
+                self.default_members.iter().exactly_one(),
+            ) {
+                (Ok(only_member), _) => Some(only_member.clone()),
+                (_, Ok(only_default)) => workspace_members.get(only_default).cloned(),
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+
+    /// Translates the `[profile.*]` settings of the workspace `Cargo.toml`.
+    fn resolve_cargo_profiles(&mut self) -> Result<(), Error> {
+        let profiles = match &self.workspace_root {
+            Some(workspace_root) => {
+                profiles::CargoProfiles::read_from(&Path::new(workspace_root).join("Cargo.toml"))?
+            }
+            None => profiles::CargoProfiles::default(),
+        };
+        if let Some(name) = profiles
+            .profile
+            .iter()
+            .find(|(_, settings)| settings.build_override.is_some())
+            .map(|(name, _)| name)
+        {
+            self.config.reporter.report(&Event::warning(format!(
+                "[profile.{}.build-override] is not supported and ignored.",
+                name
+            )));
+        }
+        self.cargo_profiles = profiles.flags(&self.crates)?;
+        Ok(())
     }
 
     fn prune_unneeded_crates(&mut self) {
@@ -132,13 +520,7 @@ impl BuildInfo {
         config: &GenerateConfig,
         metadata: IndexedMetadata,
     ) -> Result<BuildInfo, Error> {
-        let crate2nix_json = crate::config::Config::read_from_or_default(
-            &config
-                .crate_hashes_json
-                .parent()
-                .expect("crate-hashes.json has parent dir")
-                .join("crate2nix.json"),
-        )?;
+        let crate2nix_json = crate::config::Config::read_from_or_default(&config.crate2nix_json)?;
 
         Ok(BuildInfo {
             root_package_id: metadata.root.clone(),
@@ -152,6 +534,13 @@ impl BuildInfo {
                         .map(|pkg| (pkg.name.clone(), pkg_id.clone()))
                 })
                 .collect(),
+            default_members: metadata
+                .default_members
+                .iter()
+                .flat_map(|pkg_id| metadata.pkgs_by_id.get(pkg_id))
+                .map(|pkg| pkg.name.clone())
+                .sorted()
+                .collect(),
             registries: BTreeMap::new(),
             crates: metadata
                 .pkgs_by_id
@@ -159,8 +548,11 @@ impl BuildInfo {
                 .map(|package| {
                     CrateDerivation::resolve(config, &crate2nix_json, &metadata, package)
                 })
-                .collect::<Result<_, Error>>()?,
+                .collect::<Result<_, anyhow::Error>>()?,
             workspace_root: metadata.workspace_root.clone(),
+            cargo_profiles: BTreeMap::new(),
+            feature_profiles: BTreeMap::new(),
+            crate_hashes: BTreeMap::new(),
             indexed_metadata: metadata,
             info: info.clone(),
             config: config.clone(),
@@ -168,42 +560,71 @@ impl BuildInfo {
     }
 }
 
-/// Call `cargo metadata` and return result.
-fn cargo_metadata(config: &GenerateConfig, cargo_toml: &Path) -> Result<Metadata, Error> {
+/// Calls `cargo metadata` for every `Cargo.toml` of the config and merges the results.
+fn merged_metadata(config: &GenerateConfig) -> Result<MergedMetadata, Error> {
+    let cache =
+        metadata_cache::MetadataCache::in_user_cache_dir().filter(|_| config.use_metadata_cache);
+    let mut metadatas = Vec::new();
+    for cargo_toml in &config.cargo_toml {
+        metadatas.push(cargo_metadata(config, cache.as_ref(), cargo_toml)?);
+    }
+    metadata::MergedMetadata::merge(metadatas, &*config.reporter).map_err(|e| Error::Metadata {
+        manifest_path: config.cargo_toml[0].clone(),
+        message: format!("{:#}", e),
+    })
+}
+
+/// Call `cargo metadata` and return result, using `cache` if given.
+fn cargo_metadata(
+    config: &GenerateConfig,
+    cache: Option<&metadata_cache::MetadataCache>,
+    cargo_toml: &Path,
+) -> Result<Metadata, Error> {
     let mut cmd = cargo_metadata::MetadataCommand::new();
     let mut other_options = config.other_metadata_options.clone();
     other_options.push("--locked".into());
     cmd.manifest_path(cargo_toml).other_options(&*other_options);
-    cmd.exec().map_err(|e| {
-        format_err!(
-            "while retrieving metadata about {}: {}",
-            &cargo_toml.to_string_lossy(),
-            e
-        )
-    })
+    let exec = || {
+        cmd.exec().map_err(|e| {
+            anyhow::Error::from(Error::Metadata {
+                manifest_path: cargo_toml.to_path_buf(),
+                message: e.to_string(),
+            })
+        })
+    };
+    let metadata = match cache {
+        Some(cache) => cache.get_or_exec(cargo_toml, &other_options, &*config.reporter, exec)?,
+        None => exec()?,
+    };
+    Ok(metadata)
 }
 
-/// Prefetch hashes when necessary.
+/// Prefetch hashes when necessary. Returns the changed `crate-hashes.json`.
 fn prefetch_and_fill_crates_sha256(
     config: &GenerateConfig,
     merged: &MergedMetadata,
     default_nix: &mut BuildInfo,
-) -> Result<(), Error> {
+) -> Result<Option<Artifact>, Error> {
     let mut from_lock_file: HashMap<PackageId, String> =
         extract_hashes_from_lockfile(config, merged, default_nix)?;
-    for (_package_id, hash) in from_lock_file.iter_mut() {
-        let bytes =
-            hex::decode(&hash).map_err(|e| format_err!("while decoding '{}': {}", hash, e))?;
+    for (package_id, hash) in from_lock_file.iter_mut() {
+        let bytes = hex::decode(&hash).map_err(|e| {
+            format_err!(
+                "while decoding checksum '{}' of {}: {}",
+                hash,
+                package_id,
+                e
+            )
+        })?;
         *hash = nix_base32::to_nix_base32(&bytes);
     }
 
-    let prefetched = prefetch::prefetch(
+    let (prefetched, hash_file) = prefetch::prefetch(
         config,
         &from_lock_file,
         &default_nix.crates,
         &default_nix.indexed_metadata.id_shortener,
-    )
-    .map_err(|e| format_err!("while prefetching crates for calculating sha256: {}", e))?;
+    )?;
 
     for package in default_nix.crates.iter_mut() {
         if package.source.sha256().is_none() {
@@ -220,19 +641,20 @@ fn prefetch_and_fill_crates_sha256(
             }
         }
     }
+    default_nix.crate_hashes = prefetched;
 
-    Ok(())
+    Ok(hash_file)
 }
 
-/// Prefetch hashes when necessary.
+/// Prefetch hashes when necessary. Returns the changed `registry-hashes.json`.
 fn prefetch_and_fill_registries(
     config: &GenerateConfig,
     default_nix: &mut BuildInfo,
-) -> Result<(), Error> {
-    default_nix.registries = prefetch::prefetch_registries(config, &default_nix.crates)
-        .map_err(|e| format_err!("while prefetching crates for calculating sha256: {}", e))?;
+) -> Result<Option<Artifact>, Error> {
+    let (registries, hash_file) = prefetch::prefetch_registries(config, &default_nix.crates)?;
+    default_nix.registries = registries;
 
-    Ok(())
+    Ok(hash_file)
 }
 
 fn extract_hashes_from_lockfile(
@@ -248,13 +670,12 @@ fn extract_hashes_from_lockfile(
 
     for cargo_toml in &config.cargo_toml {
         let lock_file_path = cargo_toml.parent().unwrap().join("Cargo.lock");
-        let lock_file = crate::lock::EncodableResolve::load_lock_file(&lock_file_path)?;
-        lock_file
-            .get_hashes_by_package_id(merged, &mut hashes)
-            .context(format!(
-                "while parsing checksums from Lockfile {}",
-                &lock_file_path.to_string_lossy()
-            ))?;
+        crate::lock::EncodableResolve::load_lock_file(&lock_file_path)
+            .and_then(|lock_file| lock_file.get_hashes_by_package_id(merged, &mut hashes))
+            .map_err(|e| Error::Lockfile {
+                path: lock_file_path.clone(),
+                message: format!("{:#}", e),
+            })?;
     }
 
     let hashes_with_shortened_ids: HashMap<PackageId, String> = hashes
@@ -280,11 +701,11 @@ fn extract_hashes_from_lockfile(
         missing_hashes.push(format!("{} {}", package.crate_name, package.version));
     }
     if !missing_hashes.is_empty() {
-        eprintln!(
+        config.reporter.report(&Event::warning(format!(
             "Did not find all crates.io hashes in Cargo.lock. Hashes for e.g. {} are missing.\n\
              This is probably a bug.",
             missing_hashes.iter().take(10).join(", ")
-        );
+        )));
     }
     Ok(hashes_with_shortened_ids)
 }
@@ -298,17 +719,48 @@ pub struct GenerateInfo {
     pub crate2nix_arguments: Vec<String>,
 }
 
+impl GenerateInfo {
+    /// The info for this crate2nix version with the given arguments, which are
+    /// recorded in the generated files.
+    pub fn new<S: Into<String>>(arguments: impl IntoIterator<Item = S>) -> GenerateInfo {
+        GenerateInfo {
+            crate2nix_arguments: arguments.into_iter().map(Into::into).collect(),
+            ..GenerateInfo::default()
+        }
+    }
+
+    /// The info with the arguments of the running process.
+    pub fn from_env_args() -> GenerateInfo {
+        GenerateInfo::new(env::args().skip(1))
+    }
+
+    /// Reads the info recorded in a `Cargo.nix` or `Cargo.json` that was
+    /// generated by crate2nix, `None` if nothing was recorded.
+    pub fn read_from(path: &Path) -> Result<Option<GenerateInfo>, Error> {
+        let info = if path.extension() == Some("json".as_ref()) {
+            json_output::read_generate_info(path)
+        } else {
+            render::read_generate_info(path)
+        };
+        Ok(info.with_context(|| format!("while reading {}", path.to_string_lossy()))?)
+    }
+}
+
 impl Default for GenerateInfo {
+    /// The info for this crate2nix version without arguments.
     fn default() -> GenerateInfo {
         GenerateInfo {
             crate2nix_version: env!("CARGO_PKG_VERSION").to_string(),
-            crate2nix_arguments: env::args().skip(1).collect(),
+            crate2nix_arguments: Vec::new(),
         }
     }
 }
 
 /// Configuration for the default.nix generation.
+///
+/// Use [`GenerateConfig::builder`] to create one.
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[non_exhaustive]
 pub struct GenerateConfig {
     /// The path to `Cargo.toml`.
     pub cargo_toml: Vec<PathBuf>,
@@ -322,10 +774,691 @@ pub struct GenerateConfig {
     /// The path of the `registry-hashes.json` file which is used to look up hashes and/or store
     /// prefetched hashes at.
     pub registry_hashes_json: PathBuf,
+    /// The path of `crate2nix.json` with out-of-tree sources and feature profiles.
+    #[serde(default)]
+    pub crate2nix_json: PathBuf,
     /// The nix expression for the nixpkgs path to use.
     pub nixpkgs_path: String,
     /// Additional arguments to pass to `cargo metadata`.
     pub other_metadata_options: Vec<String>,
     /// Whether to read a `crate-hashes.json` file.
     pub read_crate_hashes: bool,
+    /// Whether to reuse `cargo metadata` output cached in `$XDG_CACHE_HOME/crate2nix`
+    /// if the manifests, `Cargo.lock` and cargo configuration did not change.
+    pub use_metadata_cache: bool,
+    /// The format of the hashes in the output and written hash files.
+    #[serde(default)]
+    pub hash_format: hashes::HashFormat,
+    /// The format of the generated output.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Only generate these workspace members and their dependencies. All
+    /// members if empty.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Leave out these workspace members unless they are needed by another one.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Whether to leave out all dev-dependencies.
+    #[serde(default)]
+    pub no_dev_dependencies: bool,
+    /// The prefetched hashes to use instead of reading `crate_hashes_json`.
+    #[serde(default)]
+    pub crate_hashes: Option<BTreeMap<PackageId, String>>,
+    /// Receives the progress and warnings.
+    #[serde(skip, default = "report::default_reporter")]
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl GenerateConfig {
+    /// A builder with the defaults of `crate2nix generate`.
+    pub fn builder() -> GenerateConfigBuilder {
+        GenerateConfigBuilder::default()
+    }
+}
+
+/// The format of the generated output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A `Cargo.nix` file.
+    #[default]
+    Nix,
+    /// A `Cargo.json` file with all features and platform conditions resolved
+    /// for `lib/build-from-json.nix`.
+    Json,
+    /// Like [`OutputFormat::Json`] but with the feature rules and optional
+    /// dependencies, so that features can be selected at evaluation time.
+    ResolvableJson,
+}
+
+/// Builds a validated [`GenerateConfig`].
+///
+/// ```
+/// use crate2nix::{GenerateConfig, OutputFormat};
+///
+/// let config = GenerateConfig::builder()
+///     .cargo_toml("Cargo.toml")
+///     .output_format(OutputFormat::Json)
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.output, std::path::Path::new("./Cargo.json"));
+/// assert_eq!(config.crate_hashes_json, std::path::Path::new("./crate-hashes.json"));
+///
+/// assert!(GenerateConfig::builder().cargo_toml("missing/Cargo.toml").build().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct GenerateConfigBuilder {
+    cargo_toml: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    crate_hashes_json: Option<PathBuf>,
+    registry_hashes_json: Option<PathBuf>,
+    crate2nix_json: Option<PathBuf>,
+    nixpkgs_path: String,
+    metadata_options: Vec<String>,
+    use_cargo_lock_checksums: bool,
+    read_crate_hashes: bool,
+    use_metadata_cache: bool,
+    hash_format: hashes::HashFormat,
+    output_format: OutputFormat,
+    packages: Vec<String>,
+    exclude: Vec<String>,
+    no_dev_dependencies: bool,
+    crate_hashes: Option<BTreeMap<PackageId, String>>,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl Default for GenerateConfigBuilder {
+    fn default() -> GenerateConfigBuilder {
+        GenerateConfigBuilder {
+            cargo_toml: Vec::new(),
+            output: None,
+            crate_hashes_json: None,
+            registry_hashes_json: None,
+            crate2nix_json: None,
+            nixpkgs_path: "<nixpkgs>".to_string(),
+            metadata_options: vec!["--all-features".to_string()],
+            use_cargo_lock_checksums: true,
+            read_crate_hashes: true,
+            use_metadata_cache: false,
+            hash_format: hashes::HashFormat::default(),
+            output_format: OutputFormat::default(),
+            packages: Vec::new(),
+            exclude: Vec::new(),
+            no_dev_dependencies: false,
+            crate_hashes: None,
+            reporter: report::default_reporter(),
+        }
+    }
+}
+
+impl GenerateConfigBuilder {
+    /// Adds a `Cargo.toml` to generate the build for. Defaults to `./Cargo.toml`.
+    pub fn cargo_toml(mut self, cargo_toml: impl Into<PathBuf>) -> Self {
+        self.cargo_toml.push(cargo_toml.into());
+        self
+    }
+
+    /// The path of the generated file. Defaults to `./Cargo.nix` or
+    /// `./Cargo.json`, depending on the output format.
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// The path of `crate-hashes.json`. Defaults to the directory of the output.
+    pub fn crate_hashes_json(mut self, path: impl Into<PathBuf>) -> Self {
+        self.crate_hashes_json = Some(path.into());
+        self
+    }
+
+    /// The path of `registry-hashes.json`. Defaults to the directory of the output.
+    pub fn registry_hashes_json(mut self, path: impl Into<PathBuf>) -> Self {
+        self.registry_hashes_json = Some(path.into());
+        self
+    }
+
+    /// The path of `crate2nix.json`. Defaults to the directory of the output.
+    pub fn crate2nix_json(mut self, path: impl Into<PathBuf>) -> Self {
+        self.crate2nix_json = Some(path.into());
+        self
+    }
+
+    /// The nix expression for the default nixpkgs. Defaults to `<nixpkgs>`.
+    pub fn nixpkgs_path(mut self, nixpkgs_path: impl Into<String>) -> Self {
+        self.nixpkgs_path = nixpkgs_path.into();
+        self
+    }
+
+    /// The feature options for `cargo metadata`. Defaults to `--all-features`
+    /// so that features can be selected at evaluation time.
+    pub fn metadata_options<S: Into<String>>(
+        mut self,
+        options: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.metadata_options = options.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether to use the checksums in `Cargo.lock`. Defaults to `true`.
+    pub fn use_cargo_lock_checksums(mut self, use_cargo_lock_checksums: bool) -> Self {
+        self.use_cargo_lock_checksums = use_cargo_lock_checksums;
+        self
+    }
+
+    /// Whether to read the existing hash files. Defaults to `true`.
+    pub fn read_crate_hashes(mut self, read_crate_hashes: bool) -> Self {
+        self.read_crate_hashes = read_crate_hashes;
+        self
+    }
+
+    /// Whether to reuse `cargo metadata` results cached in `$XDG_CACHE_HOME/crate2nix`.
+    /// Defaults to `false`.
+    pub fn use_metadata_cache(mut self, use_metadata_cache: bool) -> Self {
+        self.use_metadata_cache = use_metadata_cache;
+        self
+    }
+
+    /// The format of the hashes in the output. Defaults to nix-base32.
+    pub fn hash_format(mut self, hash_format: hashes::HashFormat) -> Self {
+        self.hash_format = hash_format;
+        self
+    }
+
+    /// The format of the output. Defaults to [`OutputFormat::Nix`].
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Only generates the given workspace member and the crates it needs. Can
+    /// be called multiple times. Defaults to all members.
+    pub fn package(mut self, name: impl Into<String>) -> Self {
+        self.packages.push(name.into());
+        self
+    }
+
+    /// Leaves out the given workspace member unless another selected member
+    /// needs it. Can be called multiple times.
+    pub fn exclude(mut self, name: impl Into<String>) -> Self {
+        self.exclude.push(name.into());
+        self
+    }
+
+    /// Whether to leave out all dev-dependencies, e.g. if the tests are not
+    /// built with nix. Defaults to `false`.
+    pub fn no_dev_dependencies(mut self, no_dev_dependencies: bool) -> Self {
+        self.no_dev_dependencies = no_dev_dependencies;
+        self
+    }
+
+    /// Uses these prefetched hashes by package ID instead of reading
+    /// `crate-hashes.json`, e.g. the [`BuildInfo::crate_hashes`] of a previous
+    /// generation.
+    pub fn crate_hashes(mut self, crate_hashes: BTreeMap<PackageId, String>) -> Self {
+        self.crate_hashes = Some(crate_hashes);
+        self
+    }
+
+    /// Receives the progress and warnings. Defaults to a
+    /// [`report::TerminalReporter`].
+    pub fn reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Validates the options and returns the configuration.
+    pub fn build(self) -> Result<GenerateConfig, Error> {
+        let cargo_toml = if self.cargo_toml.is_empty() {
+            vec![PathBuf::from("./Cargo.toml")]
+        } else {
+            self.cargo_toml
+        };
+        for path in &cargo_toml {
+            if !path.is_file() {
+                return Err(invalid_config(format!(
+                    "Did not find {}.",
+                    path.to_string_lossy()
+                )));
+            }
+        }
+
+        let output = self.output.unwrap_or_else(|| match self.output_format {
+            OutputFormat::Nix => "./Cargo.nix".into(),
+            OutputFormat::Json | OutputFormat::ResolvableJson => "./Cargo.json".into(),
+        });
+        let output_dir = match output.parent() {
+            Some(dir) if output.file_name().is_some() && dir.as_os_str().is_empty() => {
+                Path::new(".")
+            }
+            Some(dir) if output.file_name().is_some() => dir,
+            _ => {
+                return Err(invalid_config(format!(
+                    "The output {} is not a file path.",
+                    output.to_string_lossy()
+                )))
+            }
+        };
+        let next_to_output = |path: Option<PathBuf>, file_name: &str| {
+            path.unwrap_or_else(|| output_dir.join(file_name))
+        };
+
+        if self.nixpkgs_path.trim().is_empty() {
+            return Err(invalid_config("The nixpkgs path must not be empty."));
+        }
+        if let Some(option) = self.metadata_options.iter().find(|option| {
+            ["--manifest-path", "--format-version", "--locked"]
+                .iter()
+                .any(|reserved| option.starts_with(reserved))
+        }) {
+            return Err(invalid_config(format!(
+                "'{}' is set by crate2nix and must not be passed as a metadata option.",
+                option
+            )));
+        }
+
+        Ok(GenerateConfig {
+            cargo_toml,
+            use_cargo_lock_checksums: self.use_cargo_lock_checksums,
+            crate_hashes_json: next_to_output(self.crate_hashes_json, "crate-hashes.json"),
+            registry_hashes_json: next_to_output(self.registry_hashes_json, "registry-hashes.json"),
+            crate2nix_json: next_to_output(self.crate2nix_json, "crate2nix.json"),
+            output,
+            nixpkgs_path: self.nixpkgs_path,
+            other_metadata_options: self.metadata_options,
+            read_crate_hashes: self.read_crate_hashes,
+            use_metadata_cache: self.use_metadata_cache,
+            hash_format: self.hash_format,
+            output_format: self.output_format,
+            packages: self.packages,
+            exclude: self.exclude,
+            no_dev_dependencies: self.no_dev_dependencies,
+            crate_hashes: self.crate_hashes,
+            reporter: self.reporter,
+        })
+    }
+}
+
+fn invalid_config(message: impl Into<String>) -> Error {
+    Error::InvalidConfig {
+        message: message.into(),
+    }
+}
+
+/// A file rendered by [`generate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    /// The path that the file is written to.
+    pub path: PathBuf,
+    /// The content of the file.
+    pub content: String,
+}
+
+impl Artifact {
+    /// Writes the content to the path.
+    pub fn write(&self) -> Result<(), Error> {
+        std::fs::write(&self.path, &self.content)
+            .with_context(|| format!("while writing {}", self.path.to_string_lossy()))?;
+        Ok(())
+    }
+}
+
+/// The result of [`generate`].
+#[derive(Debug)]
+pub struct Artifacts {
+    /// The resolved build that the output was rendered from.
+    pub build_info: BuildInfo,
+    /// The generated `Cargo.nix` or `Cargo.json`.
+    pub output: Artifact,
+    /// The hash files that changed because of newly prefetched hashes.
+    pub hash_files: Vec<Artifact>,
+}
+
+impl Artifacts {
+    /// All files, the hash files first.
+    pub fn files(&self) -> impl Iterator<Item = &Artifact> {
+        self.hash_files.iter().chain(std::iter::once(&self.output))
+    }
+
+    /// Writes all files and reports them as written.
+    pub fn write(&self) -> Result<(), Error> {
+        let reporter = &self.build_info.config.reporter;
+        for hash_file in &self.hash_files {
+            hash_file.write()?;
+            reporter.report(&Event::OutputWritten {
+                kind: OutputKind::Hashes,
+                path: hash_file.path.clone(),
+            });
+        }
+        self.output.write()?;
+        reporter.report(&Event::OutputWritten {
+            kind: OutputKind::Output,
+            path: self.output.path.clone(),
+        });
+        Ok(())
+    }
+}
+
+/// Resolves the build for the given configuration, prefetches missing hashes
+/// and renders the output without writing any files.
+pub fn generate(info: &GenerateInfo, config: &GenerateConfig) -> Result<Artifacts, Error> {
+    let (build_info, hash_files) = BuildInfo::resolve_and_prefetch(info, config)?;
+    let output = build_info.render()?;
+    Ok(Artifacts {
+        build_info,
+        output,
+        hash_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn generates_artifacts_in_memory() {
+        let out_dir = TempDir::new("crate2nix_generate").unwrap();
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .output(out_dir.path().join("Cargo.nix"))
+            .crate_hashes_json("../sample_projects/bin/crate-hashes.json")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.registry_hashes_json,
+            out_dir.path().join("registry-hashes.json")
+        );
+
+        let artifacts =
+            generate(&GenerateInfo::new(["generate", "-o", "Cargo.nix"]), &config).unwrap();
+        assert!(artifacts.hash_files.is_empty());
+        assert_eq!(artifacts.output.path, out_dir.path().join("Cargo.nix"));
+        assert!(artifacts
+            .output
+            .content
+            .contains(r#"#   "generate" "-o" "Cargo.nix""#));
+        assert!(artifacts.output.content.contains("hello_world_bin"));
+        assert_eq!(std::fs::read_dir(out_dir.path()).unwrap().count(), 0);
+
+        artifacts.write().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out_dir.path().join("Cargo.nix")).unwrap(),
+            artifacts.output.content
+        );
+    }
+
+    #[test]
+    fn renders_sri_hashes_of_registry_crates() {
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.add_dependency("private")
+            .version_and_package_id("1.0.0")
+            .update_package(|p| {
+                p.source = Some(cargo_metadata::Source {
+                    repr: "sparse+https://registry.example.com/index/".to_string(),
+                })
+            });
+        let mut config = test::generate_config();
+        config.hash_format = hashes::HashFormat::Sri;
+        let mut build_info =
+            BuildInfo::new(&GenerateInfo::default(), &config, env.indexed_metadata()).unwrap();
+        let sri = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        for crate_derivation in &mut build_info.crates {
+            if crate_derivation.crate_name == "private" {
+                crate_derivation.source = crate_derivation.source.with_sha256(sri.to_string());
+            }
+        }
+
+        let output = build_info.render().unwrap().content;
+        let registry_url = output
+            .split("url = registryUrl {")
+            .nth(1)
+            .and_then(|rest| rest.split("};").next())
+            .unwrap();
+        assert!(registry_url.contains(&format!("hash = \"{}\";", sri)));
+        assert!(!output.contains(&format!("sha256 = \"{}\"", sri)));
+
+        env.close();
+    }
+
+    #[test]
+    fn uses_given_crate_hashes_instead_of_file() {
+        let out_dir = TempDir::new("crate2nix_generate").unwrap();
+        let crate_hashes_json = out_dir.path().join("crate-hashes.json");
+        std::fs::write(&crate_hashes_json, r#"{ "unused 0.1.0": "abc" }"#).unwrap();
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .output(out_dir.path().join("Cargo.nix"))
+            .crate_hashes_json(&crate_hashes_json)
+            .crate_hashes(BTreeMap::new())
+            .build()
+            .unwrap();
+
+        // Reading the file would drop the unused hash and rewrite it.
+        let artifacts = generate(&GenerateInfo::default(), &config).unwrap();
+        assert!(artifacts.hash_files.is_empty());
+        assert!(artifacts.build_info.crate_hashes.is_empty());
+    }
+
+    #[test]
+    fn records_generate_info_in_json() {
+        let out_dir = TempDir::new("crate2nix_generate").unwrap();
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .output(out_dir.path().join("Cargo.json"))
+            .crate_hashes_json("../sample_projects/bin/crate-hashes.json")
+            .output_format(OutputFormat::Json)
+            .build()
+            .unwrap();
+        let info = GenerateInfo::new(["generate", "--format", "json", "-o", "Cargo.json"]);
+        generate(&info, &config).unwrap().output.write().unwrap();
+
+        let recorded = GenerateInfo::read_from(&config.output).unwrap().unwrap();
+        assert_eq!(recorded.crate2nix_version, info.crate2nix_version);
+        assert_eq!(recorded.crate2nix_arguments, info.crate2nix_arguments);
+    }
+
+    #[test]
+    fn selects_workspace_members() {
+        let build_info = |configure: fn(GenerateConfigBuilder) -> GenerateConfigBuilder| {
+            let config = configure(
+                GenerateConfig::builder()
+                    .cargo_toml("../sample_projects/workspace_with_nondefault_lib/Cargo.toml"),
+            )
+            .build()
+            .unwrap();
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config)
+        };
+        let names = |build_info: &BuildInfo| {
+            (
+                build_info.workspace_members.keys().cloned().collect_vec(),
+                build_info
+                    .crates
+                    .iter()
+                    .map(|c| c.crate_name.clone())
+                    .sorted()
+                    .collect_vec(),
+            )
+        };
+
+        let selected = build_info(|b| b.package("main")).unwrap();
+        assert_eq!(
+            names(&selected),
+            (vec!["main".into()], vec!["main".into(), "somelib".into()])
+        );
+        let excluded = build_info(|b| b.exclude("main")).unwrap();
+        assert_eq!(
+            names(&excluded),
+            (vec!["somelib".into()], vec!["somelib".into()])
+        );
+        let unknown = build_info(|b| b.package("other")).unwrap_err();
+        assert_eq!(unknown.exit_code(), 16);
+    }
+
+    #[test]
+    fn uses_single_default_member_as_root() {
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/workspace_with_default_members/Cargo.toml")
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        assert_eq!(build_info.default_members, vec!["app".to_string()]);
+        assert_eq!(
+            build_info
+                .root_package_id
+                .as_ref()
+                .map(|id| id.repr.as_str()),
+            Some("app")
+        );
+        assert_eq!(
+            crate::json_output::to_resolved_workspace(&build_info).default_members,
+            vec!["app".to_string()]
+        );
+
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/workspace_with_default_members/Cargo.toml")
+            .package("tool")
+            .package("util")
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        assert!(build_info.default_members.is_empty());
+        assert_eq!(build_info.root_package_id, None);
+    }
+
+    #[test]
+    fn ignores_implicit_default_members_of_root_package() {
+        let workspace = TempDir::new("crate2nix_root_package").unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = workspace.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "Cargo.toml",
+            "[package]\nname = \"main\"\nversion = \"0.1.0\"\n\n[workspace]\nmembers = [\"member\"]\n",
+        );
+        write("src/main.rs", "fn main() {}\n");
+        write(
+            "member/Cargo.toml",
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\n",
+        );
+        write("member/src/lib.rs", "");
+        write(
+            "Cargo.lock",
+            "version = 3\n\n[[package]]\nname = \"main\"\nversion = \"0.1.0\"\n\n\
+             [[package]]\nname = \"member\"\nversion = \"0.1.0\"\n",
+        );
+
+        let config = GenerateConfig::builder()
+            .cargo_toml(workspace.path().join("Cargo.toml"))
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        assert_eq!(build_info.workspace_members.len(), 2);
+        assert!(build_info.default_members.is_empty());
+        assert_eq!(build_info.root_package_id, None);
+    }
+
+    #[test]
+    fn translates_cargo_profiles() {
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/cargo_profiles/Cargo.toml")
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        assert_eq!(
+            build_info.cargo_profiles.keys().collect_vec(),
+            vec!["dev", "release", "small"]
+        );
+        let release = &build_info.cargo_profiles["release"];
+        assert!(release.release);
+        assert!(release
+            .rustc_flags
+            .contains(&"-C codegen-units=1".to_string()));
+        assert!(!release.rustc_flags.contains(&"-C lto=fat".to_string()));
+        assert_eq!(
+            release
+                .crates
+                .iter()
+                .map(|(id, flags)| (id.repr.as_str(), flags[0].as_str()))
+                .collect_vec(),
+            vec![
+                ("cargo_profiles", "-C opt-level=3"),
+                ("greeting", "-C opt-level=3"),
+                ("helper", "-C opt-level=z")
+            ]
+        );
+        // Only the binary is linked with LTO, the proc-macro always unwinds.
+        assert!(release.crates[&PackageId {
+            repr: "cargo_profiles".to_string()
+        }]
+            .contains(&"-C lto=fat".to_string()));
+        assert!(!release.crates[&PackageId {
+            repr: "greeting".to_string()
+        }]
+            .contains(&"-C panic=abort".to_string()));
+        assert_eq!(
+            build_info.cargo_profiles["small"].rustc_flags[0],
+            "-C opt-level=s"
+        );
+    }
+
+    #[test]
+    fn translates_lints_of_workspace_members() {
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/workspace_lints/Cargo.toml")
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        let lint_flags = |name: &str| {
+            build_info
+                .crates
+                .iter()
+                .find(|c| c.crate_name == name)
+                .map(|c| c.lint_flags.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            lint_flags("app"),
+            vec!["-D unused", "-D clippy::enum_glob_use", "-F unsafe_code"]
+        );
+        assert_eq!(lint_flags("util"), vec!["-W missing_docs"]);
+    }
+
+    #[test]
+    fn leaves_out_dev_dependencies() {
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/renamed_dev_deps/Cargo.toml")
+            .no_dev_dependencies(true)
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        assert_eq!(
+            build_info
+                .crates
+                .iter()
+                .map(|c| c.crate_name.as_str())
+                .collect_vec(),
+            vec!["renamed_dev_deps"]
+        );
+    }
+
+    #[test]
+    fn builder_rejects_reserved_metadata_options() {
+        let error = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/bin/Cargo.toml")
+            .metadata_options(["--locked"])
+            .build()
+            .unwrap_err();
+        assert!(error.to_string().contains("--locked"), "{}", error);
+    }
 }
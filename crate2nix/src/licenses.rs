@@ -0,0 +1,622 @@
+//! Checking the licenses of all crates in a build against a policy.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{bail, format_err, Error};
+use cargo_metadata::PackageId;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::config::LicensePolicy;
+use crate::json_output::to_resolved_workspace;
+use crate::BuildInfo;
+
+/// A parsed SPDX license expression, see
+/// <https://spdx.github.io/spdx-spec/v2.3/SPDX-license-expressions/>.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseExpr {
+    /// A license identifier, e.g. "MIT" or "GPL-2.0+".
+    License(String),
+    /// A license with an exception, e.g. "Apache-2.0 WITH LLVM-exception".
+    With(String, String),
+    /// All of the licenses apply.
+    And(Box<LicenseExpr>, Box<LicenseExpr>),
+    /// Any of the licenses can be chosen.
+    Or(Box<LicenseExpr>, Box<LicenseExpr>),
+}
+
+impl LicenseExpr {
+    /// Parses an SPDX license expression.
+    ///
+    /// Also accepts lower case operators and the deprecated "MIT/Apache-2.0"
+    /// syntax that is still common on crates.io.
+    ///
+    /// ```
+    /// use crate2nix::licenses::LicenseExpr;
+    /// let expr = LicenseExpr::parse("(MIT OR Apache-2.0) AND Unicode-DFS-2016").unwrap();
+    /// assert_eq!(expr.to_string(), "(MIT OR Apache-2.0) AND Unicode-DFS-2016");
+    /// assert_eq!(LicenseExpr::parse("MIT/Apache-2.0").unwrap().to_string(), "MIT OR Apache-2.0");
+    /// assert!(LicenseExpr::parse("MIT OR").is_err());
+    /// ```
+    pub fn parse(expression: &str) -> Result<LicenseExpr, Error> {
+        let tokens = tokenize(expression);
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let expr = parser
+            .or_expr()
+            .map_err(|e| format_err!("while parsing license '{}': {}", expression, e))?;
+        if let Some(token) = parser.peek() {
+            bail!(
+                "while parsing license '{}': unexpected '{}'",
+                expression,
+                token
+            );
+        }
+        Ok(expr)
+    }
+
+    /// Whether the licenses can be used under the policy: for "OR", one of the
+    /// alternatives must be allowed, for "AND", all licenses must be allowed.
+    pub fn is_allowed(&self, policy: &LicensePolicy) -> bool {
+        match self {
+            LicenseExpr::License(id) => policy.allows(id),
+            LicenseExpr::With(id, exception) => policy.allows_with(id, exception),
+            LicenseExpr::And(left, right) => left.is_allowed(policy) && right.is_allowed(policy),
+            LicenseExpr::Or(left, right) => left.is_allowed(policy) || right.is_allowed(policy),
+        }
+    }
+}
+
+impl Display for LicenseExpr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Parenthesize sub expressions with a weaker operator.
+        let fmt_operand = |f: &mut Formatter, operand: &LicenseExpr| match (self, operand) {
+            (LicenseExpr::And(..), LicenseExpr::Or(..)) => write!(f, "({})", operand),
+            _ => write!(f, "{}", operand),
+        };
+        match self {
+            LicenseExpr::License(id) => write!(f, "{}", id),
+            LicenseExpr::With(id, exception) => write!(f, "{} WITH {}", id, exception),
+            LicenseExpr::And(left, right) => {
+                fmt_operand(f, left)?;
+                write!(f, " AND ")?;
+                fmt_operand(f, right)
+            }
+            LicenseExpr::Or(left, right) => write!(f, "{} OR {}", left, right),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Vec<String> {
+    expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .replace('/', " OR ")
+        .split_whitespace()
+        .map(|token| match token {
+            "and" | "or" | "with" => token.to_uppercase(),
+            _ => token.to_string(),
+        })
+        .collect()
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<&str, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| format_err!("unexpected end"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or_expr(&mut self) -> Result<LicenseExpr, Error> {
+        let mut expr = self.and_expr()?;
+        while self.peek() == Some("OR") {
+            self.pos += 1;
+            expr = LicenseExpr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<LicenseExpr, Error> {
+        let mut expr = self.with_expr()?;
+        while self.peek() == Some("AND") {
+            self.pos += 1;
+            expr = LicenseExpr::And(Box::new(expr), Box::new(self.with_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn with_expr(&mut self) -> Result<LicenseExpr, Error> {
+        let expr = self.primary()?;
+        if self.peek() != Some("WITH") {
+            return Ok(expr);
+        }
+        self.pos += 1;
+        match expr {
+            LicenseExpr::License(id) => {
+                let exception = self.identifier()?;
+                Ok(LicenseExpr::With(id, exception))
+            }
+            _ => bail!("WITH is only allowed after a license identifier"),
+        }
+    }
+
+    fn primary(&mut self) -> Result<LicenseExpr, Error> {
+        if self.peek() == Some("(") {
+            self.pos += 1;
+            let expr = self.or_expr()?;
+            if self.next()? != ")" {
+                bail!("expected ')'");
+            }
+            return Ok(expr);
+        }
+        Ok(LicenseExpr::License(self.identifier()?))
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        let token = self.next()?;
+        if ["(", ")", "AND", "OR", "WITH"].contains(&token) {
+            bail!("expected license identifier instead of '{}'", token);
+        }
+        Ok(token.to_string())
+    }
+}
+
+impl LicenseExpr {
+    /// The license and exception identifiers in order of appearance, without
+    /// duplicates.
+    pub fn ids(&self) -> Vec<&str> {
+        let mut ids = Vec::new();
+        self.collect_ids(&mut ids);
+        ids.into_iter().unique().collect()
+    }
+
+    fn collect_ids<'a>(&'a self, ids: &mut Vec<&'a str>) {
+        match self {
+            LicenseExpr::License(id) => ids.push(id),
+            LicenseExpr::With(id, exception) => {
+                ids.push(id);
+                ids.push(exception);
+            }
+            LicenseExpr::And(left, right) | LicenseExpr::Or(left, right) => {
+                left.collect_ids(ids);
+                right.collect_ids(ids);
+            }
+        }
+    }
+}
+
+/// A license for the `meta.license` attribute of a derivation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NixLicense {
+    /// The attribute name in nixpkgs' `lib.licenses`, if known.
+    pub attr: Option<String>,
+    /// The SPDX identifier.
+    pub spdx_id: String,
+}
+
+impl NixLicense {
+    /// All licenses mentioned in an SPDX expression. Invalid expressions are
+    /// kept as a single unknown license.
+    ///
+    /// ```
+    /// use crate2nix::licenses::NixLicense;
+    /// let licenses = NixLicense::from_expression("MIT OR Apache-2.0 WITH Foo-exception");
+    /// let attrs: Vec<_> = licenses.iter().map(|l| l.attr.as_deref()).collect();
+    /// assert_eq!(attrs, vec![Some("mit"), Some("asl20"), None]);
+    /// ```
+    pub fn from_expression(expression: &str) -> Vec<NixLicense> {
+        let ids = match LicenseExpr::parse(expression) {
+            Ok(expr) => expr.ids().into_iter().map(str::to_string).collect(),
+            Err(_) => vec![expression.trim().to_string()],
+        };
+        ids.into_iter()
+            .map(|spdx_id| NixLicense {
+                attr: nixpkgs_license_attr(&spdx_id).map(str::to_string),
+                spdx_id,
+            })
+            .collect()
+    }
+}
+
+/// The attribute name in nixpkgs' `lib.licenses` for common SPDX identifiers.
+fn nixpkgs_license_attr(spdx_id: &str) -> Option<&'static str> {
+    Some(match spdx_id {
+        "0BSD" => "bsd0",
+        "AGPL-3.0-only" => "agpl3Only",
+        "AGPL-3.0-or-later" => "agpl3Plus",
+        "Apache-2.0" => "asl20",
+        "Artistic-2.0" => "artistic2",
+        "BSD-1-Clause" => "bsd1",
+        "BSD-2-Clause" => "bsd2",
+        "BSD-2-Clause-Patent" => "bsd2Patent",
+        "BSD-3-Clause" => "bsd3",
+        "BSL-1.0" => "boost",
+        "CC-BY-4.0" => "cc-by-40",
+        "CC-BY-SA-4.0" => "cc-by-sa-40",
+        "CC0-1.0" => "cc0",
+        "EPL-2.0" => "epl20",
+        "GPL-2.0" | "GPL-2.0-only" => "gpl2Only",
+        "GPL-2.0+" | "GPL-2.0-or-later" => "gpl2Plus",
+        "GPL-3.0" | "GPL-3.0-only" => "gpl3Only",
+        "GPL-3.0+" | "GPL-3.0-or-later" => "gpl3Plus",
+        "ISC" => "isc",
+        "LGPL-2.1" | "LGPL-2.1-only" => "lgpl21Only",
+        "LGPL-2.1+" | "LGPL-2.1-or-later" => "lgpl21Plus",
+        "LGPL-3.0" | "LGPL-3.0-only" => "lgpl3Only",
+        "LGPL-3.0+" | "LGPL-3.0-or-later" => "lgpl3Plus",
+        "LLVM-exception" => "llvm-exception",
+        "MIT" => "mit",
+        "MIT-0" => "mit0",
+        "MPL-2.0" => "mpl20",
+        "NCSA" => "ncsa",
+        "OpenSSL" => "openssl",
+        "Unicode-3.0" => "unicode-30",
+        "Unicode-DFS-2016" => "unicode-dfs-2016",
+        "Unlicense" => "unlicense",
+        "WTFPL" => "wtfpl",
+        "Zlib" => "zlib",
+        _ => return None,
+    })
+}
+
+impl LicensePolicy {
+    /// Whether the given license identifier is allowed on its own.
+    fn allows(&self, id: &str) -> bool {
+        !matches_any(&self.deny, id, None)
+            && (self.allow.is_empty() || matches_any(&self.allow, id, None))
+    }
+
+    /// Whether the license with the exception is allowed. A denied license
+    /// stays denied, but "license WITH exception" can be allowed on its own.
+    fn allows_with(&self, id: &str, exception: &str) -> bool {
+        !matches_any(&self.deny, id, None)
+            && !matches_any(&self.deny, id, Some(exception))
+            && (self.allow.is_empty()
+                || matches_any(&self.allow, id, None)
+                || matches_any(&self.allow, id, Some(exception)))
+    }
+}
+
+/// Whether one of the policy entries matches the license identifier with the
+/// optional exception.
+///
+/// An entry without "-only" or "-or-later" matches all variants, e.g. "GPL-3.0"
+/// matches "GPL-3.0-only", "GPL-3.0-or-later" and "GPL-3.0+". The deprecated
+/// identifiers without suffix mean "-only" and "+" means "-or-later".
+fn matches_any(entries: &[String], id: &str, exception: Option<&str>) -> bool {
+    let (base, variant) = split_variant(id);
+    entries.iter().any(|entry| {
+        let (entry_id, entry_exception) = match entry.split_once(" WITH ") {
+            Some((entry_id, entry_exception)) => (entry_id.trim(), Some(entry_exception.trim())),
+            None => (entry.as_str(), None),
+        };
+        let (entry_base, entry_variant) = split_variant(entry_id);
+        entry_exception == exception
+            && entry_base == base
+            && (entry_variant.is_none() || entry_variant == variant.or(Some("-only")))
+    })
+}
+
+/// Splits off the "-only" or "-or-later" suffix of a license identifier.
+fn split_variant(id: &str) -> (&str, Option<&'static str>) {
+    if let Some(base) = id.strip_suffix('+') {
+        (base, Some("-or-later"))
+    } else if let Some(base) = id.strip_suffix("-or-later") {
+        (base, Some("-or-later"))
+    } else if let Some(base) = id.strip_suffix("-only") {
+        (base, Some("-only"))
+    } else {
+        (id, None)
+    }
+}
+
+/// The licenses of all crates needed to build each workspace member.
+#[derive(Debug, Serialize)]
+pub struct LicenseReport {
+    /// The crates in the build closure by workspace member name.
+    pub members: BTreeMap<String, Vec<CrateLicense>>,
+}
+
+/// The license information of a crate.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrateLicense {
+    /// The crate name.
+    pub name: String,
+    /// The crate version.
+    pub version: String,
+    /// The SPDX license expression from `Cargo.toml`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The license file from `Cargo.toml` for non-standard licenses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license_file: Option<String>,
+    /// The policy check result.
+    pub status: LicenseStatus,
+}
+
+/// The result of checking a crate against the license policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LicenseStatus {
+    /// No policy was configured.
+    Unchecked,
+    /// The license is allowed or the crate is an exception.
+    Allowed,
+    /// The license is not allowed by the policy.
+    Denied,
+    /// The license expression could not be parsed.
+    Invalid,
+    /// The crate has no SPDX license but maybe a license file.
+    Unknown,
+}
+
+impl LicenseReport {
+    /// Collects the licenses of all crates needed to build each workspace member
+    /// and checks them against the policy, if any.
+    pub fn new(build_info: &BuildInfo, policy: Option<&LicensePolicy>) -> LicenseReport {
+        let workspace = to_resolved_workspace(build_info);
+        let members = workspace
+            .workspace_members
+            .iter()
+            .map(|(member, package_id)| {
+                let crates = workspace
+                    .closure_of([package_id.as_str()])
+                    .into_iter()
+                    .map(|package_id| {
+                        let resolved = &workspace.crates[package_id];
+                        let package = build_info.indexed_metadata.pkgs_by_id.get(&PackageId {
+                            repr: package_id.to_string(),
+                        });
+                        let license = package.and_then(|p| p.license.clone());
+                        let status = match (policy, &license) {
+                            (None, _) => LicenseStatus::Unchecked,
+                            (Some(policy), _)
+                                if policy.exceptions.contains(&resolved.crate_name) =>
+                            {
+                                LicenseStatus::Allowed
+                            }
+                            (Some(_), None) => LicenseStatus::Unknown,
+                            (Some(policy), Some(license)) => match LicenseExpr::parse(license) {
+                                Ok(expr) if expr.is_allowed(policy) => LicenseStatus::Allowed,
+                                Ok(_) => LicenseStatus::Denied,
+                                Err(_) => LicenseStatus::Invalid,
+                            },
+                        };
+                        CrateLicense {
+                            name: resolved.crate_name.clone(),
+                            version: resolved.version.clone(),
+                            license,
+                            license_file: package
+                                .and_then(|p| p.license_file.as_ref())
+                                .map(|f| f.to_string()),
+                            status,
+                        }
+                    })
+                    .collect();
+                (member.clone(), crates)
+            })
+            .collect();
+        LicenseReport { members }
+    }
+
+    /// The crates that violate the policy with the members that depend on them.
+    pub fn violations(&self) -> BTreeMap<(&str, &str), (&CrateLicense, BTreeSet<&str>)> {
+        let mut violations: BTreeMap<(&str, &str), (&CrateLicense, BTreeSet<&str>)> =
+            BTreeMap::new();
+        for (member, crates) in &self.members {
+            for c in crates {
+                if matches!(c.status, LicenseStatus::Allowed | LicenseStatus::Unchecked) {
+                    continue;
+                }
+                violations
+                    .entry((&c.name, &c.version))
+                    .or_insert_with(|| (c, BTreeSet::new()))
+                    .1
+                    .insert(member);
+            }
+        }
+        violations
+    }
+
+    /// Fails with a description of all violations, if any.
+    pub fn check(&self) -> Result<(), Error> {
+        let violations = self.violations();
+        if violations.is_empty() {
+            return Ok(());
+        }
+        bail!(
+            "{} crates violate the license policy:\n{}",
+            violations.len(),
+            violations
+                .values()
+                .map(|(c, members)| format!(
+                    "  {} {}: {} (used by {})",
+                    c.name,
+                    c.version,
+                    c.describe(),
+                    members.iter().join(", ")
+                ))
+                .join("\n")
+        )
+    }
+}
+
+impl CrateLicense {
+    fn describe(&self) -> String {
+        let license = match (&self.license, &self.license_file) {
+            (Some(license), _) => license.clone(),
+            (None, Some(file)) => format!("license file {}", file),
+            (None, None) => "no license".to_string(),
+        };
+        match self.status {
+            LicenseStatus::Invalid => format!("{} [invalid expression]", license),
+            LicenseStatus::Unknown => format!("{} [not SPDX]", license),
+            _ => license,
+        }
+    }
+}
+
+impl Display for LicenseReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (member, crates) in &self.members {
+            writeln!(f, "{} ({} crates):", member, crates.len())?;
+            let by_license = crates.iter().into_group_map_by(|c| c.describe());
+            for (license, crates) in by_license.iter().sorted_by_key(|(license, _)| *license) {
+                writeln!(
+                    f,
+                    "  {}: {}",
+                    license,
+                    crates
+                        .iter()
+                        .map(|c| format!("{} {}", c.name, c.version))
+                        .join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> LicensePolicy {
+        LicensePolicy {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+            ..LicensePolicy::default()
+        }
+    }
+
+    fn allowed(expression: &str, policy: &LicensePolicy) -> bool {
+        LicenseExpr::parse(expression).unwrap().is_allowed(policy)
+    }
+
+    #[test]
+    fn parses_precedence_and_exceptions() {
+        assert_eq!(
+            LicenseExpr::parse("MIT or Apache-2.0 WITH LLVM-exception AND BSD-3-Clause")
+                .unwrap()
+                .to_string(),
+            "MIT OR Apache-2.0 WITH LLVM-exception AND BSD-3-Clause"
+        );
+        assert_eq!(
+            LicenseExpr::parse("(MIT OR Apache-2.0) AND (ISC)").unwrap(),
+            LicenseExpr::And(
+                Box::new(LicenseExpr::Or(
+                    Box::new(LicenseExpr::License("MIT".to_string())),
+                    Box::new(LicenseExpr::License("Apache-2.0".to_string())),
+                )),
+                Box::new(LicenseExpr::License("ISC".to_string())),
+            )
+        );
+        assert!(LicenseExpr::parse("(MIT").is_err());
+        assert!(LicenseExpr::parse("MIT Apache-2.0").is_err());
+        assert!(LicenseExpr::parse("(MIT OR ISC) WITH LLVM-exception").is_err());
+    }
+
+    #[test]
+    fn evaluates_policies() {
+        let permissive = policy(&["MIT", "Apache-2.0"], &[]);
+        assert!(allowed("MIT", &permissive));
+        assert!(allowed("GPL-3.0 OR MIT", &permissive));
+        assert!(!allowed("GPL-3.0 AND MIT", &permissive));
+        assert!(allowed("Apache-2.0 WITH LLVM-exception", &permissive));
+
+        let no_gpl = policy(&[], &["GPL-2.0", "GPL-3.0"]);
+        assert!(allowed("MIT", &no_gpl));
+        assert!(!allowed("GPL-2.0+", &no_gpl));
+        assert!(allowed("GPL-3.0 OR ISC", &no_gpl));
+    }
+
+    #[test]
+    fn denied_license_stays_denied_with_exception() {
+        let no_apache = policy(&[], &["Apache-2.0"]);
+        assert!(!allowed("Apache-2.0 WITH LLVM-exception", &no_apache));
+        assert!(allowed("MIT", &no_apache));
+
+        let with_exception = policy(&["Apache-2.0 WITH LLVM-exception"], &[]);
+        assert!(allowed("Apache-2.0 WITH LLVM-exception", &with_exception));
+        assert!(!allowed("Apache-2.0", &with_exception));
+
+        let no_exception = policy(&[], &["GPL-2.0 WITH Classpath-exception-2.0"]);
+        assert!(!allowed(
+            "GPL-2.0 WITH Classpath-exception-2.0",
+            &no_exception
+        ));
+        assert!(allowed("GPL-2.0", &no_exception));
+    }
+
+    #[test]
+    fn matches_only_and_or_later_variants() {
+        let no_gpl = policy(&[], &["GPL-3.0"]);
+        assert!(!allowed("GPL-3.0-only", &no_gpl));
+        assert!(!allowed("GPL-3.0-or-later", &no_gpl));
+        assert!(!allowed("GPL-3.0+", &no_gpl));
+        assert!(allowed("LGPL-3.0-only", &no_gpl));
+
+        let no_gpl_only = policy(&[], &["GPL-3.0-only"]);
+        assert!(!allowed("GPL-3.0", &no_gpl_only));
+        assert!(allowed("GPL-3.0-or-later", &no_gpl_only));
+
+        let gpl_or_later = policy(&["GPL-2.0-or-later"], &[]);
+        assert!(allowed("GPL-2.0+", &gpl_or_later));
+        assert!(!allowed("GPL-2.0-only", &gpl_or_later));
+    }
+
+    #[test]
+    fn reports_violations_per_member() {
+        use crate::test;
+
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.update_package(|p| p.license = Some("MIT".to_string()));
+        main.add_dependency("gpl")
+            .update_package(|p| p.license = Some("GPL-3.0-only".to_string()));
+        main.add_dependency("custom")
+            .update_package(|p| p.license_file = Some("LICENSE.txt".into()));
+        main.add_dependency("excepted")
+            .update_package(|p| p.license = Some("GPL-3.0-only".to_string()));
+
+        let build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+        let policy = LicensePolicy {
+            exceptions: vec!["excepted".to_string()],
+            ..policy(&["MIT"], &[])
+        };
+
+        let report = LicenseReport::new(&build_info, Some(&policy));
+
+        assert_eq!(
+            report.check().unwrap_err().to_string(),
+            "2 crates violate the license policy:\n  \
+             custom 0.1.0: license file LICENSE.txt [not SPDX] (used by main)\n  \
+             gpl 0.1.0: GPL-3.0-only (used by main)"
+        );
+        assert!(LicenseReport::new(&build_info, None).check().is_ok());
+
+        env.close();
+    }
+}
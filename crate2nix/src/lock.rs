@@ -27,6 +27,11 @@ impl EncodableResolve {
         Ok(v)
     }
 
+    /// All packages in the lock file.
+    pub fn packages(&self) -> &[EncodableDependency] {
+        &self.package
+    }
+
     pub fn get_hashes_by_package_id(
         &self,
         metadata: &MergedMetadata,
@@ -96,10 +101,10 @@ pub struct EncodableResolve {
 
 #[derive(Serialize, Deserialize, Debug, PartialOrd, Ord, PartialEq, Eq)]
 pub struct EncodableDependency {
-    name: String,
-    version: String,
-    source: Option<String>,
-    checksum: Option<String>,
+    pub name: String,
+    pub version: String,
+    pub source: Option<String>,
+    pub checksum: Option<String>,
     dependencies: Option<Vec<EncodablePackageId>>,
     replace: Option<EncodablePackageId>,
 }
@@ -1,20 +1,27 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use structopt::clap::ArgGroup;
 use structopt::StructOpt;
 
 use anyhow::format_err;
 use anyhow::{bail, Error};
+use cargo_metadata::PackageId;
 use crate2nix::{
     config::{Config, NixFile},
-    render,
+    report::{Event, JsonReporter, Reporter, TerminalReporter},
+    OutputFormat,
 };
+use itertools::Itertools;
 use semver::Version;
 use serde::Deserialize;
 use serde::Serialize;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 const DEFAULT_OUTPUT: &str = "./Cargo.nix";
 const DEFAULT_JSON_OUTPUT: &str = "./Cargo.json";
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Debug, StructOpt, Deserialize, Serialize)]
 #[structopt(
@@ -26,109 +33,148 @@ pub enum Opt {
         name = "generate",
         about = "Generates a Cargo.nix file from a cargo rust project."
     )]
-    Generate {
-        #[structopt(
-            short = "c",
-            long = "config",
-            parse(from_os_str),
-            help = "The path to the crate2nix.json file (same directory as Cargo.nix ...).",
-            default_value = "./crate2nix.json"
-        )]
-        crate2nix_json: PathBuf,
-
-        #[structopt(
-            short = "f",
-            long = "cargo-toml",
-            parse(from_os_str),
-            help = "The path to the Cargo.toml of the project."
-        )]
-        cargo_toml: Vec<PathBuf>,
+    Generate(GenerateOpt),
 
-        #[structopt(
-            long = "all-features",
-            help = "Resolve project dependencies with all features enabled. \
-                    This is the default and does not need to be specified. \
-                    Users can choose their sub set of features and evaluation time so \
-                    that one generated build file can be used for different feature selections."
-        )]
-        all_features: bool,
-
-        #[structopt(
-            long = "default-features",
-            help = "Enables the default default features \
-                    (instead of all features as is the default). \
-                    Often combined with --features to add selected features on top."
-        )]
-        default_features: bool,
+    #[structopt(
+        name = "sbom",
+        about = "Generates a software bill of materials (SBOM) for the crates needed to build \
+                 the root crate or a workspace member."
+    )]
+    Sbom {
+        #[structopt(flatten)]
+        project: ProjectOpt,
 
         #[structopt(
-            long = "no-default-features",
-            help = "Disables all features. \
-                    Often combined with --features to reenable selected features."
+            long = "format",
+            help = "The SBOM format: 'cyclonedx-json' or 'spdx-json'.",
+            default_value = "cyclonedx-json"
         )]
-        no_default_features: bool,
+        format: String,
 
         #[structopt(
-            long = "features",
-            help = "Resolve project dependencies additionally with these features enabled. \
-                    By default, all features are resolved."
+            long = "member",
+            help = "The workspace member to generate the SBOM for. \
+                    Uses the root crate or all workspace members by default."
         )]
-        features: Vec<String>,
+        member: Option<String>,
 
         #[structopt(
             short = "o",
             long = "output",
-            help = "The path of the output.nix file. Uses ./Cargo.nix by default."
+            parse(from_os_str),
+            help = "The path of the SBOM file. Prints to stdout by default."
         )]
         output: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "audit",
+        about = "Matches the crates needed to build each workspace member against \
+                 a local checkout of the RustSec advisory database."
+    )]
+    Audit {
+        #[structopt(flatten)]
+        project: ProjectOpt,
 
         #[structopt(
-            short = "n",
-            long = "nixpkgs-path",
-            help = "The default path for the nixpkgs to use.",
-            default_value = "<nixpkgs>"
+            long = "advisory-db",
+            parse(from_os_str),
+            help = "The path to a checkout of https://github.com/rustsec/advisory-db."
         )]
-        nixpkgs_path: String,
+        advisory_db: PathBuf,
 
         #[structopt(
-            short = "h",
-            long = "crate-hashes",
-            parse(from_os_str),
-            help = "The path to the crate hash cache file. \
-                    Uses 'crate-hashes.json' in the same directory as the Cargo.nix output by default."
+            long = "member",
+            help = "Only report the crates needed by this workspace member."
         )]
-        crate_hashes: Option<PathBuf>,
+        member: Option<String>,
+
+        #[structopt(long = "json", help = "Print the report as JSON.")]
+        json: bool,
+    },
+
+    #[structopt(
+        name = "licenses",
+        about = "Lists the licenses of the crates needed to build each workspace member \
+                 and checks them against the license policy in crate2nix.json."
+    )]
+    Licenses {
+        #[structopt(flatten)]
+        project: ProjectOpt,
 
         #[structopt(
-            short = "r",
-            long = "registry-hashes",
-            parse(from_os_str),
-            help = "The path to the registry hash cache file. \
-                    Uses 'registry-hashes.json' in the same directory as the Cargo.nix output by default."
+            long = "member",
+            help = "Only report the crates needed by this workspace member."
         )]
-        registry_hashes: Option<PathBuf>,
+        member: Option<String>,
+
+        #[structopt(long = "json", help = "Print the report as JSON.")]
+        json: bool,
+    },
+
+    #[structopt(
+        name = "msrv",
+        about = "Reports the minimum rustc version needed to build each workspace member \
+                 according to the rust-version of all crates that it needs."
+    )]
+    Msrv {
+        #[structopt(flatten)]
+        project: ProjectOpt,
 
-        // Mostly useful for testing
         #[structopt(
-            long = "no-cargo-lock-checksums",
-            help = "(FOR TESTING) Do not use checksums from Cargo.lock."
+            long = "rustc-version",
+            parse(try_from_str = parse_rustc_version),
+            help = "Fail if a workspace member needs a newer rustc than this version, e.g. 1.75."
         )]
-        no_cargo_lock_checksums: bool,
+        rustc_version: Option<semver::Version>,
 
+        #[structopt(long = "json", help = "Print the report as JSON.")]
+        json: bool,
+    },
+
+    #[structopt(
+        name = "regenerate",
+        about = "Regenerates a Cargo.nix or Cargo.json with exactly the arguments \
+                 that are recorded in it."
+    )]
+    Regenerate {
         #[structopt(
-            long = "dont-read-crate-hashes",
-            help = "(FOR TESTING) Do not read crate-hashes file. \
-                    If there are any prefetches, their hashes will still be written into crate-hashes.json."
+            parse(from_os_str),
+            help = "The generated file. Defaults to ./Cargo.nix or, \
+                    if that does not exist, ./Cargo.json."
         )]
-        dont_read_crate_hashes: bool,
+        path: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "doctor",
+        about = "Checks the environment and the project for common problems, \
+                 e.g. missing prefetch tools or stale entries in crate-hashes.json."
+    )]
+    Doctor {
+        #[structopt(flatten)]
+        project: ProjectOpt,
 
         #[structopt(
-            long = "format",
-            help = "Output format: 'nix' (default, generates Cargo.nix) or 'json' \
-                    (pre-resolved JSON with platform conditions preserved as strings).",
-            default_value = "nix"
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "The path of the generated Cargo.nix to check.",
+            default_value = "./Cargo.nix"
         )]
-        format: String,
+        output: PathBuf,
+
+        #[structopt(long = "json", help = "Print the findings as JSON.")]
+        json: bool,
+    },
+
+    #[structopt(
+        name = "hashes",
+        about = "Manage the hashes in crate-hashes.json and registry-hashes.json."
+    )]
+    Hashes {
+        #[structopt(subcommand)]
+        command: HashesCommands,
     },
 
     #[structopt(name = "source", about = "Manage out of tree sources for crate2nix.")]
@@ -170,6 +216,281 @@ pub enum Opt {
     },
 }
 
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize)]
+pub struct GenerateOpt {
+    #[structopt(
+        short = "c",
+        long = "config",
+        parse(from_os_str),
+        help = "The path to the crate2nix.json file (same directory as Cargo.nix ...).",
+        default_value = "./crate2nix.json"
+    )]
+    crate2nix_json: PathBuf,
+
+    #[structopt(
+        short = "f",
+        long = "cargo-toml",
+        parse(from_os_str),
+        help = "The path to the Cargo.toml of the project."
+    )]
+    cargo_toml: Vec<PathBuf>,
+
+    #[structopt(flatten)]
+    features: FeatureOpt,
+
+    #[structopt(
+        short = "p",
+        long = "package",
+        help = "Only generate the given workspace member and its dependencies. \
+                Can be given multiple times."
+    )]
+    package: Vec<String>,
+
+    #[structopt(
+        long = "exclude",
+        help = "Leave out the given workspace member unless other members depend on it. \
+                Can be given multiple times."
+    )]
+    exclude: Vec<String>,
+
+    #[structopt(
+        long = "no-dev-dependencies",
+        help = "Leave out dev-dependencies, e.g. if the tests are not built with nix."
+    )]
+    no_dev_dependencies: bool,
+
+    #[structopt(
+        short = "o",
+        long = "output",
+        help = "The path of the output.nix file. Uses ./Cargo.nix by default."
+    )]
+    output: Option<PathBuf>,
+
+    #[structopt(
+        short = "n",
+        long = "nixpkgs-path",
+        help = "The default path for the nixpkgs to use.",
+        default_value = "<nixpkgs>"
+    )]
+    nixpkgs_path: String,
+
+    #[structopt(
+        short = "h",
+        long = "crate-hashes",
+        parse(from_os_str),
+        help = "The path to the crate hash cache file. \
+                Uses 'crate-hashes.json' in the same directory as the Cargo.nix output by default."
+    )]
+    crate_hashes: Option<PathBuf>,
+
+    #[structopt(
+        short = "r",
+        long = "registry-hashes",
+        parse(from_os_str),
+        help = "The path to the registry hash cache file. \
+                Uses 'registry-hashes.json' in the same directory as the Cargo.nix output by default."
+    )]
+    registry_hashes: Option<PathBuf>,
+
+    // Mostly useful for testing
+    #[structopt(
+        long = "no-cargo-lock-checksums",
+        help = "(FOR TESTING) Do not use checksums from Cargo.lock."
+    )]
+    no_cargo_lock_checksums: bool,
+
+    #[structopt(
+        long = "dont-read-crate-hashes",
+        help = "(FOR TESTING) Do not read crate-hashes file. \
+                If there are any prefetches, their hashes will still be written into crate-hashes.json."
+    )]
+    dont_read_crate_hashes: bool,
+
+    #[structopt(
+        long = "no-metadata-cache",
+        help = "Always run 'cargo metadata' instead of reusing cached results \
+                from $XDG_CACHE_HOME/crate2nix."
+    )]
+    no_metadata_cache: bool,
+
+    #[structopt(
+        long = "format",
+        help = "Output format: 'nix' (default, generates Cargo.nix) or 'json' \
+                (pre-resolved JSON with platform conditions preserved as strings).",
+        default_value = "nix"
+    )]
+    format: String,
+
+    #[structopt(
+        long = "resolvable",
+        help = "With --format json: keep feature rules and optional dependencies \
+                so that consumers can select features at evaluation time."
+    )]
+    resolvable: bool,
+
+    #[structopt(
+        long = "watch",
+        help = "Keep running and regenerate whenever a Cargo.toml, Cargo.lock, \
+                crate2nix.json or cargo config file changes."
+    )]
+    watch: bool,
+
+    #[structopt(
+        long = "summary",
+        help = "Print which crates were added, removed or changed compared to the previous output. \
+                With --format nix, the previous state is kept in a Cargo.snapshot.json file \
+                next to the output."
+    )]
+    summary: bool,
+
+    #[structopt(
+        long = "summary-json",
+        parse(from_os_str),
+        help = "Like --summary but write the changes as JSON to the given file."
+    )]
+    summary_json: Option<PathBuf>,
+
+    #[structopt(
+        long = "advisory-db",
+        parse(from_os_str),
+        help = "Mark crates with RustSec advisories from this advisory-db checkout \
+                as insecure via meta.knownVulnerabilities."
+    )]
+    advisory_db: Option<PathBuf>,
+
+    #[structopt(
+        long = "hash-format",
+        default_value = "nix-base32",
+        help = "The format of the hashes in the output and in newly written hash files: \
+                'nix-base32' (as `sha256 = ...`) or 'sri' (as `hash = \"sha256-...\"`)."
+    )]
+    hash_format: crate2nix::hashes::HashFormat,
+
+    #[structopt(
+        long = "message-format",
+        default_value = "human",
+        help = "How to report progress and warnings: 'human' (default, as text on stderr) \
+                or 'json' (as newline-delimited JSON events on stdout)."
+    )]
+    message_format: String,
+}
+
+#[derive(Debug, StructOpt, Deserialize, Serialize)]
+#[structopt(about = "Support for managing prefetched hashes.")]
+pub enum HashesCommands {
+    #[structopt(
+        name = "verify",
+        about = "Re-fetches the sources with a stored hash and compares the hashes."
+    )]
+    Verify {
+        #[structopt(flatten)]
+        project: ProjectOpt,
+
+        #[structopt(
+            short = "p",
+            long = "package",
+            help = "Only re-fetch the sources of these crates. Can be repeated."
+        )]
+        packages: Vec<String>,
+
+        #[structopt(
+            long = "fix",
+            help = "Rewrite the hash files with the fetched hashes and without unused entries."
+        )]
+        fix: bool,
+    },
+
+    #[structopt(
+        name = "migrate",
+        about = "Rewrites the hashes in the hash files and crate2nix.json in another format."
+    )]
+    Migrate {
+        #[structopt(
+            short = "c",
+            long = "config",
+            parse(from_os_str),
+            help = "The path to the crate2nix.json file (same directory as Cargo.nix ...).",
+            default_value = "./crate2nix.json"
+        )]
+        crate2nix_json: PathBuf,
+
+        #[structopt(
+            short = "h",
+            long = "crate-hashes",
+            parse(from_os_str),
+            help = "The path to the crate hash cache file.",
+            default_value = "./crate-hashes.json"
+        )]
+        crate_hashes: PathBuf,
+
+        #[structopt(
+            short = "r",
+            long = "registry-hashes",
+            parse(from_os_str),
+            help = "The path to the registry hash cache file.",
+            default_value = "./registry-hashes.json"
+        )]
+        registry_hashes: PathBuf,
+
+        #[structopt(
+            long = "to",
+            default_value = "sri",
+            help = "The hash format to convert to: 'sri' or 'nix-base32'."
+        )]
+        to: crate2nix::hashes::HashFormat,
+    },
+}
+
+impl HashesCommands {
+    pub fn execute(self) -> Result<(), Error> {
+        match self {
+            HashesCommands::Verify {
+                project,
+                packages,
+                fix,
+            } => {
+                let build_info = crate2nix::BuildInfo::for_config_without_hashes(
+                    &crate2nix::GenerateInfo::from_env_args(),
+                    &project.generate_config()?,
+                )?;
+                let verification = crate2nix::hashes::verify(
+                    &build_info,
+                    |name| packages.is_empty() || packages.iter().any(|p| p == name),
+                    &mut crate2nix::hashes::NixPrefetch,
+                )?;
+                print!("{}", verification);
+                if fix {
+                    verification.fix(&build_info)?;
+                }
+                let is_ok = verification.is_ok();
+                if let Some(error) = verification.into_fetch_error() {
+                    return Err(error.into());
+                }
+                if !fix && !is_ok {
+                    bail!("Stored hashes are wrong or unused. Rerun with --fix to update them.");
+                }
+                Ok(())
+            }
+            HashesCommands::Migrate {
+                crate2nix_json,
+                crate_hashes,
+                registry_hashes,
+                to,
+            } => {
+                let converted = crate2nix::hashes::migrate(
+                    &crate_hashes,
+                    &registry_hashes,
+                    &crate2nix_json,
+                    to,
+                    &TerminalReporter,
+                )?;
+                println!("Converted {} hashes to {}.", converted, to);
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Debug, StructOpt, Deserialize, Serialize)]
 #[structopt(about = "Support for managing out-of-tree sources.")]
 pub enum SourceCommands {
@@ -185,6 +506,14 @@ pub enum SourceCommands {
     },
     #[structopt(name = "list", about = "Lists all sources.")]
     List,
+    #[structopt(
+        name = "update",
+        about = "Moves git sources with a --ref to the latest commit of the ref."
+    )]
+    Update {
+        #[structopt(long = "name", help = "Only update the source with this name.")]
+        name: Option<String>,
+    },
 
     #[structopt(
         name = "fetch",
@@ -209,6 +538,23 @@ impl SourceCommands {
                 config.print_sources();
                 Ok(())
             }
+            SourceCommands::Update { name } => {
+                let mut config = Config::read_from_or_default(crate2nix_json)?;
+                let updates = crate2nix::sources::update_git_sources(
+                    &TerminalReporter,
+                    &mut config,
+                    name.as_deref(),
+                )?;
+                if updates.is_empty() {
+                    eprintln!("All sources are up to date.");
+                    return Ok(());
+                }
+                config.write_to(crate2nix_json)?;
+                for update in updates {
+                    println!("{}: {} -> {}", update.name, update.old_rev, update.new_rev);
+                }
+                Ok(())
+            }
             SourceCommands::Remove { name } => {
                 let mut config = Config::read_from_or_default(crate2nix_json)?;
                 if config.sources.is_empty() {
@@ -264,10 +610,8 @@ pub enum SourceAddingCommands {
         name = "git",
         about = "Adds git source.\n\
                  \n\
-                 If you want auto-update support, consider using the \"nix\" source type\n\
-                 and manage the sources with niv.\n\
-                 \n\
-                 See https://github.com/nmattia/niv."
+                 With --ref, `crate2nix source update` moves the source to the latest\n\
+                 commit of the branch or tag."
     )]
     Git {
         #[structopt(
@@ -282,8 +626,18 @@ pub enum SourceAddingCommands {
         /// E.g. https://github.com/kolloch/crate2nix.git
         url: url::Url,
 
-        #[structopt(long = "rev", parse(from_str), help = "The git revision hash.")]
-        rev: String,
+        #[structopt(
+            long = "rev",
+            parse(from_str),
+            help = "The git revision hash. Defaults to the current commit of --ref."
+        )]
+        rev: Option<String>,
+
+        #[structopt(
+            long = "ref",
+            help = "The branch or tag to follow with `crate2nix source update`."
+        )]
+        r#ref: Option<String>,
     },
 
     #[structopt(
@@ -323,6 +677,46 @@ pub enum SourceAddingCommands {
         )]
         attr: Option<String>,
     },
+
+    #[structopt(
+        name = "path",
+        about = "Adds a local directory as source.\n\
+                 Relative paths are relative to the directory of crate2nix.json."
+    )]
+    Path {
+        #[structopt(
+            long = "name",
+            help = "Use this source name instead of the last path component.\n\
+                    The source name is used as a workspaceMember name."
+        )]
+        name: Option<String>,
+
+        #[structopt(help = "The path of the directory.")]
+        path: String,
+    },
+
+    #[structopt(
+        name = "tarball",
+        about = "Adds a tarball or zip archive from a URL as source.\n\
+                 E.g. crate2nix source add tarball https://example.com/my_crate-1.0.tar.gz"
+    )]
+    Tarball {
+        #[structopt(
+            long = "name",
+            help = "Use this source name instead of the file name of the URL without extension.\n\
+                    The source name is used as a workspaceMember name."
+        )]
+        name: Option<String>,
+
+        /// The URL of the archive.
+        url: url::Url,
+
+        #[structopt(
+            long = "strip-prefix",
+            help = "The directory of the source within the unpacked archive."
+        )]
+        strip_prefix: Option<String>,
+    },
 }
 
 impl SourceAddingCommands {
@@ -333,11 +727,25 @@ impl SourceAddingCommands {
                 crate_name,
                 crate_version,
             } => {
-                let source = crate2nix::sources::crates_io_source(crate_name, crate_version)?;
+                let source = crate2nix::sources::crates_io_source(
+                    &TerminalReporter,
+                    crate_name,
+                    crate_version,
+                )?;
                 (name, source)
             }
-            SourceAddingCommands::Git { name, url, rev } => {
-                let source = crate2nix::sources::git_io_source(url, rev)?;
+            SourceAddingCommands::Git {
+                name,
+                url,
+                rev,
+                r#ref,
+            } => {
+                let rev = match (rev, r#ref.as_deref()) {
+                    (Some(rev), _) => rev,
+                    (None, Some(r#ref)) => crate2nix::sources::resolve_git_ref(&url, r#ref)?,
+                    (None, None) => bail!("Please specify --rev or --ref."),
+                };
+                let source = crate2nix::sources::git_io_source(&TerminalReporter, url, rev, r#ref)?;
                 (name, source)
             }
             SourceAddingCommands::Nix {
@@ -354,6 +762,18 @@ impl SourceAddingCommands {
 
                 (name, crate2nix::config::Source::Nix { file, attr })
             }
+            SourceAddingCommands::Path { name, path } => {
+                (name, crate2nix::config::Source::Path { path })
+            }
+            SourceAddingCommands::Tarball {
+                name,
+                url,
+                strip_prefix,
+            } => {
+                let source =
+                    crate2nix::sources::tarball_source(&TerminalReporter, url, strip_prefix)?;
+                (name, source)
+            }
         };
         let mut config = Config::read_from_or_default(crate2nix_json)?;
         let old_source = config.upsert_source(name, source.clone());
@@ -373,152 +793,626 @@ impl SourceAddingCommands {
     }
 }
 
-/// Refuse to overwrite a JSON file that wasn't generated by crate2nix.
-fn check_generated_json(path: impl AsRef<Path>) -> Result<(), Error> {
-    let path = path.as_ref();
-    if !path.exists() {
-        return Ok(());
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize)]
+pub struct FeatureOpt {
+    #[structopt(
+        long = "all-features",
+        help = "Resolve project dependencies with all features enabled. \
+                This is the default and does not need to be specified. \
+                Users can choose their sub set of features and evaluation time so \
+                that one generated build file can be used for different feature selections."
+    )]
+    all_features: bool,
+
+    #[structopt(
+        long = "default-features",
+        help = "Enables the default default features \
+                (instead of all features as is the default). \
+                Often combined with --features to add selected features on top."
+    )]
+    default_features: bool,
+
+    #[structopt(
+        long = "no-default-features",
+        help = "Disables all features. \
+                Often combined with --features to reenable selected features."
+    )]
+    no_default_features: bool,
+
+    #[structopt(
+        long = "features",
+        help = "Resolve project dependencies additionally with these features enabled. \
+                By default, all features are resolved."
+    )]
+    features: Vec<String>,
+}
+
+/// Options for commands that analyze the resolved build of a project.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize)]
+pub struct ProjectOpt {
+    #[structopt(
+        short = "c",
+        long = "config",
+        parse(from_os_str),
+        help = "The path to the crate2nix.json file (same directory as Cargo.nix ...).",
+        default_value = "./crate2nix.json"
+    )]
+    crate2nix_json: PathBuf,
+
+    #[structopt(
+        short = "f",
+        long = "cargo-toml",
+        parse(from_os_str),
+        help = "The path to the Cargo.toml of the project.",
+        default_value = "./Cargo.toml"
+    )]
+    cargo_toml: Vec<PathBuf>,
+
+    #[structopt(
+        short = "h",
+        long = "crate-hashes",
+        parse(from_os_str),
+        help = "The path to the crate hash cache file.",
+        default_value = "./crate-hashes.json"
+    )]
+    crate_hashes: PathBuf,
+
+    #[structopt(
+        short = "r",
+        long = "registry-hashes",
+        parse(from_os_str),
+        help = "The path to the registry hash cache file.",
+        default_value = "./registry-hashes.json"
+    )]
+    registry_hashes: PathBuf,
+
+    #[structopt(flatten)]
+    features: FeatureOpt,
+
+    #[structopt(
+        long = "no-metadata-cache",
+        help = "Always run 'cargo metadata' instead of reusing cached results \
+                from $XDG_CACHE_HOME/crate2nix."
+    )]
+    no_metadata_cache: bool,
+}
+
+impl ProjectOpt {
+    /// Resolves the build like `generate` would without writing any output.
+    fn build_info(&self) -> Result<crate2nix::BuildInfo, Error> {
+        Ok(crate2nix::BuildInfo::for_config(
+            &crate2nix::GenerateInfo::from_env_args(),
+            &self.generate_config()?,
+        )?)
     }
 
-    let contents = std::fs::read_to_string(path)?;
-    if !contents.contains("@generated by crate2nix") {
-        bail!(
-            "Cowardly refusing to overwrite {} without generated marker.",
-            path.to_string_lossy()
-        );
+    /// The configuration to resolve the build like `generate` would.
+    fn generate_config(&self) -> Result<crate2nix::GenerateConfig, Error> {
+        Ok(self
+            .cargo_toml
+            .iter()
+            .fold(
+                crate2nix::GenerateConfig::builder(),
+                |builder, cargo_toml| builder.cargo_toml(cargo_toml),
+            )
+            .output(DEFAULT_OUTPUT)
+            .crate_hashes_json(&self.crate_hashes)
+            .registry_hashes_json(&self.registry_hashes)
+            .crate2nix_json(&self.crate2nix_json)
+            .metadata_options(self.features.metadata_options()?)
+            .use_metadata_cache(!self.no_metadata_cache)
+            .build()?)
     }
-    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
-    match opt {
-        Opt::Generate {
+impl FeatureOpt {
+    /// The options to pass to `cargo metadata` for the selected features.
+    fn metadata_options(&self) -> Result<Vec<String>, Error> {
+        let mut options = Vec::new();
+
+        if [
+            self.all_features,
+            self.default_features,
+            self.no_default_features,
+        ]
+        .iter()
+        .filter(|x| **x)
+        .count()
+            > 1
+        {
+            bail!(
+                "Please specify at most one of \
+                 --all-features, --no-default-features and --default-features."
+            )
+        }
+
+        // "cargo metadata" will default to the "default features".
+        // crate2nix defaults to "--all-features" since this allows users to choose
+        // any set of features at evaluation time.
+        let all_features = !self.no_default_features && !self.default_features;
+        if self.no_default_features {
+            options.push("--no-default-features".to_string());
+        } else if !self.default_features {
+            assert!(all_features);
+            options.push("--all-features".to_string());
+        }
+
+        if !self.features.is_empty() {
+            if all_features {
+                bail!(
+                    "You specified --features but --all-features was already selected. \
+                       Use --no-default-features or --default-features to only select \
+                       some features as a basis and then use --features to add additional \
+                       features on top."
+                )
+            }
+            options.push("--features".to_string());
+            options.push(self.features.join(" "));
+        }
+
+        Ok(options)
+    }
+}
+
+impl GenerateOpt {
+    /// Generates the output, recording the given info in it.
+    fn execute(&self, generate_info: &crate2nix::GenerateInfo) -> Result<(), Error> {
+        let build_info = self.generate(generate_info, None)?;
+        if self.watch {
+            self.watch(build_info, generate_info)?;
+        }
+        Ok(())
+    }
+
+    /// Regenerates whenever one of the input files changes, forever.
+    fn watch(
+        &self,
+        mut build_info: crate2nix::BuildInfo,
+        generate_info: &crate2nix::GenerateInfo,
+    ) -> Result<(), Error> {
+        loop {
+            let mut input_files = build_info.input_files();
+            input_files.push(self.crate2nix_json.clone());
+            let mut watcher = crate2nix::watch::FileWatcher::new(input_files);
+            eprintln!("Watching for changes...");
+
+            let changed = watcher.wait_for_changes(WATCH_INTERVAL);
+            eprintln!(
+                "Regenerating after changes to {}.",
+                changed.iter().map(|p| p.to_string_lossy()).join(", ")
+            );
+            // Reuse the hashes in memory instead of re-reading crate-hashes.json.
+            let prefetched_hashes = build_info.crate_hashes.clone();
+            match self.generate(generate_info, Some(prefetched_hashes)) {
+                Ok(new_build_info) => {
+                    let changes = crate2nix::changes::WorkspaceChanges::between(
+                        &crate2nix::json_output::to_resolved_workspace(&build_info),
+                        &crate2nix::json_output::to_resolved_workspace(&new_build_info),
+                    );
+                    eprintln!("{}", changes);
+                    build_info = new_build_info;
+                }
+                Err(e) => eprintln!("ERROR: {:#}", e),
+            }
+        }
+    }
+
+    /// Generates the output once and returns the build info it was generated from.
+    ///
+    /// Uses the given prefetched hashes instead of reading `crate-hashes.json`.
+    fn generate(
+        &self,
+        generate_info: &crate2nix::GenerateInfo,
+        prefetched_hashes: Option<BTreeMap<PackageId, String>>,
+    ) -> Result<crate2nix::BuildInfo, Error> {
+        let GenerateOpt {
             crate2nix_json,
             mut cargo_toml,
             output: opt_output,
             nixpkgs_path,
             crate_hashes,
             registry_hashes,
-            all_features,
-            default_features,
-            no_default_features,
             features,
+            package,
+            exclude,
+            no_dev_dependencies,
             no_cargo_lock_checksums,
             dont_read_crate_hashes,
+            no_metadata_cache,
             format,
-        } => {
-            let config = crate2nix::config::Config::read_from_or_default(&crate2nix_json)?;
+            resolvable,
+            watch: _,
+            summary,
+            summary_json,
+            advisory_db,
+            hash_format,
+            message_format,
+        } = self.clone();
+        let config = crate2nix::config::Config::read_from_or_default(&crate2nix_json)?;
+
+        let output_format = match (format.as_str(), resolvable) {
+            ("nix", false) => OutputFormat::Nix,
+            ("nix", true) => bail!("--resolvable is only supported with --format json."),
+            ("json", false) => OutputFormat::Json,
+            ("json", true) => OutputFormat::ResolvableJson,
+            _ => bail!("Unknown format '{}'. Use 'nix' or 'json'.", format),
+        };
+        let reporter: Arc<dyn Reporter> = match message_format.as_str() {
+            "human" => Arc::new(TerminalReporter),
+            "json" => Arc::new(JsonReporter::new(std::io::stdout())),
+            _ => bail!(
+                "Unknown message format '{}'. Use 'human' or 'json'.",
+                message_format
+            ),
+        };
+
+        if !config.sources.is_empty() {
+            let fetched_sources = crate2nix::sources::FetchedSources::new(&crate2nix_json)
+                .with_generate_info(generate_info.clone())
+                .with_reporter(reporter.clone());
+            let cargo_tomls = fetched_sources.get_cargo_tomls()?;
+            cargo_toml.extend(cargo_tomls);
+        }
+
+        let output: PathBuf = opt_output
+            .map(|v| Ok(v) as Result<_, Error>)
+            .unwrap_or_else(|| {
+                if output_format == OutputFormat::Nix {
+                    crate2nix::render::check_generated_by_crate2nix(DEFAULT_OUTPUT)?;
+                    Ok(DEFAULT_OUTPUT.into())
+                } else {
+                    check_generated_json(DEFAULT_JSON_OUTPUT)?;
+                    Ok(DEFAULT_JSON_OUTPUT.into())
+                }
+            })?;
+
+        let builder = cargo_toml.iter().fold(
+            crate2nix::GenerateConfig::builder(),
+            |builder, cargo_toml| builder.cargo_toml(cargo_toml),
+        );
+        let builder = package
+            .iter()
+            .fold(builder, |builder, package| builder.package(package));
+        let mut builder = exclude
+            .iter()
+            .fold(builder, |builder, exclude| builder.exclude(exclude))
+            .no_dev_dependencies(no_dev_dependencies)
+            .output(&output)
+            .crate2nix_json(&crate2nix_json)
+            .nixpkgs_path(nixpkgs_path)
+            .metadata_options(features.metadata_options()?)
+            .use_cargo_lock_checksums(!no_cargo_lock_checksums)
+            .read_crate_hashes(!dont_read_crate_hashes)
+            .use_metadata_cache(!no_metadata_cache)
+            .hash_format(hash_format)
+            .output_format(output_format)
+            .reporter(reporter.clone());
+        if let Some(crate_hashes) = crate_hashes {
+            builder = builder.crate_hashes_json(crate_hashes);
+        }
+        if let Some(registry_hashes) = registry_hashes {
+            builder = builder.registry_hashes_json(registry_hashes);
+        }
+        if let Some(prefetched_hashes) = prefetched_hashes {
+            builder = builder.crate_hashes(prefetched_hashes);
+        }
+        let generate_config = builder.build()?;
+
+        if output_format == OutputFormat::Nix && !config.profiles.is_empty() {
+            reporter.report(&Event::warning(format!(
+                "Feature profiles in {} are only used with --format json.",
+                crate2nix_json.to_string_lossy()
+            )));
+        }
+
+        let mut artifacts = crate2nix::generate(generate_info, &generate_config)?;
+
+        if let Some(advisory_db) = advisory_db {
+            crate2nix::audit::AdvisoryDatabase::load(&advisory_db)?
+                .annotate(&mut artifacts.build_info);
+            artifacts.output = artifacts.build_info.render()?;
+        }
+
+        if let Some(policy) = config.licenses.as_ref().filter(|p| p.enforce) {
+            crate2nix::licenses::LicenseReport::new(&artifacts.build_info, Some(policy)).check()?;
+        }
+
+        let summarize = summary || summary_json.is_some();
+        let snapshot_path = match output_format {
+            OutputFormat::Nix => output.with_extension("snapshot.json"),
+            OutputFormat::Json | OutputFormat::ResolvableJson => output.clone(),
+        };
+        let previous = if summarize {
+            read_snapshot(&snapshot_path)?
+        } else {
+            None
+        };
+
+        artifacts.write()?;
 
-            if !config.sources.is_empty() {
-                let fetched_sources = crate2nix::sources::FetchedSources::new(&crate2nix_json);
-                let cargo_tomls = fetched_sources.get_cargo_tomls()?;
-                cargo_toml.extend(cargo_tomls);
+        let snapshot = match output_format {
+            OutputFormat::Nix => {
+                let snapshot = crate2nix::json_output::to_resolved_workspace(&artifacts.build_info);
+                if summarize {
+                    std::fs::write(&snapshot_path, serde_json::to_string_pretty(&snapshot)?)?;
+                }
+                snapshot
             }
+            OutputFormat::Json | OutputFormat::ResolvableJson => {
+                serde_json::from_str(&artifacts.output.content)?
+            }
+        };
 
-            if cargo_toml.is_empty() {
-                cargo_toml.push("./Cargo.toml".into());
+        if summarize {
+            let previous = previous.unwrap_or_else(|| {
+                eprintln!(
+                    "No previous {} found, all crates are reported as added.",
+                    snapshot_path.to_string_lossy()
+                );
+                crate2nix::json_output::ResolvedWorkspace::default()
+            });
+            let changes = crate2nix::changes::WorkspaceChanges::between(&previous, &snapshot);
+            if summary {
+                eprintln!("{}", changes);
+            }
+            if let Some(summary_json) = summary_json {
+                std::fs::write(&summary_json, serde_json::to_string_pretty(&changes)?)?;
             }
+        }
 
-            let output: PathBuf = opt_output
-                .map(|v| Ok(v) as Result<_, Error>)
-                .unwrap_or_else(|| {
-                    if format == "json" {
-                        check_generated_json(DEFAULT_JSON_OUTPUT)?;
-                        Ok(DEFAULT_JSON_OUTPUT.into())
-                    } else {
-                        crate2nix::render::check_generated_by_crate2nix(DEFAULT_OUTPUT)?;
-                        Ok(DEFAULT_OUTPUT.into())
-                    }
-                })?;
+        Ok(artifacts.build_info)
+    }
+}
 
-            let crate_hashes_json = crate_hashes.unwrap_or_else(|| {
-                output
-                    .parent()
-                    .expect("Cargo.nix has parent")
-                    .join("crate-hashes.json")
-            });
+/// Reads the workspace that was generated previously, if any.
+fn read_snapshot(path: &Path) -> Result<Option<crate2nix::json_output::ResolvedWorkspace>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(path)?;
+    let workspace = serde_json::from_str(&contents)
+        .map_err(|e| format_err!("while parsing {}: {}", path.to_string_lossy(), e))?;
+    Ok(Some(workspace))
+}
+
+/// Parses a rustc version like cargo parses `rust-version`: "1.75" means "1.75.0".
+fn parse_rustc_version(version: &str) -> Result<semver::Version, semver::Error> {
+    match version.matches('.').count() {
+        1 => format!("{}.0", version).parse(),
+        _ => version.parse(),
+    }
+}
+
+/// Regenerates the given file with the arguments recorded in it.
+fn regenerate(path: Option<PathBuf>) -> Result<(), Error> {
+    let path = path.unwrap_or_else(|| {
+        if !Path::new(DEFAULT_OUTPUT).exists() && Path::new(DEFAULT_JSON_OUTPUT).exists() {
+            DEFAULT_JSON_OUTPUT.into()
+        } else {
+            DEFAULT_OUTPUT.into()
+        }
+    });
+    let recorded = crate2nix::GenerateInfo::read_from(&path)?.ok_or_else(|| {
+        format_err!(
+            "{} does not record the arguments it was generated with. \
+             Generate it once with `crate2nix generate`.",
+            path.to_string_lossy()
+        )
+    })?;
+
+    let current = crate2nix::GenerateInfo::default();
+    if recorded.crate2nix_version != current.crate2nix_version {
+        eprintln!(
+            "WARNING: {} was generated by crate2nix {}, this is crate2nix {}. \
+             The output may change beyond your changes.",
+            path.to_string_lossy(),
+            recorded.crate2nix_version,
+            current.crate2nix_version
+        );
+    }
+
+    // Regenerating is a one-shot operation, even if the file was generated
+    // by a `crate2nix generate --watch`.
+    let crate2nix_arguments: Vec<String> = recorded
+        .crate2nix_arguments
+        .into_iter()
+        .filter(|argument| argument != "--watch")
+        .collect();
+    let arguments = std::iter::once("crate2nix".to_string())
+        .chain(crate2nix_arguments.iter().cloned())
+        .collect::<Vec<_>>();
+    let generate = match Opt::from_iter_safe(&arguments)
+        .map_err(|e| format_err!("while parsing the recorded arguments: {}", e.message))?
+    {
+        Opt::Generate(generate) => generate,
+        _ => bail!(
+            "{} was not generated by `crate2nix generate` but by `{}`.",
+            path.to_string_lossy(),
+            arguments.join(" ")
+        ),
+    };
 
-            let registry_hashes_json = registry_hashes.unwrap_or_else(|| {
-                output
-                    .parent()
-                    .expect("Cargo.nix has parent")
-                    .join("registry-hashes.json")
+    // The recorded paths are relative to the directory that crate2nix was
+    // called in, so we find the directory in which the recorded output is `path`.
+    let recorded_output =
+        generate
+            .output
+            .clone()
+            .unwrap_or_else(|| match generate.format.as_str() {
+                "json" => DEFAULT_JSON_OUTPUT.into(),
+                _ => DEFAULT_OUTPUT.into(),
             });
+    let path = path
+        .canonicalize()
+        .map_err(|e| format_err!("while resolving {}: {}", path.to_string_lossy(), e))?;
+    let generation_dir = std::iter::once(std::env::current_dir()?)
+        .chain(path.ancestors().skip(1).map(Path::to_path_buf))
+        .find(|dir| {
+            dir.join(&recorded_output)
+                .canonicalize()
+                .is_ok_and(|output| output == path)
+        })
+        .ok_or_else(|| {
+            format_err!(
+                "Could not find the directory that {} was generated in: \
+                 the recorded output is {}.",
+                path.to_string_lossy(),
+                recorded_output.to_string_lossy()
+            )
+        })?;
 
-            let generate_info = crate2nix::GenerateInfo::default();
-
-            let feature_metadata_options = || {
-                let mut options = Vec::new();
-
-                if [all_features, default_features, no_default_features]
-                    .iter()
-                    .filter(|x| **x)
-                    .count()
-                    > 1
-                {
-                    bail!(
-                        "Please specify at most one of \
-                         --all-features, --no-default-features and --default-features."
-                    )
-                }
+    eprintln!(
+        "Regenerating in {} with: crate2nix {}",
+        generation_dir.to_string_lossy(),
+        crate2nix_arguments.join(" ")
+    );
+    std::env::set_current_dir(&generation_dir).map_err(|e| {
+        format_err!(
+            "while changing to {}: {}",
+            generation_dir.to_string_lossy(),
+            e
+        )
+    })?;
+    generate.execute(&crate2nix::GenerateInfo::new(crate2nix_arguments))
+}
 
-                // "cargo metadata" will default to the "default features".
-                // crate2nix defaults to "--all-features" since this allows users to choose
-                // any set of features at evaluation time.
-                let all_features = !no_default_features && !default_features;
-                if no_default_features {
-                    options.push("--no-default-features".to_string());
-                } else if !default_features {
-                    assert!(all_features);
-                    options.push("--all-features".to_string());
-                }
+/// Refuse to overwrite a JSON file that wasn't generated by crate2nix.
+fn check_generated_json(path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(());
+    }
 
-                if !features.is_empty() {
-                    if all_features {
-                        bail!(
-                            "You specified --features but --all-features was already selected. \
-                               Use --no-default-features or --default-features to only select \
-                               some features as a basis and then use --features to add additional \
-                               features on top."
-                        )
-                    }
-                    options.push("--features".to_string());
-                    options.push(features.join(" "));
-                }
+    let contents = std::fs::read_to_string(path)?;
+    if !contents.contains("@generated by crate2nix") {
+        return Err(crate2nix::Error::OverwriteGuard {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        let exit_code = e
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<crate2nix::Error>())
+            .map_or(1, crate2nix::Error::exit_code);
+        std::process::exit(exit_code);
+    }
+}
 
-                Ok(options)
-            };
-
-            let generate_config = crate2nix::GenerateConfig {
-                cargo_toml,
-                output: output.clone(),
-                nixpkgs_path,
-                crate_hashes_json,
-                registry_hashes_json,
-                other_metadata_options: feature_metadata_options()?,
-                use_cargo_lock_checksums: !no_cargo_lock_checksums,
-                read_crate_hashes: !dont_read_crate_hashes,
-            };
-            let build_info = crate2nix::BuildInfo::for_config(&generate_info, &generate_config)?;
-
-            match format.as_str() {
-                "json" => {
-                    let resolved = crate2nix::json_output::to_resolved_workspace(&build_info);
-                    let json = serde_json::to_string_pretty(&resolved)?;
+fn run() -> anyhow::Result<()> {
+    let opt = Opt::from_args();
+    match opt {
+        Opt::Generate(generate) => {
+            generate.execute(&crate2nix::GenerateInfo::from_env_args())?;
+        }
+        Opt::Sbom {
+            project,
+            format,
+            member,
+            output,
+        } => {
+            let format: crate2nix::sbom::SbomFormat = format.parse()?;
+            let build_info = project.build_info()?;
+            let sbom = crate2nix::sbom::sbom(&build_info, member.as_deref(), format)?;
+            let json = serde_json::to_string_pretty(&sbom)?;
+            match output {
+                Some(output) => {
                     std::fs::write(&output, json)?;
-                    eprintln!("Generated {} successfully.", output.to_string_lossy());
+                    eprintln!("Wrote SBOM to {}.", output.to_string_lossy());
                 }
-                "nix" => {
-                    render::CARGO_NIX.write_to_file(&output, &build_info)?;
+                None => println!("{}", json),
+            }
+        }
+        Opt::Audit {
+            project,
+            advisory_db,
+            member,
+            json,
+        } => {
+            let db = crate2nix::audit::AdvisoryDatabase::load(&advisory_db)?;
+            let build_info = project.build_info()?;
+            let mut report = crate2nix::audit::AuditReport::new(&build_info, &db);
+            if let Some(member) = member {
+                report.members.retain(|name, _| *name == member);
+                if report.members.is_empty() {
+                    bail!("No workspace member named '{}'.", member);
+                }
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report);
+            }
+            report.check()?;
+        }
+        Opt::Licenses {
+            project,
+            member,
+            json,
+        } => {
+            let config = Config::read_from_or_default(&project.crate2nix_json)?;
+            let build_info = project.build_info()?;
+            let mut report =
+                crate2nix::licenses::LicenseReport::new(&build_info, config.licenses.as_ref());
+            if let Some(member) = member {
+                report.members.retain(|name, _| *name == member);
+                if report.members.is_empty() {
+                    bail!("No workspace member named '{}'.", member);
                 }
-                _ => bail!("Unknown format '{}'. Use 'nix' or 'json'.", format),
             }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report);
+            }
+            report.check()?;
+        }
+        Opt::Msrv {
+            project,
+            rustc_version,
+            json,
+        } => {
+            let build_info = project.build_info()?;
+            let report = crate2nix::msrv::MsrvReport::new(&build_info);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report);
+            }
+            if let Some(rustc_version) = rustc_version {
+                report.check(&rustc_version)?;
+            }
+        }
+        Opt::Regenerate { path } => {
+            regenerate(path)?;
+        }
+        Opt::Doctor {
+            project,
+            output,
+            json,
+        } => {
+            let mut config = project.generate_config()?;
+            config.output = output;
+            let diagnosis = crate2nix::doctor::Diagnosis::new(&config);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diagnosis)?);
+            } else {
+                print!("{}", diagnosis);
+            }
+            diagnosis.check()?;
         }
         Opt::Completions { shell, output } => {
             let shell = FromStr::from_str(&shell).map_err(|s| format_err!("{}", s))?;
             Opt::clap().gen_completions(env!("CARGO_PKG_NAME"), shell, output);
         }
+        Opt::Hashes { command } => {
+            command.execute()?;
+        }
         Opt::Source {
             crate2nix_json,
             command,
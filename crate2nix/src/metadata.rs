@@ -1,17 +1,20 @@
 //! Indexing cargo metadata.
 
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 
 use anyhow::format_err;
 use anyhow::{Error, Result};
 use cargo_metadata::Node;
 use cargo_metadata::Package;
 use cargo_metadata::PackageId;
-use cargo_metadata::{Metadata, NodeDep};
+use cargo_metadata::{workspace_default_members_is_missing, Metadata, NodeDep};
 use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::report::{Event, Reporter};
+
 /// The merged metadata of potentially multiple sources.
 ///
 /// Metadata: Package Metadata from Cargo.lock files.
@@ -22,6 +25,7 @@ use serde::Serialize;
 #[derive(Debug)]
 pub struct MergedMetadata {
     workspace_members: Vec<PackageId>,
+    default_members: Vec<PackageId>,
     pub(crate) packages: Vec<Package>,
     root: Option<PackageId>,
     nodes: Vec<Node>,
@@ -30,9 +34,10 @@ pub struct MergedMetadata {
 }
 
 impl MergedMetadata {
-    pub fn merge(metadatas: Vec<Metadata>) -> Result<MergedMetadata> {
+    pub fn merge(metadatas: Vec<Metadata>, reporter: &dyn Reporter) -> Result<MergedMetadata> {
         assert!(!metadatas.is_empty());
         let mut workspace_members = Vec::new();
+        let mut default_members = Vec::new();
         let mut package_ids = HashSet::new();
         let mut packages = Vec::new();
         let mut node_package_ids = HashSet::new();
@@ -44,13 +49,21 @@ impl MergedMetadata {
             let resolve = metadata
                 .resolve
                 .ok_or_else(|| format_err!("no resolve in metadata"))?;
-            if let Some(root) = resolve.root {
+            if let Some(root) = &resolve.root {
                 if metadata.workspace_members != vec![root.clone()] {
                     // Usually, cargo metadata also puts the root into workspace_members.
                     // Therefore, I only saw this warning in unit tests.
-                    eprintln!("WARNING: root missing from workspace_members.");
+                    reporter.report(&Event::warning("Root missing from workspace_members."));
                 }
             }
+            // Cargo also reports the root package or all members as default
+            // members if the workspace does not declare any. Cargo before 1.71
+            // does not report them at all.
+            if declares_default_members(metadata.workspace_root.as_std_path())
+                && !workspace_default_members_is_missing(&metadata.workspace_default_members)
+            {
+                default_members.extend(metadata.workspace_default_members.iter().cloned());
+            }
             workspace_members.extend(metadata.workspace_members);
             packages.extend(
                 metadata
@@ -66,8 +79,13 @@ impl MergedMetadata {
             );
         }
 
+        let workspace_members: Vec<PackageId> = workspace_members.into_iter().unique().collect();
+        let default_members: Vec<PackageId> = default_members.into_iter().unique().collect();
         let root = if workspace_members.len() <= 1 {
             workspace_members.first().cloned()
+        } else if default_members.len() == 1 {
+            // `cargo build` only builds this explicitly declared member.
+            default_members.first().cloned()
         } else {
             None
         };
@@ -75,18 +93,35 @@ impl MergedMetadata {
         Ok(MergedMetadata {
             packages,
             root,
-            workspace_members: workspace_members.into_iter().unique().collect(),
+            workspace_members,
+            default_members,
             nodes,
             workspace_root,
         })
     }
 }
 
+/// Whether the `Cargo.toml` in `workspace_root` declares `workspace.default-members`.
+fn declares_default_members(workspace_root: &Path) -> bool {
+    std::fs::read_to_string(workspace_root.join("Cargo.toml"))
+        .ok()
+        .and_then(|manifest| manifest.parse::<toml::Table>().ok())
+        .is_some_and(|manifest| {
+            manifest
+                .get("workspace")
+                .and_then(|workspace| workspace.get("default-members"))
+                .is_some()
+        })
+}
+
 /// The metadata with maps indexed by {{PackageId}} instead of flat lists.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct IndexedMetadata {
     pub root: Option<PackageId>,
     pub workspace_members: Vec<PackageId>,
+    /// The `workspace.default-members`, empty if the workspace does not declare any.
+    #[serde(default)]
+    pub default_members: Vec<PackageId>,
     pub pkgs_by_id: BTreeMap<PackageId, Package>,
     pub nodes_by_id: BTreeMap<PackageId, Node>,
     pub id_shortener: PackageIdShortener,
@@ -95,21 +130,23 @@ pub struct IndexedMetadata {
 }
 
 impl IndexedMetadata {
-    pub fn new_from(metadata: Metadata) -> Result<IndexedMetadata, Error> {
-        let merged = MergedMetadata::merge(vec![metadata])?;
-        Self::new_from_merged(&merged)
+    pub fn new_from(metadata: Metadata, reporter: &dyn Reporter) -> Result<IndexedMetadata, Error> {
+        let merged = MergedMetadata::merge(vec![metadata], reporter)?;
+        Self::new_from_merged(&merged, reporter)
     }
 
     pub fn new_from_merged(
         MergedMetadata {
             root,
             workspace_members,
+            default_members,
             packages,
             nodes,
             workspace_root,
         }: &MergedMetadata,
+        reporter: &dyn Reporter,
     ) -> Result<IndexedMetadata, Error> {
-        let id_shortener = PackageIdShortener::new(packages.iter());
+        let id_shortener = PackageIdShortener::new(packages.iter(), reporter);
 
         let pkgs_by_id: BTreeMap<PackageId, Package> = packages
             .iter()
@@ -137,6 +174,10 @@ impl IndexedMetadata {
                 .iter()
                 .map(|id| id_shortener.shorten(id))
                 .collect(),
+            default_members: default_members
+                .iter()
+                .map(|id| id_shortener.shorten(id))
+                .collect(),
             pkgs_by_id,
             nodes_by_id,
             id_shortener,
@@ -170,7 +211,10 @@ impl PackageIdShortener {
     /// If the shortening substitution wasn't successful, the package_id is not contained in
     /// the returned map.
     #[allow(clippy::needless_lifetimes)]
-    fn new<'a>(all_packages: impl Iterator<Item = &'a Package>) -> PackageIdShortener {
+    fn new<'a>(
+        all_packages: impl Iterator<Item = &'a Package>,
+        reporter: &dyn Reporter,
+    ) -> PackageIdShortener {
         let mut substitution = HashMap::new();
         let mut reverse = HashMap::new();
 
@@ -191,10 +235,10 @@ impl PackageIdShortener {
             } else if packages.iter().map(|p| &p.version).unique().count() == packages.len() {
                 UniqueComponent::NameVersion
             } else {
-                eprintln!(
+                reporter.report(&Event::warning(format!(
                     "Using same version of crate from different sources: {:#?}",
                     packages.iter().map(|p| &p.id.repr).collect::<Vec<_>>()
-                );
+                )));
                 UniqueComponent::PackageId
             };
 
@@ -225,6 +269,20 @@ impl PackageIdShortener {
         self.substitution.get(package_id).unwrap_or(package_id)
     }
 
+    /// Returns the short ID of `package_id`. If it has none yet, `short` becomes
+    /// its short ID unless `short` is already taken by another package.
+    pub fn shorten_or_add(&mut self, package_id: &PackageId, short: &PackageId) -> PackageId {
+        if let Some(existing) = self.substitution.get(package_id) {
+            return existing.clone();
+        }
+        if short == package_id || self.reverse.contains_key(short) {
+            return package_id.clone();
+        }
+        self.substitution.insert(package_id.clone(), short.clone());
+        self.reverse.insert(short.clone(), package_id.clone());
+        short.clone()
+    }
+
     pub fn shorten(&self, package_id: &PackageId) -> PackageId {
         self.substitution
             .get(package_id)
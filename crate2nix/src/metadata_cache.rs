@@ -0,0 +1,394 @@
+//! Caching `cargo metadata` output between invocations.
+//!
+//! Entries are keyed by the manifest path, the `cargo metadata` options and
+//! the cargo/crate2nix versions. Every entry records the content hashes of the
+//! files that it was derived from: the workspace manifests, `Cargo.lock` and
+//! cargo config files. It also records the listings of the locations in which
+//! cargo discovers targets automatically, e.g. `src/bin` or `build.rs`, for
+//! every path package. An entry is only used if all of these still match.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use anyhow::{format_err, Error};
+use cargo_metadata::Metadata;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::report::{Event, Reporter};
+
+/// A directory with cached `cargo metadata` results.
+#[derive(Debug)]
+pub struct MetadataCache {
+    dir: PathBuf,
+    /// The output of `cargo --version`, determined on first use.
+    cargo_version: OnceLock<String>,
+}
+
+/// A cached `cargo metadata` result.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    /// The content hash of every input file by path, `None` if the file did not
+    /// exist.
+    inputs: BTreeMap<PathBuf, Option<String>>,
+    /// The listing hash of every target discovery location by path, `None` if
+    /// it did not exist.
+    listings: BTreeMap<PathBuf, Option<String>>,
+    metadata: Metadata,
+}
+
+impl MetadataCache {
+    /// The cache in `$XDG_CACHE_HOME/crate2nix/metadata` (or `~/.cache/...`).
+    ///
+    /// Returns `None` if neither variable is set.
+    pub fn in_user_cache_dir() -> Option<MetadataCache> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(MetadataCache::new(
+            cache_home.join("crate2nix").join("metadata"),
+        ))
+    }
+
+    /// A cache in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> MetadataCache {
+        MetadataCache {
+            dir: dir.into(),
+            cargo_version: OnceLock::new(),
+        }
+    }
+
+    /// Returns the cached metadata for `cargo_toml` and `options` if all its
+    /// inputs are unchanged, otherwise calls `exec` and caches the result.
+    pub fn get_or_exec(
+        &self,
+        cargo_toml: &Path,
+        options: &[String],
+        reporter: &dyn Reporter,
+        exec: impl FnOnce() -> Result<Metadata, Error>,
+    ) -> Result<Metadata, Error> {
+        let cargo_toml = cargo_toml.canonicalize().map_err(|e| {
+            format_err!(
+                "while canonicalizing {}: {}",
+                cargo_toml.to_string_lossy(),
+                e
+            )
+        })?;
+        let cargo_version = self
+            .cargo_version
+            .get_or_init(|| cargo_version().unwrap_or_default());
+        let entry_path = self.dir.join(format!(
+            "{}.json",
+            cache_key(&cargo_toml, options, cargo_version)
+        ));
+
+        if let Some(entry) = read_entry(&entry_path) {
+            if entry.is_fresh() {
+                return Ok(entry.metadata);
+            }
+        }
+
+        let metadata = exec()?;
+        let entry = CacheEntry {
+            inputs: input_files(&cargo_toml, &metadata)
+                .into_iter()
+                .map(|path| {
+                    let hash = file_hash(&path);
+                    (path, hash)
+                })
+                .collect(),
+            listings: target_locations(&metadata)
+                .into_iter()
+                .map(|path| {
+                    let hash = listing_hash(&path);
+                    (path, hash)
+                })
+                .collect(),
+            metadata,
+        };
+        if let Err(e) = write_entry(&entry_path, &entry) {
+            reporter.report(&Event::warning(format!(
+                "Could not write metadata cache entry {}: {}",
+                entry_path.to_string_lossy(),
+                e
+            )));
+        }
+        Ok(entry.metadata)
+    }
+}
+
+impl CacheEntry {
+    fn is_fresh(&self) -> bool {
+        self.inputs
+            .iter()
+            .all(|(path, hash)| file_hash(path) == *hash)
+            && self
+                .listings
+                .iter()
+                .all(|(path, hash)| listing_hash(path) == *hash)
+    }
+}
+
+fn cache_key(cargo_toml: &Path, options: &[String], cargo_version: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(cargo_version);
+    hasher.update([0]);
+    hasher.update(cargo_toml.to_string_lossy().as_bytes());
+    for option in options {
+        hasher.update([0]);
+        hasher.update(option);
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn cargo_version() -> Option<String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo).arg("--version").output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The hex encoded sha256 of the file content or `None` if it cannot be read.
+fn file_hash(path: &Path) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    Some(hex::encode(Sha256::digest(content)))
+}
+
+/// The hex encoded sha256 of the names in the directory at `path`, an empty
+/// string for other files and `None` if nothing exists at `path`.
+///
+/// Directory entries which are directories themselves are listed with a
+/// trailing `/`, followed by `main.rs` if they contain one, to match the
+/// `src/bin/<name>/main.rs` style of target discovery.
+fn listing_hash(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return Some(String::new());
+    }
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                name.push('/');
+                if entry.path().join("main.rs").is_file() {
+                    name.push_str("main.rs");
+                }
+            }
+            name
+        })
+        .collect();
+    names.sort();
+    let mut hasher = Sha256::new();
+    for name in names {
+        hasher.update(name);
+        hasher.update([0]);
+    }
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// The locations in which cargo discovers targets of path packages
+/// automatically, plus the source files of their current targets.
+fn target_locations(metadata: &Metadata) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for package in metadata.packages.iter().filter(|p| p.source.is_none()) {
+        let package_dir = package
+            .manifest_path
+            .parent()
+            .expect("manifest has parent")
+            .as_std_path();
+        paths.extend(
+            [
+                "build.rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "src/bin",
+                "examples",
+                "tests",
+                "benches",
+            ]
+            .iter()
+            .map(|p| package_dir.join(p)),
+        );
+        paths.extend(
+            package
+                .targets
+                .iter()
+                .map(|t| t.src_path.clone().into_std_path_buf()),
+        );
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// All files which influence the metadata result for the given manifest.
+fn input_files(cargo_toml: &Path, metadata: &Metadata) -> Vec<PathBuf> {
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let mut files = vec![
+        cargo_toml.to_path_buf(),
+        workspace_root.join("Cargo.toml"),
+        workspace_root.join("Cargo.lock"),
+    ];
+    // Manifests of workspace members and path dependencies.
+    files.extend(
+        metadata
+            .packages
+            .iter()
+            .filter(|p| p.source.is_none())
+            .map(|p| p.manifest_path.clone().into_std_path_buf()),
+    );
+    files.extend(cargo_config_files(
+        cargo_toml.parent().expect("manifest has parent"),
+    ));
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// The paths at which cargo looks for configuration when invoked for a
+/// manifest in `dir`, whether they exist or not.
+pub(crate) fn cargo_config_files(dir: &Path) -> Vec<PathBuf> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    dir.ancestors()
+        .map(|d| d.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|d| [d.join("config"), d.join("config.toml")])
+        .collect()
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn write_entry(path: &Path, entry: &CacheEntry) -> Result<(), Error> {
+    let dir = path.parent().expect("cache entry has parent");
+    std::fs::create_dir_all(dir)?;
+    // Write to a temporary file first so that concurrent readers never see
+    // partial entries.
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp_path, serde_json::to_vec(entry)?)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::TerminalReporter;
+    use crate::test;
+    use std::cell::Cell;
+
+    #[test]
+    fn reuses_entry_until_inputs_change() {
+        let mut env = test::MetadataEnv::default();
+        let project_dir = env.temp_dir();
+        let cache = MetadataCache::new(env.temp_dir());
+        let cargo_toml = project_dir.join("Cargo.toml");
+        std::fs::write(&cargo_toml, "[package]\nname = \"main\"\n").unwrap();
+        std::fs::write(project_dir.join("Cargo.lock"), "version = 3\n").unwrap();
+
+        std::fs::create_dir(project_dir.join("src")).unwrap();
+        std::fs::write(project_dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        env.add_package_and_node("main").update_package(|p| {
+            p.manifest_path = cargo_toml.clone().try_into().unwrap();
+            p.targets = vec![serde_json::from_value(serde_json::json!({
+                "kind": ["bin"],
+                "crate_types": ["bin"],
+                "name": "main",
+                "src_path": project_dir.join("src/main.rs"),
+                "edition": "2018",
+            }))
+            .unwrap()];
+        });
+        let mut metadata = env.metadata();
+        metadata.workspace_root = project_dir.clone().try_into().unwrap();
+        let execs = Cell::new(0);
+        let exec = || {
+            execs.set(execs.get() + 1);
+            Ok(metadata.clone())
+        };
+        let options = vec!["--all-features".to_string()];
+
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 1, "second call should hit the cache");
+
+        cache
+            .get_or_exec(&cargo_toml, &[], &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 2, "other options are a different entry");
+
+        std::fs::write(project_dir.join("Cargo.lock"), "version = 4\n").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 3, "changed lock file invalidates the entry");
+
+        std::fs::create_dir(project_dir.join(".cargo")).unwrap();
+        std::fs::write(project_dir.join(".cargo/config.toml"), "").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 4, "new cargo config invalidates the entry");
+
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 4);
+
+        std::fs::write(project_dir.join("src/main.rs"), "fn main() { }\n").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 4, "source changes do not affect metadata");
+
+        std::fs::create_dir(project_dir.join("src/bin")).unwrap();
+        std::fs::write(project_dir.join("src/bin/other.rs"), "fn main() {}\n").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 5, "new binary invalidates the entry");
+
+        std::fs::create_dir(project_dir.join("src/bin/third")).unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 6);
+        std::fs::write(project_dir.join("src/bin/third/main.rs"), "fn main() {}\n").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 7, "new binary directory invalidates the entry");
+
+        std::fs::write(project_dir.join("build.rs"), "fn main() {}\n").unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 8, "new build script invalidates the entry");
+
+        std::fs::remove_file(project_dir.join("src/main.rs")).unwrap();
+        cache
+            .get_or_exec(&cargo_toml, &options, &TerminalReporter, exec)
+            .unwrap();
+        assert_eq!(execs.get(), 9, "removed target invalidates the entry");
+
+        env.close();
+    }
+}
@@ -0,0 +1,197 @@
+//! Computing the minimum supported rust version (MSRV) of each workspace member.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{bail, Error};
+use itertools::Itertools;
+use semver::Version;
+use serde::Serialize;
+
+use crate::json_output::to_resolved_workspace;
+use crate::BuildInfo;
+
+/// The minimum rustc version needed to build each workspace member.
+#[derive(Debug, Serialize)]
+pub struct MsrvReport {
+    /// The MSRV by workspace member name.
+    pub members: BTreeMap<String, MemberMsrv>,
+}
+
+/// The minimum rustc version needed to build a workspace member.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberMsrv {
+    /// The highest `rust-version` of all crates needed to build the member,
+    /// `None` if none of them declares one.
+    pub rust_version: Option<Version>,
+    /// The crates that require `rust_version`.
+    pub required_by: Vec<RequiringCrate>,
+    /// The number of crates needed to build the member.
+    pub crates: usize,
+    /// The number of those crates without a `rust-version`.
+    pub crates_without_rust_version: usize,
+}
+
+/// A crate that determines the MSRV of a workspace member.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequiringCrate {
+    /// The crate name.
+    pub name: String,
+    /// The crate version.
+    pub version: String,
+    /// The chain of dependencies from the workspace member to the crate,
+    /// as "name version".
+    pub path: Vec<String>,
+}
+
+impl MsrvReport {
+    /// Computes the MSRV of each workspace member from the `rust-version` of
+    /// all crates that are needed to build it.
+    pub fn new(build_info: &BuildInfo) -> MsrvReport {
+        let workspace = to_resolved_workspace(build_info);
+        let rust_versions: BTreeMap<&str, &Version> = build_info
+            .crates
+            .iter()
+            .filter_map(|c| Some((c.package_id.repr.as_str(), c.rust_version.as_ref()?)))
+            .collect();
+        let describe = |package_id: &str| {
+            let c = &workspace.crates[package_id];
+            format!("{} {}", c.crate_name, c.version)
+        };
+
+        let members = workspace
+            .workspace_members
+            .iter()
+            .map(|(member, member_id)| {
+                let closure = workspace.closure_of([member_id.as_str()]);
+                let rust_version = closure
+                    .iter()
+                    .filter_map(|package_id| rust_versions.get(package_id))
+                    .max()
+                    .map(|v| (*v).clone());
+                let required_by = closure
+                    .iter()
+                    .filter(|package_id| {
+                        rust_version.is_some()
+                            && rust_versions.get(*package_id).copied() == rust_version.as_ref()
+                    })
+                    .map(|package_id| {
+                        let c = &workspace.crates[*package_id];
+                        RequiringCrate {
+                            name: c.crate_name.clone(),
+                            version: c.version.clone(),
+                            path: workspace
+                                .path_to(member_id, package_id)
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(describe)
+                                .collect(),
+                        }
+                    })
+                    .collect();
+                let msrv = MemberMsrv {
+                    rust_version,
+                    required_by,
+                    crates: closure.len(),
+                    crates_without_rust_version: closure
+                        .iter()
+                        .filter(|package_id| !rust_versions.contains_key(*package_id))
+                        .count(),
+                };
+                (member.clone(), msrv)
+            })
+            .collect();
+        MsrvReport { members }
+    }
+
+    /// Fails if a workspace member needs a newer rustc than `rustc_version`.
+    pub fn check(&self, rustc_version: &Version) -> Result<(), Error> {
+        let too_old = self
+            .members
+            .iter()
+            .filter_map(|(member, msrv)| Some((member, msrv.rust_version.as_ref()?, msrv)))
+            .filter(|(_, rust_version, _)| *rust_version > rustc_version)
+            .map(|(member, rust_version, msrv)| {
+                format!(
+                    "  {} needs rustc {} because of {}",
+                    member,
+                    rust_version,
+                    msrv.required_by
+                        .iter()
+                        .map(|c| format!("{} {}", c.name, c.version))
+                        .join(", ")
+                )
+            })
+            .collect_vec();
+        if too_old.is_empty() {
+            return Ok(());
+        }
+        bail!(
+            "rustc {} is too old for {} workspace members:\n{}",
+            rustc_version,
+            too_old.len(),
+            too_old.join("\n")
+        )
+    }
+}
+
+impl Display for MsrvReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (member, msrv) in &self.members {
+            match &msrv.rust_version {
+                Some(rust_version) => writeln!(f, "{}: rustc {}", member, rust_version)?,
+                None => writeln!(f, "{}: no crate declares a rust-version", member)?,
+            }
+            for requiring in &msrv.required_by {
+                writeln!(
+                    f,
+                    "  required by {} {} (via {})",
+                    requiring.name,
+                    requiring.version,
+                    requiring.path.join(" -> ")
+                )?;
+            }
+            if msrv.crates_without_rust_version > 0 {
+                writeln!(
+                    f,
+                    "  {} of {} crates do not declare a rust-version",
+                    msrv.crates_without_rust_version, msrv.crates
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenerateConfig, GenerateInfo};
+
+    #[test]
+    fn reports_highest_rust_version_of_closure() {
+        let config = GenerateConfig::builder()
+            .cargo_toml("../sample_projects/rust_version/Cargo.toml")
+            .build()
+            .unwrap();
+        let build_info =
+            BuildInfo::for_config_without_hashes(&GenerateInfo::default(), &config).unwrap();
+        let report = MsrvReport::new(&build_info);
+
+        let app = &report.members["app"];
+        assert_eq!(app.rust_version, Some(Version::new(1, 60, 0)));
+        assert_eq!(app.required_by.len(), 1);
+        assert_eq!(app.required_by[0].path, vec!["app 0.1.0", "newer 0.1.0"]);
+        assert_eq!(app.crates_without_rust_version, 0);
+        let tool = &report.members["tool"];
+        assert_eq!(tool.rust_version, None);
+        assert_eq!(tool.crates_without_rust_version, 1);
+
+        assert!(report.check(&Version::new(1, 60, 0)).is_ok());
+        let error = report.check(&Version::new(1, 59, 0)).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("app needs rustc 1.60.0 because of newer 0.1.0"));
+    }
+}
@@ -19,6 +19,7 @@ pub fn nix_build(
     let project_dir = project_dir_path.to_string_lossy().to_string();
 
     let result = crate::command::run(
+        &crate::report::TerminalReporter,
         &format!("Building {}", project_dir),
         Command::new("nix")
             .current_dir(&project_dir)
@@ -1,12 +1,15 @@
 //! Utilities for calling `nix-prefetch` on packages.
 
-use std::io::Write;
 use std::process::Command;
 
+use crate::error::CommandFailure;
+use crate::hashes;
 use crate::metadata::PackageIdShortener;
-use crate::resolve::{CrateDerivation, CratesIoSource, GitSource, RegistrySource, ResolvedSource};
-use crate::GenerateConfig;
-use anyhow::bail;
+use crate::report::Event;
+use crate::resolve::{
+    CrateDerivation, CratesIoSource, GitSource, RegistrySource, ResolvedSource, TarballSource,
+};
+use crate::{Artifact, GenerateConfig};
 use anyhow::format_err;
 use anyhow::Error;
 use cargo_metadata::PackageId;
@@ -18,7 +21,7 @@ use std::collections::{BTreeMap, HashMap};
 /// - New: `"source#name@version"` → split on `#`, then on `@`
 ///
 /// Returns `None` for malformed IDs or new-format IDs without a name (e.g. `"source#version"`).
-fn parse_package_id_components(repr: &str) -> Option<(String, String)> {
+pub(crate) fn parse_package_id_components(repr: &str) -> Option<(String, String)> {
     if repr.contains(' ') {
         // Old format: "name version (source)" or "name version"
         let mut parts = repr.split_whitespace();
@@ -37,42 +40,68 @@ fn parse_package_id_components(repr: &str) -> Option<(String, String)> {
 /// The source is important because we need to store only hashes for which we performed
 /// a prefetch.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum HashSource {
+pub(crate) enum HashSource {
     Prefetched,
     Existing,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct HashWithSource {
-    sha256: String,
-    source: HashSource,
+pub(crate) struct HashWithSource {
+    pub(crate) sha256: String,
+    pub(crate) source: HashSource,
 }
 
 /// A source with all the packages that depend on it and a potentially preexisting hash.
 #[derive(Debug)]
-struct SourcePrefetchBundle<'a> {
-    source: &'a ResolvedSource,
-    packages: &'a Vec<&'a CrateDerivation>,
-    hash: Option<HashWithSource>,
+pub(crate) struct SourcePrefetchBundle<'a> {
+    pub(crate) source: &'a ResolvedSource,
+    pub(crate) packages: &'a Vec<&'a CrateDerivation>,
+    pub(crate) hash: Option<HashWithSource>,
+}
+
+/// Groups the packages by their source (ignoring hashes).
+///
+/// Usually, a source is only used by one package but e.g. the same git source can be used
+/// by multiple packages.
+pub(crate) fn packages_by_source(
+    crate_derivations: &[CrateDerivation],
+) -> HashMap<ResolvedSource, Vec<&CrateDerivation>> {
+    let mut index = HashMap::new();
+    for package in crate_derivations {
+        index
+            .entry(package.source.without_sha256())
+            .or_insert_with(Vec::new)
+            .push(package);
+    }
+    index
 }
 
 /// Uses `nix-prefetch` to get the hashes of the sources for the given packages if they come from crates.io.
 ///
-/// Uses and updates the existing hashes in the `config.crate_hash_json` file.
+/// Uses the existing hashes in the `config.crate_hash_json` file and returns the
+/// updated file if it changed.
 pub fn prefetch(
     config: &GenerateConfig,
     from_lock_file: &HashMap<PackageId, String>,
     crate_derivations: &[CrateDerivation],
     id_shortener: &PackageIdShortener,
-) -> Result<BTreeMap<PackageId, String>, Error> {
-    let hashes_string: String = if config.read_crate_hashes {
-        std::fs::read_to_string(&config.crate_hashes_json).unwrap_or_else(|_| "{}".to_string())
-    } else {
-        "{}".to_string()
+) -> Result<(BTreeMap<PackageId, String>, Option<Artifact>), Error> {
+    let old_prefetched_hashes: BTreeMap<PackageId, String> = match &config.crate_hashes {
+        Some(crate_hashes) => crate_hashes.clone(),
+        None => {
+            let hashes_string: String = if config.read_crate_hashes {
+                std::fs::read_to_string(&config.crate_hashes_json)
+                    .unwrap_or_else(|_| "{}".to_string())
+            } else {
+                "{}".to_string()
+            };
+            hashes::normalize_hashes(
+                serde_json::from_str(&hashes_string)?,
+                &config.crate_hashes_json,
+            )?
+        }
     };
 
-    let old_prefetched_hashes: BTreeMap<PackageId, String> = serde_json::from_str(&hashes_string)?;
-
     // Build a fallback index keyed by (name, version) so we can match hashes
     // even when the package ID format differs (old vs new cargo format).
     let hashes_by_name_version: HashMap<(String, String), &String> = old_prefetched_hashes
@@ -84,19 +113,7 @@ pub fn prefetch(
     let mut hashes = BTreeMap::<PackageId, String>::new();
 
     // Multiple packages might be fetched from the same source.
-    //
-    // Usually, a source is only used by one package but e.g. the same git source can be used
-    // by multiple packages.
-    let packages_by_source: HashMap<ResolvedSource, Vec<&CrateDerivation>> = {
-        let mut index = HashMap::new();
-        for package in crate_derivations {
-            index
-                .entry(package.source.without_sha256())
-                .or_insert_with(Vec::new)
-                .push(package);
-        }
-        index
-    };
+    let packages_by_source = packages_by_source(crate_derivations);
 
     // Associate prefetchable sources with existing hashes.
     let prefetchable_sources: Vec<SourcePrefetchBundle> = packages_by_source
@@ -169,9 +186,24 @@ pub fn prefetch(
         let (sha256, hash_source) = if let Some(HashWithSource { sha256, source }) = hash {
             (sha256.trim().to_string(), source)
         } else {
-            eprintln!("Prefetching {:>4}/{}: {}", idx, without_hash_num, source);
+            config.reporter.report(&Event::PrefetchStarted {
+                index: idx,
+                total: without_hash_num,
+                source: source.to_string(),
+            });
+            let sha256 = source.prefetch().map_err(|e| {
+                let crate_name_and_version = packages
+                    .first()
+                    .map(|p| (p.crate_name.clone(), p.version.to_string()));
+                crate::Error::prefetch(crate_name_and_version, source, e)
+            })?;
+            config.reporter.report(&Event::PrefetchFinished {
+                index: idx,
+                total: without_hash_num,
+                source: source.to_string(),
+            });
             idx += 1;
-            (source.prefetch()?, HashSource::Prefetched)
+            (sha256, HashSource::Prefetched)
         };
 
         for package in packages {
@@ -184,39 +216,36 @@ pub fn prefetch(
         }
     }
 
-    if hashes != old_prefetched_hashes {
-        std::fs::write(
+    let hash_file = if hashes != old_prefetched_hashes {
+        Some(hashes::hash_file(
             &config.crate_hashes_json,
-            serde_json::to_vec_pretty(&hashes)?,
-        )
-        .map_err(|e| {
-            format_err!(
-                "while writing hashes to {}: {}",
-                config.crate_hashes_json.to_str().unwrap_or("<unknown>"),
-                e
-            )
-        })?;
-        eprintln!(
-            "Wrote hashes to {}.",
-            config.crate_hashes_json.to_string_lossy()
-        );
-    }
+            &hashes,
+            config.hash_format,
+        )?)
+    } else {
+        None
+    };
 
-    Ok(hashes)
+    Ok((hashes, hash_file))
 }
 
 /// Prefetch the config.json file from all the derivation's private registries.
+///
+/// Returns the updated `config.registry_hashes_json` file if it changed.
 pub fn prefetch_registries(
     config: &GenerateConfig,
     crate_derivations: &[CrateDerivation],
-) -> Result<BTreeMap<String, String>, Error> {
+) -> Result<(BTreeMap<String, String>, Option<Artifact>), Error> {
     let hashes_string: String = if config.read_crate_hashes {
         std::fs::read_to_string(&config.registry_hashes_json).unwrap_or_else(|_| "{}".to_string())
     } else {
         "{}".to_string()
     };
 
-    let old_prefetched_hashes: BTreeMap<String, String> = serde_json::from_str(&hashes_string)?;
+    let old_prefetched_hashes: BTreeMap<String, String> = hashes::normalize_hashes(
+        serde_json::from_str(&hashes_string)?,
+        &config.registry_hashes_json,
+    )?;
 
     let mut hashes = old_prefetched_hashes.clone();
 
@@ -229,54 +258,59 @@ pub fn prefetch_registries(
             };
         use std::collections::btree_map::Entry;
         if let Entry::Vacant(e) = hashes.entry(registry.to_string()) {
-            eprintln!("Prefetching {} config", e.key());
-            let out = get_command_output(
-                "nix-prefetch-url",
-                &[&format!(
-                    "{}{}config.json",
-                    e.key(),
-                    if e.key().ends_with('/') { "" } else { "/" }
-                )],
-            )?;
+            let caption = format!("Prefetching {} config", e.key());
+            config.reporter.report(&Event::TaskStarted {
+                caption: caption.clone(),
+            });
+            let out = prefetch_registry_config(e.key()).map_err(|error| {
+                config.reporter.report(&Event::TaskFinished {
+                    caption: caption.clone(),
+                    success: false,
+                });
+                crate::Error::prefetch(None, e.key(), error)
+            })?;
+            config.reporter.report(&Event::TaskFinished {
+                caption,
+                success: true,
+            });
             e.insert(out);
         }
     }
 
-    if hashes != old_prefetched_hashes {
-        std::fs::write(
+    let hash_file = if hashes != old_prefetched_hashes {
+        Some(hashes::hash_file(
             &config.registry_hashes_json,
-            serde_json::to_vec_pretty(&hashes)?,
-        )
-        .map_err(|e| {
-            format_err!(
-                "while writing hashes to {}: {}",
-                config.crate_hashes_json.to_str().unwrap_or("<unknown>"),
-                e
-            )
-        })?;
-        eprintln!(
-            "Wrote hashes to {}.",
-            config.registry_hashes_json.to_string_lossy()
-        );
-    }
+            &hashes,
+            config.hash_format,
+        )?)
+    } else {
+        None
+    };
+
+    Ok((hashes, hash_file))
+}
 
-    Ok(hashes)
+/// Prefetches the `config.json` of the given registry and returns its hash.
+pub(crate) fn prefetch_registry_config(registry: &str) -> Result<String, Error> {
+    get_command_output(
+        "nix-prefetch-url",
+        &[&format!(
+            "{}{}config.json",
+            registry,
+            if registry.ends_with('/') { "" } else { "/" }
+        )],
+    )
 }
 
 fn get_command_output(cmd: &str, args: &[&str]) -> Result<String, Error> {
-    let output = Command::new(cmd)
-        .args(args)
+    let mut command = Command::new(cmd);
+    command.args(args);
+    let output = command
         .output()
         .map_err(|e| format_err!("While spawning '{} {}': {}", cmd, args.join(" "), e))?;
 
     if !output.status.success() {
-        std::io::stdout().write_all(&output.stdout)?;
-        std::io::stderr().write_all(&output.stderr)?;
-        bail!(
-            "{}\n=> exited with: {}",
-            cmd,
-            output.status.code().unwrap_or(-1)
-        );
+        return Err(CommandFailure::new(&command, &output).into());
     }
 
     String::from_utf8(output.stdout)
@@ -299,6 +333,7 @@ impl ResolvedSource {
             ResolvedSource::CratesIo(source) => Some(source),
             ResolvedSource::Registry(source) => Some(source),
             ResolvedSource::Git(source) => Some(source),
+            ResolvedSource::Tarball(source) => Some(source),
             _ => None,
         }
     }
@@ -345,6 +380,16 @@ impl PrefetchableSource for RegistrySource {
     }
 }
 
+impl PrefetchableSource for TarballSource {
+    fn needs_prefetch(&self) -> bool {
+        self.sha256.is_none()
+    }
+
+    fn prefetch(&self) -> Result<String, Error> {
+        get_command_output("nix-prefetch-url", &["--unpack", self.url.as_str()])
+    }
+}
+
 impl PrefetchableSource for GitSource {
     fn needs_prefetch(&self) -> bool {
         self.sha256.is_none()
@@ -378,7 +423,8 @@ impl PrefetchableSource for GitSource {
 
         let json = get_command_output("nix-prefetch-git", &args)?;
         let prefetch_info: NixPrefetchGitInfo = serde_json::from_str(&json)?;
-        Ok(prefetch_info.sha256)
+        // Newer versions of nix-prefetch-git output SRI hashes.
+        hashes::to_nix_base32(&prefetch_info.sha256)
     }
 }
 
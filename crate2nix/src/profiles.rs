@@ -0,0 +1,473 @@
+//! Translating the `[profile.*]` settings of the workspace `Cargo.toml` into rustc flags.
+//!
+//! See <https://doc.rust-lang.org/cargo/reference/profiles.html>.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, format_err, Context, Error};
+use cargo_metadata::PackageId;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::resolve::CrateDerivation;
+
+/// A profile setting that can be given with different types,
+/// e.g. `debug = true`, `debug = 1` or `debug = "limited"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    /// E.g. `lto = true`.
+    Bool(bool),
+    /// E.g. `opt-level = 3`.
+    Int(i64),
+    /// E.g. `opt-level = "s"`.
+    String(String),
+}
+
+/// The settings of a `[profile.<name>]` table that are translated into rustc flags.
+///
+/// Other settings, e.g. `incremental` or `rpath`, are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileSettings {
+    /// The profile that this custom profile is based on.
+    pub inherits: Option<String>,
+    /// `-C opt-level`.
+    pub opt_level: Option<SettingValue>,
+    /// `-C debuginfo`.
+    pub debug: Option<SettingValue>,
+    /// `-C strip`.
+    pub strip: Option<SettingValue>,
+    /// `-C debug-assertions`.
+    pub debug_assertions: Option<bool>,
+    /// `-C overflow-checks`.
+    pub overflow_checks: Option<bool>,
+    /// `-C lto`, only applied to crates that are linked into a binary, cdylib
+    /// or staticlib.
+    pub lto: Option<SettingValue>,
+    /// `-C panic`, not applied to proc-macros.
+    pub panic: Option<String>,
+    /// `-C codegen-units`.
+    pub codegen_units: Option<u32>,
+    /// Overrides by package spec (`name` or `name@version`). `*` applies to
+    /// all crates that are not workspace members.
+    #[serde(default)]
+    pub package: BTreeMap<String, ProfileSettings>,
+    /// Settings for build scripts and proc-macros, not supported.
+    pub build_override: Option<toml::Value>,
+}
+
+impl ProfileSettings {
+    /// The settings of the built-in `dev` or `release` profile that are always
+    /// rendered, so that the profile does not depend on the `release` flag
+    /// of `buildRustCrate`.
+    fn built_in(release: bool) -> ProfileSettings {
+        ProfileSettings {
+            opt_level: Some(SettingValue::Int(if release { 3 } else { 0 })),
+            debug: Some(SettingValue::Bool(!release)),
+            ..ProfileSettings::default()
+        }
+    }
+
+    /// Returns these settings with unset values taken from `base`.
+    fn or(self, base: &ProfileSettings) -> ProfileSettings {
+        let mut package = base.package.clone();
+        for (spec, settings) in self.package {
+            let merged = match package.get(&spec) {
+                Some(base) => settings.or(base),
+                None => settings,
+            };
+            package.insert(spec, merged);
+        }
+        ProfileSettings {
+            inherits: self.inherits.or_else(|| base.inherits.clone()),
+            opt_level: self.opt_level.or_else(|| base.opt_level.clone()),
+            debug: self.debug.or_else(|| base.debug.clone()),
+            strip: self.strip.or_else(|| base.strip.clone()),
+            debug_assertions: self.debug_assertions.or(base.debug_assertions),
+            overflow_checks: self.overflow_checks.or(base.overflow_checks),
+            lto: self.lto.or_else(|| base.lto.clone()),
+            panic: self.panic.or_else(|| base.panic.clone()),
+            codegen_units: self.codegen_units.or(base.codegen_units),
+            package,
+            build_override: self.build_override.or_else(|| base.build_override.clone()),
+        }
+    }
+
+    /// The rustc flags for these settings for a crate of the given kind.
+    fn rustc_flags(&self, kind: CrateKind) -> Result<Vec<String>, Error> {
+        use SettingValue::*;
+
+        let mut flags = Vec::new();
+        let mut flag = |name: &str, value: &str| flags.push(format!("-C {}={}", name, value));
+        if let Some(opt_level) = &self.opt_level {
+            let value = match opt_level {
+                Int(level @ 0..=3) => level.to_string(),
+                String(level) if ["0", "1", "2", "3", "s", "z"].contains(&level.as_str()) => {
+                    level.clone()
+                }
+                other => bail!("invalid opt-level: {:?}", other),
+            };
+            flag("opt-level", &value);
+        }
+        if let Some(debug) = &self.debug {
+            let value = match debug {
+                Bool(false) | Int(0) => "0",
+                Int(1) => "1",
+                Bool(true) | Int(2) => "2",
+                String(level) => match level.as_str() {
+                    "none" => "0",
+                    "limited" => "1",
+                    "full" => "2",
+                    "line-tables-only" | "line-directives-only" => level,
+                    _ => bail!("invalid debug: {:?}", debug),
+                },
+                other => bail!("invalid debug: {:?}", other),
+            };
+            flag("debuginfo", value);
+        }
+        if let Some(strip) = &self.strip {
+            let value = match strip {
+                Bool(false) => "none",
+                Bool(true) => "symbols",
+                String(strip) if ["none", "debuginfo", "symbols"].contains(&strip.as_str()) => {
+                    strip
+                }
+                other => bail!("invalid strip: {:?}", other),
+            };
+            flag("strip", value);
+        }
+        if let Some(debug_assertions) = self.debug_assertions {
+            flag("debug-assertions", yes_no(debug_assertions));
+        }
+        if let Some(overflow_checks) = self.overflow_checks {
+            flag("overflow-checks", yes_no(overflow_checks));
+        }
+        if let Some(lto) = &self.lto {
+            let value = match lto {
+                // The default: "thin local LTO", no flag needed.
+                Bool(false) => None,
+                Bool(true) => Some("fat"),
+                String(lto) => match lto.as_str() {
+                    "false" => None,
+                    "true" | "fat" => Some("fat"),
+                    "thin" | "off" => Some(lto.as_str()),
+                    _ => bail!("invalid lto: {:?}", lto),
+                },
+                other => bail!("invalid lto: {:?}", other),
+            };
+            // Like cargo, only for the crates that are linked.
+            if let (Some(value), CrateKind::Linked) = (value, kind) {
+                flag("lto", value);
+            }
+        }
+        if let Some(panic) = &self.panic {
+            if !["unwind", "abort"].contains(&panic.as_str()) {
+                bail!("invalid panic: {:?}", panic);
+            }
+            // Like cargo, always unwind in proc-macros.
+            if kind != CrateKind::ProcMacro {
+                flag("panic", panic);
+            }
+        }
+        if let Some(codegen_units) = self.codegen_units {
+            flag("codegen-units", &codegen_units.to_string());
+        }
+        Ok(flags)
+    }
+}
+
+/// What a crate is built into, for settings that cargo only applies to some crates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrateKind {
+    /// A proc-macro.
+    ProcMacro,
+    /// A crate with binaries or a cdylib or staticlib.
+    Linked,
+    /// Any other library.
+    Library,
+}
+
+impl CrateKind {
+    fn of(crate_derivation: &CrateDerivation) -> CrateKind {
+        let links_binaries =
+            crate_derivation.is_root_or_workspace_member && !crate_derivation.binaries.is_empty();
+        if crate_derivation.proc_macro {
+            CrateKind::ProcMacro
+        } else if links_binaries
+            || crate_derivation
+                .lib_crate_types
+                .iter()
+                .any(|crate_type| crate_type == "cdylib" || crate_type == "staticlib")
+        {
+            CrateKind::Linked
+        } else {
+            CrateKind::Library
+        }
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// The `[profile]` tables of a `Cargo.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct CargoProfiles {
+    /// The settings by profile name.
+    #[serde(default)]
+    pub profile: BTreeMap<String, ProfileSettings>,
+}
+
+/// The rustc flags for the crates of a build with a cargo profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileFlags {
+    /// Whether the profile inherits from `release` (or `bench`) instead of `dev`.
+    pub release: bool,
+    /// The flags for all crates that are not listed in `crates`.
+    pub rustc_flags: Vec<String>,
+    /// The flags for crates with package overrides.
+    pub crates: BTreeMap<PackageId, Vec<String>>,
+}
+
+impl CargoProfiles {
+    /// Reads the profiles from the given `Cargo.toml`.
+    pub fn read_from(cargo_toml: &Path) -> Result<CargoProfiles, Error> {
+        let content = std::fs::read_to_string(cargo_toml)
+            .with_context(|| format!("while reading {}", cargo_toml.to_string_lossy()))?;
+        toml::from_str(&content).with_context(|| {
+            format!(
+                "while parsing the profiles in {}",
+                cargo_toml.to_string_lossy()
+            )
+        })
+    }
+
+    /// The names of the profiles: `dev`, `release` and all profiles configured in
+    /// `Cargo.toml`.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec!["dev", "release"];
+        names.extend(self.profile.keys().map(String::as_str));
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Returns the settings of the given profile including all inherited settings
+    /// and whether it is based on `release`.
+    pub fn settings(&self, name: &str) -> Result<(ProfileSettings, bool), Error> {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut current = name;
+        let release = loop {
+            if chain.contains(&current) {
+                bail!(
+                    "profile inheritance loop: {} -> {}",
+                    chain.join(" -> "),
+                    current
+                );
+            }
+            chain.push(current);
+            let inherits = self
+                .profile
+                .get(current)
+                .and_then(|settings| settings.inherits.as_deref());
+            current = match (current, inherits) {
+                ("dev", _) => break false,
+                ("release", _) => break true,
+                (_, Some(parent)) => parent,
+                ("test", None) => "dev",
+                ("bench", None) => "release",
+                (_, None) if self.profile.contains_key(current) => {
+                    bail!("profile '{}' is missing an `inherits` directive", current)
+                }
+                (_, None) => bail!("unknown profile '{}'", current),
+            };
+        };
+
+        let settings = chain
+            .iter()
+            .rev()
+            .flat_map(|name| self.profile.get(*name))
+            .fold(ProfileSettings::built_in(release), |merged, settings| {
+                settings.clone().or(&merged)
+            });
+        Ok((settings, release))
+    }
+
+    /// Returns the rustc flags for the given crates by profile name.
+    pub(crate) fn flags(
+        &self,
+        crates: &[CrateDerivation],
+    ) -> Result<BTreeMap<String, ProfileFlags>, Error> {
+        self.names()
+            .into_iter()
+            .map(|name| {
+                let flags = self
+                    .profile_flags(name, crates)
+                    .map_err(|e| format_err!("in [profile.{}]: {}", name, e))?;
+                Ok((name.to_string(), flags))
+            })
+            .collect()
+    }
+
+    fn profile_flags(&self, name: &str, crates: &[CrateDerivation]) -> Result<ProfileFlags, Error> {
+        let (settings, release) = self.settings(name)?;
+        for (spec, package_settings) in &settings.package {
+            if package_settings.panic.is_some()
+                || package_settings.lto.is_some()
+                || package_settings.inherits.is_some()
+                || !package_settings.package.is_empty()
+            {
+                bail!(
+                    "package override '{}' may not set `panic`, `lto`, `inherits` or `package`",
+                    spec
+                );
+            }
+        }
+
+        let rustc_flags = settings.rustc_flags(CrateKind::Library)?;
+        let mut crates_flags = BTreeMap::new();
+        for crate_derivation in crates {
+            let version_spec = format!(
+                "{}@{}",
+                crate_derivation.crate_name, crate_derivation.version
+            );
+            let overrides = settings
+                .package
+                .iter()
+                .filter(|(spec, _)| {
+                    (spec.as_str() == "*" && !crate_derivation.is_root_or_workspace_member)
+                        || **spec == crate_derivation.crate_name
+                        || **spec == version_spec
+                })
+                // "*" has the lowest priority, then the name and then name@version.
+                .sorted_by_key(|(spec, _)| (spec.as_str() != "*", spec.contains('@')));
+            let crate_settings = overrides.fold(settings.clone(), |merged, (_, settings)| {
+                settings.clone().or(&merged)
+            });
+            let flags = crate_settings.rustc_flags(CrateKind::of(crate_derivation))?;
+            if flags != rustc_flags {
+                crates_flags.insert(crate_derivation.package_id.clone(), flags);
+            }
+        }
+
+        Ok(ProfileFlags {
+            release,
+            rustc_flags,
+            crates: crates_flags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles(toml: &str) -> CargoProfiles {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn built_in_profiles() {
+        let profiles = CargoProfiles::default();
+        assert_eq!(profiles.names(), vec!["dev", "release"]);
+        let (release, is_release) = profiles.settings("release").unwrap();
+        assert!(is_release);
+        assert_eq!(
+            release.rustc_flags(CrateKind::Linked).unwrap(),
+            vec!["-C opt-level=3", "-C debuginfo=0"]
+        );
+        let (dev, is_release) = profiles.settings("dev").unwrap();
+        assert!(!is_release);
+        assert_eq!(
+            dev.rustc_flags(CrateKind::Linked).unwrap(),
+            vec!["-C opt-level=0", "-C debuginfo=2"]
+        );
+        assert!(!profiles.settings("test").unwrap().1);
+        assert!(profiles.settings("bench").unwrap().1);
+        assert!(profiles.settings("other").is_err());
+    }
+
+    #[test]
+    fn translates_settings() {
+        let profiles = profiles(
+            r#"
+            [profile.release]
+            codegen-units = 1
+            lto = "thin"
+            panic = "abort"
+            strip = true
+            debug = "line-tables-only"
+            overflow-checks = true
+
+            [profile.dist]
+            inherits = "release"
+            lto = true
+            opt-level = "s"
+            "#,
+        );
+        let (release, _) = profiles.settings("release").unwrap();
+        assert_eq!(
+            release.rustc_flags(CrateKind::Linked).unwrap(),
+            vec![
+                "-C opt-level=3",
+                "-C debuginfo=line-tables-only",
+                "-C strip=symbols",
+                "-C overflow-checks=yes",
+                "-C lto=thin",
+                "-C panic=abort",
+                "-C codegen-units=1",
+            ]
+        );
+        let proc_macro_flags = release.rustc_flags(CrateKind::ProcMacro).unwrap();
+        assert!(!proc_macro_flags.contains(&"-C panic=abort".to_string()));
+        assert!(!proc_macro_flags.contains(&"-C lto=thin".to_string()));
+        assert!(!release
+            .rustc_flags(CrateKind::Library)
+            .unwrap()
+            .contains(&"-C lto=thin".to_string()));
+
+        assert_eq!(profiles.names(), vec!["dev", "dist", "release"]);
+        let (dist, is_release) = profiles.settings("dist").unwrap();
+        assert!(is_release);
+        assert_eq!(
+            dist.rustc_flags(CrateKind::Linked).unwrap(),
+            vec![
+                "-C opt-level=s",
+                "-C debuginfo=line-tables-only",
+                "-C strip=symbols",
+                "-C overflow-checks=yes",
+                "-C lto=fat",
+                "-C panic=abort",
+                "-C codegen-units=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        let loop_profiles = profiles(
+            r#"
+            [profile.a]
+            inherits = "b"
+            [profile.b]
+            inherits = "a"
+            "#,
+        );
+        assert!(loop_profiles.settings("a").is_err());
+        let missing_inherits = profiles("[profile.custom]\nopt-level = 1");
+        assert!(missing_inherits.settings("custom").is_err());
+        let invalid = profiles("[profile.release]\nopt-level = 4");
+        assert!(invalid
+            .settings("release")
+            .unwrap()
+            .0
+            .rustc_flags(CrateKind::Linked)
+            .is_err());
+    }
+}
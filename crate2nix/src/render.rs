@@ -5,7 +5,6 @@ use std::path::Path;
 use std::{fs::File, iter};
 
 use crate::{BuildInfo, GenerateInfo};
-use anyhow::format_err;
 use anyhow::{bail, Error};
 use cargo_platform::{Cfg, CfgExpr};
 use itertools::Itertools;
@@ -64,15 +63,13 @@ pub struct Template<C: Serialize + Debug> {
 impl<C: Serialize + Debug> Template<C> {
     /// Returns the rendered template as a string.
     pub fn render(&self, context: &C) -> Result<String, Error> {
-        let rendered = TERA
-            .render(self.template, &Context::from_serialize(context)?)
-            .map_err(|e| {
-                format_err!(
-                    "while rendering {}: {:#?}\nContext: {:#?}",
-                    self.template,
-                    e,
-                    context
-                )
+        let rendered = Context::from_serialize(context)
+            .and_then(|context| TERA.render(self.template, &context))
+            .map_err(|e| crate::Error::Render {
+                template: self.template.to_string(),
+                message: iter::successors(Some(&e as &dyn std::error::Error), |e| e.source())
+                    .map(ToString::to_string)
+                    .join(": "),
             })?;
         Ok(rendered
             .lines()
@@ -85,10 +82,6 @@ impl<C: Serialize + Debug> Template<C> {
     pub fn write_to_file(&self, path: impl AsRef<Path>, context: &C) -> Result<(), Error> {
         let mut output_file = File::create(&path)?;
         output_file.write_all(self.render(context)?.as_bytes())?;
-        println!(
-            "Generated {} successfully.",
-            path.as_ref().to_string_lossy()
-        );
         Ok(())
     }
 }
@@ -106,14 +99,63 @@ pub fn check_generated_by_crate2nix(path: impl AsRef<Path>) -> Result<(), Error>
             .unwrap_or(false)
     });
     if !generated {
-        bail!(
-            "Cowardly refusing to overwrite {} without generated marker.",
-            path.as_ref().to_string_lossy()
-        );
+        return Err(crate::Error::OverwriteGuard {
+            path: path.as_ref().to_path_buf(),
+        }
+        .into());
     }
     Ok(())
 }
 
+/// Reads the crate2nix version and arguments from the header of a file
+/// generated by crate2nix, `None` if the file has no such header.
+pub fn read_generate_info(path: impl AsRef<Path>) -> Result<Option<GenerateInfo>, Error> {
+    let reader = std::io::BufReader::new(File::open(path.as_ref())?);
+    let mut lines = reader.lines();
+    while let Some(line) = lines.next() {
+        let line = line?;
+        let Some(rest) = line.split("@generated by crate2nix ").nth(1) else {
+            continue;
+        };
+        let Some(version) = rest.strip_suffix(" with the command:") else {
+            return Ok(None);
+        };
+        let arguments = match lines.next().transpose()? {
+            Some(arguments) => parse_nix_strings(arguments.trim_start_matches('#'))?,
+            None => Vec::new(),
+        };
+        return Ok(Some(GenerateInfo {
+            crate2nix_version: version.to_string(),
+            crate2nix_arguments: arguments,
+        }));
+    }
+    Ok(None)
+}
+
+/// Parses space separated strings escaped with [`escape_nix_string`].
+fn parse_nix_strings(strings: &str) -> Result<Vec<String>, Error> {
+    let mut parsed = Vec::new();
+    let mut chars = strings.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => continue,
+            '"' => {}
+            _ => bail!("expected '\"' instead of '{}' in {}", c, strings),
+        }
+        let mut string = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => string.extend(chars.next()),
+                Some(c) => string.push(c),
+                None => bail!("unterminated string in {}", strings),
+            }
+        }
+        parsed.push(string);
+    }
+    Ok(parsed)
+}
+
 trait AbstractTemplate {
     fn template(&self) -> &'static str;
     #[cfg(not(debug_assertions))]
@@ -325,3 +367,32 @@ pub fn escape_nix_string(raw_string: &str) -> String {
     ret.push('"');
     ret
 }
+
+#[test]
+fn test_read_generate_info() {
+    let dir = tempdir::TempDir::new("crate2nix_render").unwrap();
+    let cargo_nix = dir.path().join("Cargo.nix");
+    let info = GenerateInfo {
+        crate2nix_version: "0.14.1".to_string(),
+        crate2nix_arguments: vec![
+            "generate".to_string(),
+            "-n".to_string(),
+            "<nixpkgs> \"quoted\" ${x}".to_string(),
+        ],
+    };
+    let header = format!(
+        "# This file was @generated by crate2nix {} with the command:\n#  {}\n{{ }}\n",
+        info.crate2nix_version,
+        info.crate2nix_arguments
+            .iter()
+            .map(|arg| format!(" {}", escape_nix_string(arg)))
+            .join("")
+    );
+    std::fs::write(&cargo_nix, header).unwrap();
+    let read = read_generate_info(&cargo_nix).unwrap().unwrap();
+    assert_eq!(read.crate2nix_version, info.crate2nix_version);
+    assert_eq!(read.crate2nix_arguments, info.crate2nix_arguments);
+
+    std::fs::write(&cargo_nix, "{ }\n").unwrap();
+    assert!(read_generate_info(&cargo_nix).unwrap().is_none());
+}
@@ -0,0 +1,219 @@
+//! Reporting progress and warnings while generating.
+//!
+//! The library reports everything that the user might want to see through a
+//! [`Reporter`]: [`TerminalReporter`] prints human readable messages to stderr,
+//! [`JsonReporter`] writes one JSON object per [`Event`] and line.
+
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+/// A phase of generating the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// Fetching the out-of-tree sources from `crate2nix.json`.
+    FetchSources,
+    /// Calling `cargo metadata`.
+    Metadata,
+    /// Resolving the crate derivations from the metadata.
+    Resolve,
+    /// Prefetching missing hashes.
+    Prefetch,
+    /// Rendering the output.
+    Render,
+}
+
+/// What kind of file was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputKind {
+    /// The generated `Cargo.nix` or `Cargo.json`.
+    Output,
+    /// A file with prefetched hashes, e.g. `crate-hashes.json`.
+    Hashes,
+}
+
+/// Something worth reporting while generating.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Event {
+    /// A phase started.
+    PhaseStarted {
+        /// The phase.
+        phase: Phase,
+    },
+    /// A phase finished successfully.
+    PhaseFinished {
+        /// The phase.
+        phase: Phase,
+    },
+    /// Prefetching the `index`th of `total` sources without a hash started.
+    PrefetchStarted {
+        /// The index of the source, starting at 1.
+        index: usize,
+        /// The number of sources to prefetch.
+        total: usize,
+        /// The source, e.g. its URL.
+        source: String,
+    },
+    /// Prefetching a source finished successfully.
+    PrefetchFinished {
+        /// The index of the source, starting at 1.
+        index: usize,
+        /// The number of sources to prefetch.
+        total: usize,
+        /// The source, e.g. its URL.
+        source: String,
+    },
+    /// A single step, e.g. running a command, started.
+    TaskStarted {
+        /// What is being done.
+        caption: String,
+    },
+    /// A single step finished.
+    TaskFinished {
+        /// What was done.
+        caption: String,
+        /// Whether the step succeeded.
+        success: bool,
+    },
+    /// Something looks wrong but generating continues.
+    Warning {
+        /// The warning.
+        message: String,
+    },
+    /// A file was written.
+    OutputWritten {
+        /// What kind of file was written.
+        kind: OutputKind,
+        /// The path of the file.
+        path: PathBuf,
+    },
+}
+
+impl Event {
+    /// A [`Event::Warning`] with the given message.
+    pub fn warning(message: impl Into<String>) -> Event {
+        Event::Warning {
+            message: message.into(),
+        }
+    }
+}
+
+/// Receives the [`Event`]s while generating.
+pub trait Reporter: Debug + Send + Sync {
+    /// Reports the given event.
+    fn report(&self, event: &Event);
+}
+
+/// The default reporter: a [`TerminalReporter`].
+pub fn default_reporter() -> Arc<dyn Reporter> {
+    Arc::new(TerminalReporter)
+}
+
+/// Prints human readable progress and warnings to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalReporter;
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::PhaseStarted {
+                phase: Phase::FetchSources,
+            } => eprintln!("Fetching sources."),
+            Event::PhaseStarted { .. } | Event::PhaseFinished { .. } => {}
+            Event::PrefetchStarted {
+                index,
+                total,
+                source,
+            } => eprintln!("Prefetching {:>4}/{}: {}", index, total, source),
+            Event::PrefetchFinished { .. } => {}
+            Event::TaskStarted { caption } => eprint!("{}: ", caption),
+            Event::TaskFinished { success, .. } => {
+                eprintln!("{}", if *success { "done." } else { "failed." })
+            }
+            Event::Warning { message } => eprintln!("WARNING: {}", message),
+            Event::OutputWritten {
+                kind: OutputKind::Hashes,
+                path,
+            } => eprintln!("Wrote hashes to {}.", path.to_string_lossy()),
+            Event::OutputWritten {
+                kind: OutputKind::Output,
+                path,
+            } => eprintln!("Generated {} successfully.", path.to_string_lossy()),
+        }
+    }
+}
+
+/// Writes every event as a JSON object on its own line.
+pub struct JsonReporter<W> {
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonReporter<W> {
+    /// Writes the events to `out`.
+    pub fn new(out: W) -> JsonReporter<W> {
+        JsonReporter {
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Returns the writer.
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<W> Debug for JsonReporter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("JsonReporter").finish_non_exhaustive()
+    }
+}
+
+impl<W: Write + Send> Reporter for JsonReporter<W> {
+    fn report(&self, event: &Event) {
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let line = serde_json::to_string(event).expect("events to serialize");
+        // Reporting must not fail the generation, e.g. if stdout is closed.
+        let _ = writeln!(out, "{}", line).and_then(|_| out.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_events_as_json_lines() {
+        let reporter = JsonReporter::new(Vec::new());
+        reporter.report(&Event::PhaseStarted {
+            phase: Phase::Prefetch,
+        });
+        reporter.report(&Event::PrefetchStarted {
+            index: 1,
+            total: 2,
+            source: "serde 1.0.0".to_string(),
+        });
+        reporter.report(&Event::warning("careful"));
+        reporter.report(&Event::OutputWritten {
+            kind: OutputKind::Hashes,
+            path: PathBuf::from("crate-hashes.json"),
+        });
+
+        let out = String::from_utf8(reporter.into_inner()).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"event":"phase-started","phase":"prefetch"}"#,
+                r#"{"event":"prefetch-started","index":1,"total":2,"source":"serde 1.0.0"}"#,
+                r#"{"event":"warning","message":"careful"}"#,
+                r#"{"event":"output-written","kind":"hashes","path":"crate-hashes.json"}"#,
+            ]
+        );
+    }
+}
@@ -17,7 +17,9 @@ use std::collections::HashMap;
 use std::convert::Into;
 use std::path::{Path, PathBuf};
 
+use crate::licenses::NixLicense;
 use crate::metadata::IndexedMetadata;
+use crate::report::Event;
 #[cfg(test)]
 use crate::test;
 use crate::GenerateConfig;
@@ -26,7 +28,7 @@ use std::{collections::btree_map::BTreeMap, fmt::Display};
 use url::Url;
 
 /// All data necessary for creating a derivation for a crate.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CrateDerivation {
     pub package_id: PackageId,
     pub crate_name: String,
@@ -53,6 +55,27 @@ pub struct CrateDerivation {
     pub proc_macro: bool,
     /// This derivation builds the root crate or a workspace member.
     pub is_root_or_workspace_member: bool,
+    /// The package description from `Cargo.toml`.
+    pub description: Option<String>,
+    /// The package homepage from `Cargo.toml`.
+    pub homepage: Option<String>,
+    /// The package repository from `Cargo.toml`.
+    pub repository: Option<String>,
+    /// The SPDX license expression from `Cargo.toml`.
+    pub license: Option<String>,
+    /// The licenses from `license`, rendered as `meta.license`.
+    pub nix_licenses: Vec<NixLicense>,
+    /// The binary for `nix run`: set for workspace members with a single binary.
+    pub main_program: Option<String>,
+    /// Advisories affecting this crate, rendered as `meta.knownVulnerabilities`.
+    pub known_vulnerabilities: Vec<String>,
+    /// For git sources: the directory of the crate within the repository,
+    /// `None` if it is at the root or unknown.
+    pub git_subdirectory: Option<String>,
+    /// The rustc flags for the `[lints]` of workspace members.
+    pub lint_flags: Vec<String>,
+    /// The minimum supported rust version from `package.rust-version`.
+    pub rust_version: Option<Version>,
 }
 
 impl CrateDerivation {
@@ -97,10 +120,10 @@ impl CrateDerivation {
                 .and_then(|file_name| crate2nix_json.sources.get(file_name).cloned());
 
             if !crate2nix_json.sources.is_empty() && configured_source.is_none() {
-                eprintln!(
-                    "warning: Could not find configured source for workspace member {:?}",
+                config.reporter.report(&Event::warning(format!(
+                    "Could not find configured source for workspace member {:?}",
                     package_path
-                );
+                )));
             }
 
             configured_source
@@ -114,6 +137,11 @@ impl CrateDerivation {
             ResolvedSource::new(config, package, package_path)?
         };
 
+        let git_subdirectory = match source {
+            ResolvedSource::Git(_) => git_checkout_subdirectory(package_path.as_std_path()),
+            _ => None,
+        };
+
         let package_path = package_path.canonicalize().map_err(|e| {
             format_err!(
                 "while canonicalizing crate path path {}: {}",
@@ -143,7 +171,7 @@ impl CrateDerivation {
             .iter()
             .any(|t| t.kind.iter().any(|k| k == "proc-macro"));
 
-        let binaries = package
+        let binaries: Vec<BuildTarget> = package
             .targets
             .iter()
             .filter_map(|t| {
@@ -155,6 +183,18 @@ impl CrateDerivation {
             })
             .collect();
 
+        let main_program = match binaries.as_slice() {
+            [binary] if is_root_or_workspace_member => Some(binary.name.clone()),
+            _ => None,
+        };
+
+        // Like cargo, only apply lints to workspace members.
+        let lint_flags = if is_root_or_workspace_member {
+            manifest_lint_flags(package.manifest_path.as_std_path())?
+        } else {
+            Vec::new()
+        };
+
         Ok(CrateDerivation {
             crate_name: package.name.clone(),
             edition: package.edition.to_string(),
@@ -189,10 +229,179 @@ impl CrateDerivation {
             proc_macro,
             binaries,
             is_root_or_workspace_member,
+            description: package.description.clone(),
+            homepage: package.homepage.clone(),
+            repository: package.repository.clone(),
+            license: package.license.clone(),
+            nix_licenses: package
+                .license
+                .as_deref()
+                .map(NixLicense::from_expression)
+                .unwrap_or_default(),
+            main_program,
+            known_vulnerabilities: Vec::new(),
+            git_subdirectory,
+            lint_flags,
+            rust_version: package.rust_version.clone(),
         })
     }
 }
 
+/// The level and priority of a lint in a `[lints]` table, e.g. `unsafe_code = "forbid"`
+/// or `missing_docs = { level = "warn", priority = -1 }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LintSetting {
+    Level(String),
+    Table {
+        level: String,
+        #[serde(default)]
+        priority: i8,
+    },
+}
+
+/// The lints by tool (e.g. `rust` or `clippy`) and lint name.
+type Lints = BTreeMap<String, BTreeMap<String, LintSetting>>;
+
+/// Returns the rustc flags for the `[lints]` of the given manifest. Follows
+/// `lints.workspace = true` to the `[workspace.lints]` of the workspace root.
+fn manifest_lint_flags(manifest_path: &Path) -> Result<Vec<String>, Error> {
+    let read_manifest = |path: &Path| -> Result<toml::Value, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format_err!("while reading {}: {}", path.to_string_lossy(), e))?;
+        toml::from_str(&content)
+            .map_err(|e| format_err!("while parsing {}: {}", path.to_string_lossy(), e))
+    };
+
+    if !manifest_path.exists() {
+        return Ok(Vec::new());
+    }
+    let manifest = read_manifest(manifest_path)?;
+    let Some(mut lints) = manifest.get("lints").cloned() else {
+        return Ok(Vec::new());
+    };
+    if lints.get("workspace").and_then(toml::Value::as_bool) == Some(true) {
+        let mut workspace_lints = None;
+        for dir in manifest_path.ancestors().skip(1) {
+            let cargo_toml = dir.join("Cargo.toml");
+            if !cargo_toml.exists() {
+                continue;
+            }
+            if let Some(workspace) = read_manifest(&cargo_toml)?.get("workspace") {
+                workspace_lints = Some(
+                    workspace
+                        .get("lints")
+                        .cloned()
+                        .unwrap_or_else(|| toml::Value::Table(toml::map::Map::new())),
+                );
+                break;
+            }
+        }
+        lints = workspace_lints.ok_or_else(|| {
+            format_err!(
+                "{} uses `lints.workspace` but no workspace root was found",
+                manifest_path.to_string_lossy()
+            )
+        })?;
+    }
+    let lints: Lints = lints.try_into().map_err(|e| {
+        format_err!(
+            "invalid [lints] for {}: {}",
+            manifest_path.to_string_lossy(),
+            e
+        )
+    })?;
+    lint_flags(&lints)
+}
+
+/// Returns `-A`/`-W`/`-D`/`-F` flags for the lints, ordered by priority so
+/// that lints with a higher priority override the others.
+fn lint_flags(lints: &Lints) -> Result<Vec<String>, Error> {
+    let mut flags = Vec::new();
+    for (tool, lints) in lints {
+        for (name, setting) in lints {
+            let (level, priority) = match setting {
+                LintSetting::Level(level) => (level, 0),
+                LintSetting::Table { level, priority } => (level, *priority),
+            };
+            let flag = match level.as_str() {
+                "allow" => "-A",
+                "warn" => "-W",
+                "deny" => "-D",
+                "forbid" => "-F",
+                _ => return Err(format_err!("invalid level '{}' for lint {}", level, name)),
+            };
+            let name = if tool == "rust" {
+                name.clone()
+            } else {
+                format!("{}::{}", tool, name)
+            };
+            flags.push((priority, name, flag));
+        }
+    }
+    flags.sort();
+    Ok(flags
+        .into_iter()
+        .map(|(_, name, flag)| format!("{} {}", flag, name))
+        .collect())
+}
+
+#[test]
+fn lint_flags_by_priority() {
+    let lints: Lints = toml::from_str(
+        r#"
+        [rust]
+        unsafe_code = "forbid"
+        missing_docs = { level = "warn", priority = 1 }
+        unused = { level = "deny", priority = -1 }
+
+        [clippy]
+        all = { level = "warn", priority = -1 }
+        enum_glob_use = "allow"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        lint_flags(&lints).unwrap(),
+        vec![
+            "-W clippy::all",
+            "-D unused",
+            "-A clippy::enum_glob_use",
+            "-F unsafe_code",
+            "-W missing_docs",
+        ]
+    );
+
+    let invalid: Lints = toml::from_str("[rust]\nunsafe_code = \"sometimes\"").unwrap();
+    assert!(lint_flags(&invalid).is_err());
+}
+
+/// The directory of a crate within its git repository, derived from the location
+/// of cargo's checkout: `$CARGO_HOME/git/checkouts/<repo>-<hash>/<short rev>/<subdirectory>`.
+fn git_checkout_subdirectory(package_path: &Path) -> Option<String> {
+    let components: Vec<_> = package_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let checkouts = components.iter().rposition(|c| c == "checkouts")?;
+    let subdirectory = components.get(checkouts + 3..)?.join("/");
+    (!subdirectory.is_empty()).then_some(subdirectory)
+}
+
+#[test]
+fn git_checkout_subdirectories() {
+    let checkout = "/home/user/.cargo/git/checkouts/rust-rocksdb-a1b2c3d4e5f6a7b8/66f04df";
+    assert_eq!(
+        git_checkout_subdirectory(Path::new(&format!("{}/librocksdb-sys", checkout))),
+        Some("librocksdb-sys".to_string())
+    );
+    assert_eq!(git_checkout_subdirectory(Path::new(checkout)), None);
+    assert_eq!(
+        git_checkout_subdirectory(Path::new("/vendor/librocksdb-sys")),
+        None
+    );
+}
+
 #[test]
 pub fn minimal_resolve() {
     use cargo_metadata::{Metadata, Resolve};
@@ -211,7 +420,7 @@ pub fn minimal_resolve() {
     metadata.packages = vec![package.clone()];
     metadata.resolve = Some(resolve);
 
-    let indexed = IndexedMetadata::new_from(metadata).unwrap();
+    let indexed = IndexedMetadata::new_from(metadata, &crate::report::TerminalReporter).unwrap();
 
     println!("indexed: {:#?}", indexed);
 
@@ -238,6 +447,62 @@ pub fn minimal_resolve() {
     package.close().unwrap();
 }
 
+#[test]
+pub fn package_metadata_for_meta() {
+    use cargo_metadata::{Metadata, Resolve};
+
+    let mut package = test::bin_package("main", "1.2.3");
+    let src_dir = package.manifest_path.parent().unwrap().join("src");
+    std::fs::create_dir(&src_dir).unwrap();
+    std::fs::write(src_dir.join("main.rs"), "").unwrap();
+    package.description = Some("The main binary.".to_string());
+    package.repository = Some("https://example.com/main".to_string());
+    package.license = Some("MIT/Foo-1.0".to_string());
+
+    let mut resolve: Resolve = test::empty_resolve();
+    resolve.root = Some(package.id.clone());
+    resolve.nodes = vec![test::node(&package.id.repr)];
+    let mut metadata: Metadata = test::empty_metadata();
+    metadata.workspace_members = vec![package.id.clone()];
+    metadata.packages = vec![package.clone()];
+    metadata.resolve = Some(resolve);
+    let indexed = IndexedMetadata::new_from(metadata, &crate::report::TerminalReporter).unwrap();
+
+    let crate_derivation = CrateDerivation::resolve(
+        &test::generate_config(),
+        &crate::config::Config::default(),
+        &indexed,
+        indexed.root_package().expect("root package"),
+    )
+    .unwrap();
+
+    assert_eq!(
+        crate_derivation.description.as_deref(),
+        Some("The main binary.")
+    );
+    assert_eq!(crate_derivation.homepage, None);
+    assert_eq!(
+        crate_derivation.repository.as_deref(),
+        Some("https://example.com/main")
+    );
+    assert_eq!(
+        crate_derivation.nix_licenses,
+        vec![
+            NixLicense {
+                attr: Some("mit".to_string()),
+                spdx_id: "MIT".to_string()
+            },
+            NixLicense {
+                attr: None,
+                spdx_id: "Foo-1.0".to_string()
+            },
+        ]
+    );
+    assert_eq!(crate_derivation.main_program.as_deref(), Some("main"));
+
+    package.close().unwrap();
+}
+
 #[test]
 pub fn configured_source_is_used_instead_of_local_directory() {
     use std::convert::TryInto;
@@ -332,7 +597,7 @@ pub fn double_crate_with_rename() {
 }
 
 /// A build target of a crate.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BuildTarget {
     /// The name of the build target.
     pub name: String,
@@ -363,18 +628,23 @@ pub enum ResolvedSource {
     CratesIo(CratesIoSource),
     Registry(RegistrySource),
     Git(GitSource),
+    Tarball(TarballSource),
     LocalDirectory(LocalDirectorySource),
     Nix(NixSource),
 }
 
 impl From<crate::config::Source> for ResolvedSource {
     fn from(source: crate::config::Source) -> Self {
+        // Hashes are nix-base32 internally but crate2nix.json might contain SRI hashes.
+        let normalize = |sha256: String| crate::hashes::to_nix_base32(&sha256).unwrap_or(sha256);
         match source {
-            crate::config::Source::Git { url, rev, sha256 } => ResolvedSource::Git(GitSource {
+            crate::config::Source::Git {
+                url, rev, sha256, ..
+            } => ResolvedSource::Git(GitSource {
                 url,
                 rev,
                 r#ref: None,
-                sha256: Some(sha256),
+                sha256: Some(normalize(sha256)),
             }),
             crate::config::Source::CratesIo {
                 name,
@@ -383,7 +653,7 @@ impl From<crate::config::Source> for ResolvedSource {
             } => ResolvedSource::CratesIo(CratesIoSource {
                 name,
                 version,
-                sha256: Some(sha256),
+                sha256: Some(normalize(sha256)),
             }),
             crate::config::Source::Registry {
                 name,
@@ -393,9 +663,28 @@ impl From<crate::config::Source> for ResolvedSource {
             } => ResolvedSource::Registry(RegistrySource {
                 name,
                 version,
-                sha256: Some(sha256),
+                sha256: Some(normalize(sha256)),
                 registry: registry.parse().unwrap(),
             }),
+            crate::config::Source::Tarball {
+                url,
+                sha256,
+                strip_prefix,
+            } => ResolvedSource::Tarball(TarballSource {
+                url,
+                sha256: Some(normalize(sha256)),
+                strip_prefix,
+            }),
+            crate::config::Source::Path { path } => {
+                // Relative to crate2nix.json which is usually next to Cargo.nix.
+                let path = PathBuf::from(path);
+                let path = if path.is_absolute() || path.starts_with("..") {
+                    path
+                } else {
+                    PathBuf::from("./").join(path)
+                };
+                ResolvedSource::LocalDirectory(LocalDirectorySource { path })
+            }
             crate::config::Source::Nix { file, attr } => {
                 ResolvedSource::Nix(NixSource { file, attr })
             }
@@ -426,6 +715,14 @@ pub struct GitSource {
     pub sha256: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct TarballSource {
+    pub url: Url,
+    pub sha256: Option<String>,
+    /// The directory within the unpacked tarball.
+    pub strip_prefix: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct LocalDirectorySource {
     path: PathBuf,
@@ -526,8 +823,8 @@ impl ResolvedSource {
         warning: &str,
     ) -> Result<ResolvedSource, Error> {
         let path = Self::relative_directory(config, package_path)?;
-        eprintln!(
-            "WARNING: {} Falling back to local directory for crate {} with source {}: {}",
+        config.reporter.report(&Event::warning(format!(
+            "{} Falling back to local directory for crate {} with source {}: {}",
             warning,
             package.id,
             package
@@ -536,7 +833,7 @@ impl ResolvedSource {
                 .map(std::string::ToString::to_string)
                 .unwrap_or_else(|| "N/A".to_string()),
             &path.to_string_lossy()
-        );
+        )));
         Ok(ResolvedSource::LocalDirectory(LocalDirectorySource {
             path,
         }))
@@ -591,7 +888,8 @@ impl ResolvedSource {
         match self {
             Self::CratesIo(CratesIoSource { sha256, .. })
             | Self::Registry(RegistrySource { sha256, .. })
-            | Self::Git(GitSource { sha256, .. }) => sha256.as_ref(),
+            | Self::Git(GitSource { sha256, .. })
+            | Self::Tarball(TarballSource { sha256, .. }) => sha256.as_ref(),
             _ => None,
         }
     }
@@ -610,6 +908,10 @@ impl ResolvedSource {
                 sha256: Some(sha256),
                 ..source.clone()
             }),
+            Self::Tarball(source) => Self::Tarball(TarballSource {
+                sha256: Some(sha256),
+                ..source.clone()
+            }),
             _ => self.clone(),
         }
     }
@@ -628,6 +930,10 @@ impl ResolvedSource {
                 sha256: None,
                 ..source.clone()
             }),
+            Self::Tarball(source) => Self::Tarball(TarballSource {
+                sha256: None,
+                ..source.clone()
+            }),
             _ => self.clone(),
         }
     }
@@ -639,6 +945,7 @@ impl Display for ResolvedSource {
             Self::CratesIo(source) => source.fmt(f),
             Self::Registry(source) => source.fmt(f),
             Self::Git(source) => source.fmt(f),
+            Self::Tarball(source) => source.fmt(f),
             Self::LocalDirectory(source) => source.fmt(f),
             Self::Nix(source) => source.fmt(f),
         }
@@ -686,6 +993,16 @@ impl Display for GitSource {
     }
 }
 
+impl Display for TarballSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(strip_prefix) = self.strip_prefix.as_ref() {
+            write!(f, "{} directory: {}", self.url, strip_prefix)
+        } else {
+            write!(f, "{}", self.url)
+        }
+    }
+}
+
 impl Display for LocalDirectorySource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.path.to_str().unwrap())
@@ -974,7 +1291,7 @@ pub fn resolved_dependencies_filtered_dependencies_with_double_crate() {
     env.close();
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ResolvedDependency {
     pub name: String,
     /// New name for the dependency if it is renamed.
@@ -0,0 +1,497 @@
+//! Software bills of materials (SBOMs) for the crates of a build.
+//!
+//! Supports [CycloneDX 1.5](https://cyclonedx.org/docs/1.5/json/) and
+//! [SPDX 2.3](https://spdx.github.io/spdx-spec/v2.3/) in their JSON encodings.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, format_err, Error};
+use cargo_metadata::Package;
+use itertools::Itertools;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::json_output::{to_resolved_workspace, ResolvedCrate, ResolvedWorkspace, SourceInfo};
+use crate::BuildInfo;
+
+/// The supported SBOM formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON.
+    CycloneDxJson,
+    /// SPDX 2.3 JSON.
+    SpdxJson,
+}
+
+impl FromStr for SbomFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cyclonedx-json" => Ok(SbomFormat::CycloneDxJson),
+            "spdx-json" => Ok(SbomFormat::SpdxJson),
+            _ => bail!(
+                "Unknown SBOM format '{}'. Use 'cyclonedx-json' or 'spdx-json'.",
+                s
+            ),
+        }
+    }
+}
+
+/// A crate of the SBOM with everything needed to describe it.
+struct Component<'a> {
+    package_id: &'a str,
+    resolved: &'a ResolvedCrate,
+    package: Option<&'a Package>,
+    is_member: bool,
+}
+
+/// Returns the SBOM for the given workspace member or, if `member` is `None`,
+/// for the root crate or all workspace members.
+///
+/// Only crates that are needed to build the selected crates are included,
+/// dev-dependencies are left out.
+pub fn sbom(
+    build_info: &BuildInfo,
+    member: Option<&str>,
+    format: SbomFormat,
+) -> Result<Value, Error> {
+    let workspace = to_resolved_workspace(build_info);
+    let roots: Vec<&str> = match member {
+        Some(member) => vec![workspace
+            .workspace_members
+            .get(member)
+            .ok_or_else(|| {
+                format_err!(
+                    "'{}' is not a workspace member, expected one of: {}",
+                    member,
+                    workspace.workspace_members.keys().join(", ")
+                )
+            })?
+            .as_str()],
+        None => match &workspace.root {
+            Some(root) => vec![root.as_str()],
+            None => workspace
+                .workspace_members
+                .values()
+                .map(|id| id.as_str())
+                .collect(),
+        },
+    };
+
+    let packages: BTreeMap<&str, &Package> = build_info
+        .indexed_metadata
+        .pkgs_by_id
+        .iter()
+        .map(|(package_id, package)| (package_id.repr.as_str(), package))
+        .collect();
+    let members: BTreeSet<&str> = workspace
+        .workspace_members
+        .values()
+        .map(|id| id.as_str())
+        .collect();
+    let components: Vec<Component> = workspace
+        .closure_of(roots.iter().copied())
+        .into_iter()
+        .map(|package_id| Component {
+            package_id,
+            resolved: &workspace.crates[package_id],
+            package: packages.get(package_id).copied(),
+            is_member: members.contains(package_id),
+        })
+        .collect();
+
+    let timestamp = rfc3339_timestamp(creation_time());
+    Ok(match format {
+        SbomFormat::CycloneDxJson => cyclonedx(&workspace, &roots, &components, &timestamp),
+        SbomFormat::SpdxJson => spdx(&workspace, &roots, &components, &timestamp),
+    })
+}
+
+fn cyclonedx(
+    workspace: &ResolvedWorkspace,
+    roots: &[&str],
+    components: &[Component],
+    timestamp: &str,
+) -> Value {
+    let component_json = |c: &Component| {
+        let mut json = json!({
+            "type": if c.is_member { "application" } else { "library" },
+            "bom-ref": c.package_id,
+            "name": c.resolved.crate_name,
+            "version": c.resolved.version,
+            "purl": purl(c.resolved),
+        });
+        if let Some(license) = c.package.and_then(license_expression) {
+            json["licenses"] = json!([{ "expression": license }]);
+        }
+        if let Some(hash) = sha256_hex(c.resolved) {
+            json["hashes"] = json!([{ "alg": "SHA-256", "content": hash }]);
+        }
+        if let Some(description) = c.package.and_then(|p| p.description.as_ref()) {
+            json["description"] = json!(description);
+        }
+        let external_references: Vec<Value> = c
+            .package
+            .into_iter()
+            .flat_map(|p| {
+                [("vcs", &p.repository), ("website", &p.homepage)]
+                    .into_iter()
+                    .filter_map(|(kind, url)| {
+                        url.as_ref().map(|url| json!({ "type": kind, "url": url }))
+                    })
+            })
+            .collect();
+        if !external_references.is_empty() {
+            json["externalReferences"] = json!(external_references);
+        }
+        json
+    };
+
+    let mut metadata = json!({
+        "timestamp": timestamp,
+        "tools": {
+            "components": [{
+                "type": "application",
+                "name": "crate2nix",
+                "version": env!("CARGO_PKG_VERSION"),
+            }],
+        },
+    });
+    if let [root] = roots {
+        if let Some(root) = components.iter().find(|c| c.package_id == *root) {
+            metadata["component"] = component_json(root);
+        }
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": metadata,
+        "components": components
+            .iter()
+            .filter(|c| !(roots.len() == 1 && c.package_id == roots[0]))
+            .map(component_json)
+            .collect::<Vec<_>>(),
+        "dependencies": components
+            .iter()
+            .map(|c| json!({
+                "ref": c.package_id,
+                "dependsOn": build_dependencies(workspace, c.resolved),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn spdx(
+    workspace: &ResolvedWorkspace,
+    roots: &[&str],
+    components: &[Component],
+    timestamp: &str,
+) -> Value {
+    let mut spdx_ids = BTreeMap::new();
+    let mut used_ids = BTreeSet::new();
+    for c in components {
+        let base = format!(
+            "SPDXRef-Package-{}-{}",
+            c.resolved.crate_name, c.resolved.version
+        )
+        .replace(
+            |ch: char| !ch.is_ascii_alphanumeric() && ch != '-' && ch != '.',
+            "-",
+        );
+        let mut id = base.clone();
+        let mut suffix = 2;
+        while !used_ids.insert(id.clone()) {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        spdx_ids.insert(c.package_id, id);
+    }
+
+    let packages: Vec<Value> = components
+        .iter()
+        .map(|c| {
+            let license = c
+                .package
+                .and_then(license_expression)
+                .unwrap_or_else(|| "NOASSERTION".to_string());
+            let mut json = json!({
+                "SPDXID": spdx_ids[c.package_id],
+                "name": c.resolved.crate_name,
+                "versionInfo": c.resolved.version,
+                "downloadLocation": download_location(c.resolved),
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": license,
+                "copyrightText": "NOASSERTION",
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl(c.resolved),
+                }],
+            });
+            if let Some(hash) = sha256_hex(c.resolved) {
+                json["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": hash }]);
+            }
+            if let Some(package) = c.package {
+                if let Some(description) = &package.description {
+                    json["summary"] = json!(description);
+                }
+                if let Some(homepage) = &package.homepage {
+                    json["homepage"] = json!(homepage);
+                }
+            }
+            json
+        })
+        .collect();
+
+    let mut relationships: Vec<Value> = roots
+        .iter()
+        .map(|root| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": spdx_ids[root],
+            })
+        })
+        .collect();
+    for c in components {
+        for dependency in build_dependencies(workspace, c.resolved) {
+            relationships.push(json!({
+                "spdxElementId": spdx_ids[c.package_id],
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_ids[dependency],
+            }));
+        }
+    }
+
+    let name = roots
+        .iter()
+        .map(|root| {
+            let c = &workspace.crates[*root];
+            format!("{}-{}", c.crate_name, c.version)
+        })
+        .join("+");
+    // Deterministic, so that the same build results in the same document.
+    let namespace_hash = hex::encode(Sha256::digest(
+        components.iter().map(|c| c.package_id).join("\n"),
+    ));
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": name,
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", name, namespace_hash),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: crate2nix-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+/// The package IDs of the normal and build dependencies that are part of the workspace.
+fn build_dependencies<'a>(workspace: &ResolvedWorkspace, c: &'a ResolvedCrate) -> Vec<&'a str> {
+    c.dependencies
+        .iter()
+        .chain(&c.build_dependencies)
+        .map(|d| d.package_id.as_str())
+        .filter(|id| workspace.crates.contains_key(*id))
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// The package URL, see <https://github.com/package-url/purl-spec>.
+fn purl(c: &ResolvedCrate) -> String {
+    let base = format!("pkg:cargo/{}@{}", c.crate_name, c.version);
+    match &c.source {
+        Some(SourceInfo::Git { url, rev, .. }) => {
+            format!(
+                "{}?vcs_url={}",
+                base,
+                percent_encode(&format!("git+{}@{}", url, rev))
+            )
+        }
+        _ => base,
+    }
+}
+
+fn download_location(c: &ResolvedCrate) -> String {
+    match &c.source {
+        Some(SourceInfo::CratesIo) => format!(
+            "https://crates.io/api/v1/crates/{}/{}/download",
+            c.crate_name, c.version
+        ),
+        Some(SourceInfo::Git { url, rev, .. }) => format!("git+{}@{}", url, rev),
+        Some(SourceInfo::Tarball { url, .. }) => url.clone(),
+        _ => "NOASSERTION".to_string(),
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' | '_' | '~' | '/' | '+' | ':' => {
+                c.to_string()
+            }
+            _ => format!("%{:02X}", c as u32),
+        })
+        .collect()
+}
+
+/// The SPDX license expression of the package.
+///
+/// Old style "MIT/Apache-2.0" licenses are converted to "MIT OR Apache-2.0".
+fn license_expression(package: &Package) -> Option<String> {
+    let license = package.license.as_ref()?;
+    Some(license.split('/').map(|l| l.trim()).join(" OR "))
+}
+
+/// The nix sha256 of the crate source, hex encoded.
+fn sha256_hex(c: &ResolvedCrate) -> Option<String> {
+    let bytes = crate::util::from_nix_base32(c.sha256.as_ref()?)?;
+    Some(hex::encode(bytes))
+}
+
+/// `SOURCE_DATE_EPOCH` if set for reproducible output, otherwise the current time.
+fn creation_time() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        })
+}
+
+/// Formats seconds since the epoch as e.g. "2024-02-29T12:00:00Z".
+fn rfc3339_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_build_closure_without_dev_dependencies() {
+        use crate::test;
+        use cargo_metadata::DependencyKind;
+
+        let mut env = test::MetadataEnv::default();
+        let mut main = env.add_package_and_node("main");
+        main.make_root();
+        main.add_dependency("log")
+            .update_package(|p| p.license = Some("MIT".to_string()));
+        main.add_dependency("tempdir")
+            .update_package_dep(|d| d.kind = DependencyKind::Development)
+            .update_node_dep(|d| d.dep_kinds[0].kind = DependencyKind::Development);
+
+        let build_info = BuildInfo::new(
+            &crate::GenerateInfo::default(),
+            &test::generate_config(),
+            env.indexed_metadata(),
+        )
+        .unwrap();
+
+        let cyclonedx = sbom(&build_info, None, SbomFormat::CycloneDxJson).unwrap();
+        assert_eq!(cyclonedx["metadata"]["component"]["bom-ref"], "main");
+        assert_eq!(
+            cyclonedx["components"],
+            json!([{
+                "type": "library",
+                "bom-ref": "log",
+                "name": "log",
+                "version": "0.1.0",
+                "purl": "pkg:cargo/log@0.1.0",
+                "licenses": [{ "expression": "MIT" }],
+            }])
+        );
+        assert_eq!(
+            cyclonedx["dependencies"],
+            json!([
+                { "ref": "log", "dependsOn": [] },
+                { "ref": "main", "dependsOn": ["log"] },
+            ])
+        );
+
+        let spdx = sbom(&build_info, Some("main"), SbomFormat::SpdxJson).unwrap();
+        assert_eq!(spdx["packages"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            spdx["relationships"],
+            json!([
+                {
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": "SPDXRef-Package-main-0.1.0",
+                },
+                {
+                    "spdxElementId": "SPDXRef-Package-main-0.1.0",
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": "SPDXRef-Package-log-0.1.0",
+                },
+            ])
+        );
+
+        env.close();
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339_timestamp(951825600), "2000-02-29T12:00:00Z");
+        assert_eq!(rfc3339_timestamp(1709251199), "2024-02-29T23:59:59Z");
+    }
+
+    #[test]
+    fn converts_licenses_and_purls() {
+        let mut package = crate::test::package("foo", "1.0.0");
+        package.license = Some("MIT/Apache-2.0".to_string());
+        assert_eq!(
+            license_expression(&package).as_deref(),
+            Some("MIT OR Apache-2.0")
+        );
+        package.close().unwrap();
+
+        let git_crate: ResolvedCrate = serde_json::from_value(json!({
+            "crateName": "foo",
+            "version": "1.0.0",
+            "edition": "2021",
+            "source": { "type": "git", "url": "https://example.com/foo.git", "rev": "abc" },
+        }))
+        .unwrap();
+        assert_eq!(
+            purl(&git_crate),
+            "pkg:cargo/foo@1.0.0?vcs_url=git+https://example.com/foo.git%40abc"
+        );
+    }
+}
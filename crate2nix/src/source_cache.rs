@@ -0,0 +1,469 @@
+//! A content-addressed cache of the out-of-tree sources in `crate2nix.json`.
+//!
+//! Entries are stored by the nix-base32 sha256 recorded for the source, so they
+//! are shared between projects. A fetched source is only added to the cache if
+//! it matches that hash:
+//!
+//! * crates from crates.io or other registries by the hash of the archive,
+//! * git checkouts and unpacked tarballs by their NAR hash, like `fetchgit` and
+//!   `fetchzip` in nix.
+
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, format_err, Context, Error};
+use sha2::{Digest, Sha256};
+
+use crate::config::Source;
+use crate::error::CommandFailure;
+use crate::hashes;
+
+/// A directory with fetched sources by their hash.
+#[derive(Debug)]
+pub struct SourceCache {
+    dir: PathBuf,
+}
+
+impl SourceCache {
+    /// The cache in `$XDG_CACHE_HOME/crate2nix/sources` (or `~/.cache/...`).
+    ///
+    /// Returns `None` if neither variable is set.
+    pub fn in_user_cache_dir() -> Option<SourceCache> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(SourceCache::new(
+            cache_home.join("crate2nix").join("sources"),
+        ))
+    }
+
+    /// A cache in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> SourceCache {
+        SourceCache { dir: dir.into() }
+    }
+
+    /// Returns the directory of the unpacked source, fetching it if it is not
+    /// cached yet.
+    ///
+    /// `Nix` and `Path` sources are not fetched by crate2nix.
+    pub fn get_or_fetch(&self, source: &Source) -> Result<PathBuf, Error> {
+        let (sha256, strip_prefix) = match source {
+            Source::CratesIo { sha256, .. }
+            | Source::Registry { sha256, .. }
+            | Source::Git { sha256, .. } => (sha256, None),
+            Source::Tarball {
+                sha256,
+                strip_prefix,
+                ..
+            } => (sha256, strip_prefix.as_deref()),
+            Source::Nix { .. } | Source::Path { .. } => {
+                bail!("Cannot fetch {} without nix.", source)
+            }
+        };
+
+        let expected = hashes::to_nix_base32(sha256)?;
+        let entry = self.dir.join(&expected);
+        if !entry.is_dir() {
+            self.fetch(source, &expected, &entry)
+                .with_context(|| format!("while fetching {}", source))?;
+        }
+
+        Ok(match strip_prefix {
+            Some(prefix) => entry.join(prefix),
+            None => entry,
+        })
+    }
+
+    /// Fetches the source into a temporary directory and moves it to `entry`
+    /// if it has the `expected` hash.
+    fn fetch(&self, source: &Source, expected: &str, entry: &Path) -> Result<(), Error> {
+        let tmp = self
+            .dir
+            .join(format!(".tmp-{}-{}", expected, std::process::id()));
+        if tmp.exists() {
+            std::fs::remove_dir_all(&tmp)?;
+        }
+        std::fs::create_dir_all(&tmp)
+            .with_context(|| format!("while creating {}", tmp.to_string_lossy()))?;
+
+        let out = tmp.join("out");
+        let result = fetch_into(source, &tmp, &out).and_then(|actual| {
+            if actual != expected {
+                bail!("Hash mismatch: expected {} but got {}.", expected, actual);
+            }
+            match std::fs::rename(&out, entry) {
+                // Fetched concurrently by another crate2nix.
+                Err(_) if entry.is_dir() => Ok(()),
+                result => result
+                    .with_context(|| format!("while moving source to {}", entry.to_string_lossy())),
+            }
+        });
+        std::fs::remove_dir_all(&tmp).ok();
+        result
+    }
+}
+
+/// Fetches the source to `out` and returns its nix-base32 hash. `tmp` is a
+/// scratch directory.
+fn fetch_into(source: &Source, tmp: &Path, out: &Path) -> Result<String, Error> {
+    match source {
+        Source::CratesIo { name, version, .. } => {
+            let url = format!(
+                "https://crates.io/api/v1/crates/{}/{}/download",
+                name, version
+            );
+            fetch_crate(&url, tmp, out)
+        }
+        Source::Registry {
+            registry,
+            name,
+            version,
+            sha256,
+        } => {
+            let url = registry_download_url(registry, name, &version.to_string(), sha256, tmp)?;
+            fetch_crate(&url, tmp, out)
+        }
+        Source::Git { url, rev, .. } => {
+            run(Command::new("git")
+                .args(["clone", "-q", "--no-checkout", url.as_str()])
+                .arg(out))?;
+            run(Command::new("git")
+                .current_dir(out)
+                .args(["checkout", "-q", rev]))?;
+            run(Command::new("git").current_dir(out).args([
+                "submodule",
+                "update",
+                "-q",
+                "--init",
+                "--recursive",
+            ]))?;
+            remove_dot_git(out)?;
+            nar_sha256(out)
+        }
+        Source::Tarball { url, .. } => {
+            let archive = tmp.join("archive");
+            download(url.as_str(), &archive)?;
+            let unpacked = tmp.join("unpacked");
+            std::fs::create_dir(&unpacked)?;
+            if url.path().ends_with(".zip") {
+                run(Command::new("unzip")
+                    .arg("-q")
+                    .arg(&archive)
+                    .arg("-d")
+                    .arg(&unpacked))?;
+            } else {
+                run(Command::new("tar")
+                    .arg("-xf")
+                    .arg(&archive)
+                    .arg("-C")
+                    .arg(&unpacked))?;
+            }
+            // Like `fetchzip`, use the single top-level directory of the archive.
+            let entries = std::fs::read_dir(&unpacked)?.collect::<Result<Vec<_>, _>>()?;
+            match entries.as_slice() {
+                [root] if root.file_type()?.is_dir() => std::fs::rename(root.path(), out)?,
+                _ => bail!(
+                    "The archive at {} does not contain a single directory.",
+                    url
+                ),
+            }
+            nar_sha256(out)
+        }
+        Source::Nix { .. } | Source::Path { .. } => unreachable!("not fetched by crate2nix"),
+    }
+}
+
+/// Downloads and unpacks a `.crate` archive and returns the hash of the archive.
+fn fetch_crate(url: &str, tmp: &Path, out: &Path) -> Result<String, Error> {
+    let archive = tmp.join("archive.crate");
+    download(url, &archive)?;
+    let sha256 = Sha256::digest(std::fs::read(&archive)?);
+    std::fs::create_dir(out)?;
+    run(Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("--strip-components=1")
+        .arg("-C")
+        .arg(out))?;
+    Ok(nix_base32::to_nix_base32(&sha256))
+}
+
+/// The download URL of a crate from the `dl` template in the `config.json` of
+/// the registry index.
+fn registry_download_url(
+    registry: &str,
+    name: &str,
+    version: &str,
+    sha256: &str,
+    tmp: &Path,
+) -> Result<String, Error> {
+    let index = registry.strip_prefix("sparse+").unwrap_or(registry);
+    let config_json = tmp.join("config.json");
+    download(
+        &format!("{}/config.json", index.trim_end_matches('/')),
+        &config_json,
+    )?;
+    let config: serde_json::Value = serde_json::from_slice(&std::fs::read(&config_json)?)?;
+    let dl = config["dl"]
+        .as_str()
+        .ok_or_else(|| format_err!("No 'dl' in the config.json of {}.", registry))?;
+    Ok(expand_dl_template(
+        dl,
+        name,
+        version,
+        &hex::encode(hashes::sha256_digest(sha256)?),
+    ))
+}
+
+/// Expands the markers of a registry `dl` template as described in
+/// <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration>.
+fn expand_dl_template(dl: &str, name: &str, version: &str, sha256_hex: &str) -> String {
+    const MARKERS: [&str; 5] = [
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        return format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, version);
+    }
+
+    let prefix = match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    };
+    dl.replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{sha256-checksum}", sha256_hex)
+}
+
+fn download(url: &str, path: &Path) -> Result<(), Error> {
+    run(Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "-o"])
+        .arg(path)
+        .arg(url))
+}
+
+/// Runs the command and fails with its error output if it does not succeed.
+fn run(command: &mut Command) -> Result<(), Error> {
+    let output = command
+        .output()
+        .map_err(|e| format_err!("while spawning {:?}: {}", command, e))?;
+    if !output.status.success() {
+        return Err(CommandFailure::new(command, &output).into());
+    }
+    Ok(())
+}
+
+/// Removes all `.git` directories and files, including those of submodules.
+fn remove_dot_git(dir: &Path) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if entry.file_name() == ".git" {
+            if file_type.is_dir() {
+                std::fs::remove_dir_all(entry.path())?;
+            } else {
+                std::fs::remove_file(entry.path())?;
+            }
+        } else if file_type.is_dir() {
+            remove_dot_git(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// The nix-base32 sha256 of the NAR serialization of `path`.
+fn nar_sha256(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    write_nar(&mut hasher, path)
+        .with_context(|| format!("while hashing {}", path.to_string_lossy()))?;
+    Ok(nix_base32::to_nix_base32(&hasher.finalize()))
+}
+
+/// Writes the NAR serialization of `path` as `nix-store --dump` does.
+fn write_nar(out: &mut impl Write, path: &Path) -> Result<(), Error> {
+    write_nar_str(out, b"nix-archive-1")?;
+    write_nar_node(out, path)
+}
+
+fn write_nar_node(out: &mut impl Write, path: &Path) -> Result<(), Error> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    write_nar_str(out, b"(")?;
+    write_nar_str(out, b"type")?;
+    if metadata.file_type().is_symlink() {
+        write_nar_str(out, b"symlink")?;
+        write_nar_str(out, b"target")?;
+        write_nar_str(out, std::fs::read_link(path)?.as_os_str().as_bytes())?;
+    } else if metadata.is_dir() {
+        write_nar_str(out, b"directory")?;
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        for name in entries {
+            write_nar_str(out, b"entry")?;
+            write_nar_str(out, b"(")?;
+            write_nar_str(out, b"name")?;
+            write_nar_str(out, name.as_bytes())?;
+            write_nar_str(out, b"node")?;
+            write_nar_node(out, &path.join(&name))?;
+            write_nar_str(out, b")")?;
+        }
+    } else {
+        write_nar_str(out, b"regular")?;
+        if metadata.permissions().mode() & 0o100 != 0 {
+            write_nar_str(out, b"executable")?;
+            write_nar_str(out, b"")?;
+        }
+        write_nar_str(out, b"contents")?;
+        write_nar_str(out, &std::fs::read(path)?)?;
+    }
+    write_nar_str(out, b")")?;
+    Ok(())
+}
+
+/// A length-prefixed string padded to a multiple of 8 bytes.
+fn write_nar_str(out: &mut impl Write, bytes: &[u8]) -> Result<(), Error> {
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(bytes)?;
+    let padding = (8 - bytes.len() % 8) % 8;
+    out.write_all(&[0; 8][..padding])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn nar_str(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_nar_str(&mut out, bytes).unwrap();
+        out
+    }
+
+    #[test]
+    fn serializes_nar() {
+        let dir = TempDir::new("crate2nix_nar").unwrap();
+        std::fs::write(dir.path().join("hello"), "hi\n").unwrap();
+
+        let mut nar = Vec::new();
+        write_nar(&mut nar, dir.path()).unwrap();
+
+        let expected: Vec<u8> = [
+            &b"nix-archive-1"[..],
+            b"(",
+            b"type",
+            b"directory",
+            b"entry",
+            b"(",
+            b"name",
+            b"hello",
+            b"node",
+            b"(",
+            b"type",
+            b"regular",
+            b"contents",
+            b"hi\n",
+            b")",
+            b")",
+            b")",
+        ]
+        .iter()
+        .flat_map(|s| nar_str(s))
+        .collect();
+        assert_eq!(nar, expected);
+        assert_eq!(nar_str(b"hi\n"), b"\x03\0\0\0\0\0\0\0hi\n\0\0\0\0\0");
+    }
+
+    #[test]
+    fn expands_registry_dl_templates() {
+        assert_eq!(
+            expand_dl_template("https://example.com/api/v1/crates", "serde", "1.0.0", "ab"),
+            "https://example.com/api/v1/crates/serde/1.0.0/download"
+        );
+        assert_eq!(
+            expand_dl_template(
+                "https://example.com/{lowerprefix}/{crate}-{version}.crate?sha={sha256-checksum}",
+                "Serde",
+                "1.0.0",
+                "ab"
+            ),
+            "https://example.com/se/rd/Serde-1.0.0.crate?sha=ab"
+        );
+        assert_eq!(
+            expand_dl_template("https://example.com/{prefix}/{crate}", "abc", "1.0.0", "ab"),
+            "https://example.com/3/a/abc"
+        );
+    }
+
+    #[test]
+    fn verifies_and_caches_git_sources() {
+        let repo = TempDir::new("crate2nix_cache_repo").unwrap();
+        let git = |args: &[&str]| {
+            run(Command::new("git")
+                .current_dir(repo.path())
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args))
+            .unwrap()
+        };
+        git(&["init", "-q"]);
+        std::fs::write(repo.path().join("Cargo.toml"), "[package]\n").unwrap();
+        git(&["add", "Cargo.toml"]);
+        git(&["commit", "-q", "-m", "first"]);
+        let rev = String::from_utf8(
+            Command::new("git")
+                .current_dir(repo.path())
+                .args(["rev-parse", "HEAD"])
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .unwrap()
+        .trim()
+        .to_string();
+
+        let expected = {
+            let checkout = TempDir::new("crate2nix_cache_expected").unwrap();
+            std::fs::write(checkout.path().join("Cargo.toml"), "[package]\n").unwrap();
+            nar_sha256(checkout.path()).unwrap()
+        };
+        let source = |sha256: &str| Source::Git {
+            url: url::Url::from_directory_path(repo.path()).unwrap(),
+            rev: rev.clone(),
+            r#ref: None,
+            sha256: sha256.to_string(),
+        };
+
+        let cache_dir = TempDir::new("crate2nix_cache").unwrap();
+        let cache = SourceCache::new(cache_dir.path());
+
+        let stale = "0".repeat(52);
+        let error = cache.get_or_fetch(&source(&stale)).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("Hash mismatch"),
+            "{:#}",
+            error
+        );
+        assert_eq!(std::fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+
+        let fetched = cache.get_or_fetch(&source(&expected)).unwrap();
+        assert_eq!(fetched, cache_dir.path().join(&expected));
+        assert!(fetched.join("Cargo.toml").exists());
+        assert!(!fetched.join(".git").exists());
+
+        // Cached sources are not fetched again.
+        std::fs::remove_dir_all(repo.path()).unwrap();
+        assert_eq!(cache.get_or_fetch(&source(&expected)).unwrap(), fetched);
+    }
+}
@@ -3,28 +3,50 @@
 use crate::{
     config,
     prefetch::PrefetchableSource,
-    resolve::{CratesIoSource, GitSource, RegistrySource},
+    report::{Event, Phase, Reporter},
+    resolve::{CratesIoSource, GitSource, RegistrySource, TarballSource},
+    source_cache::SourceCache,
 };
 use anyhow::{bail, format_err, Context, Error};
 use semver::Version;
 use std::{
     borrow::Cow,
     path::{Path, PathBuf},
+    sync::Arc,
 };
-use std::{fs::File, io::BufRead, process::Command, time::SystemTime};
+use std::{fs::File, io::BufRead, process::Command};
 use url::Url;
 
+/// Prefetches the hash of the source, reporting the progress as a task.
+fn prefetch(
+    reporter: &dyn Reporter,
+    prefetchable: &dyn PrefetchableSource,
+) -> Result<String, Error> {
+    let caption = format!("Prefetching {}", prefetchable.to_string());
+    reporter.report(&Event::TaskStarted {
+        caption: caption.clone(),
+    });
+    let sha256 = prefetchable.prefetch();
+    reporter.report(&Event::TaskFinished {
+        caption,
+        success: sha256.is_ok(),
+    });
+    sha256.map_err(|e| crate::Error::prefetch(None, prefetchable.to_string(), e).into())
+}
+
 /// Returns the completed Source::CratesIo definition by prefetching the hash.
-pub fn crates_io_source(name: String, version: Version) -> Result<config::Source, Error> {
+pub fn crates_io_source(
+    reporter: &dyn Reporter,
+    name: String,
+    version: Version,
+) -> Result<config::Source, Error> {
     let prefetchable = CratesIoSource {
         name: name.clone(),
         version: version.clone(),
         sha256: None,
     };
 
-    eprint!("Prefetching {}: ", prefetchable);
-    let sha256 = prefetchable.prefetch()?;
-    eprintln!("done.");
+    let sha256 = prefetch(reporter, &prefetchable)?;
 
     Ok(config::Source::CratesIo {
         name,
@@ -35,6 +57,7 @@ pub fn crates_io_source(name: String, version: Version) -> Result<config::Source
 
 /// Returns the completed Source::Registry definition by prefetching the hash.
 pub fn registry_source(
+    reporter: &dyn Reporter,
     registry: String,
     name: String,
     version: Version,
@@ -46,9 +69,7 @@ pub fn registry_source(
         sha256: None,
     };
 
-    eprint!("Prefetching {}: ", prefetchable);
-    let sha256 = prefetchable.prefetch()?;
-    eprintln!("done.");
+    let sha256 = prefetch(reporter, &prefetchable)?;
 
     Ok(config::Source::Registry {
         registry,
@@ -59,7 +80,14 @@ pub fn registry_source(
 }
 
 /// Returns the completed Source::Git definition by prefetching the hash.
-pub fn git_io_source(url: Url, rev: String) -> Result<config::Source, Error> {
+///
+/// `ref` is the branch or tag that `crate2nix source update` follows.
+pub fn git_io_source(
+    reporter: &dyn Reporter,
+    url: Url,
+    rev: String,
+    r#ref: Option<String>,
+) -> Result<config::Source, Error> {
     let prefetchable = GitSource {
         url: url.clone(),
         rev: rev.clone(),
@@ -67,16 +95,137 @@ pub fn git_io_source(url: Url, rev: String) -> Result<config::Source, Error> {
         sha256: None,
     };
 
-    eprint!("Prefetching {}: ", prefetchable);
-    let sha256 = prefetchable.prefetch()?;
-    eprintln!("done.");
+    let sha256 = prefetch(reporter, &prefetchable)?;
 
-    Ok(config::Source::Git { url, rev, sha256 })
+    Ok(config::Source::Git {
+        url,
+        rev,
+        r#ref,
+        sha256,
+    })
+}
+
+/// Returns the completed Source::Tarball definition by prefetching the hash of
+/// the unpacked tarball.
+pub fn tarball_source(
+    reporter: &dyn Reporter,
+    url: Url,
+    strip_prefix: Option<String>,
+) -> Result<config::Source, Error> {
+    let prefetchable = TarballSource {
+        url: url.clone(),
+        sha256: None,
+        strip_prefix: strip_prefix.clone(),
+    };
+
+    let sha256 = prefetch(reporter, &prefetchable)?;
+
+    Ok(config::Source::Tarball {
+        url,
+        sha256,
+        strip_prefix,
+    })
+}
+
+/// Returns the commit that the given branch or tag points to in the remote
+/// repository, via `git ls-remote`.
+pub fn resolve_git_ref(url: &Url, r#ref: &str) -> Result<String, Error> {
+    // Annotated tags are listed as "<tag>^{}" for the commit that they point to.
+    let peeled = format!("{}^{{}}", r#ref);
+    let output = Command::new("git")
+        .args(["ls-remote", url.as_str(), r#ref, &peeled])
+        .output()
+        .map_err(|e| format_err!("while spawning 'git ls-remote {}': {}", url, e))?;
+    if !output.status.success() {
+        bail!(
+            "'git ls-remote {} {}' failed: {}",
+            url,
+            r#ref,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    let refs: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    let candidates = [
+        format!("refs/heads/{}", r#ref),
+        format!("refs/tags/{}^{{}}", r#ref),
+        format!("refs/tags/{}", r#ref),
+        r#ref.to_string(),
+    ];
+    candidates
+        .iter()
+        .find_map(|candidate| {
+            refs.iter()
+                .find(|(_, name)| name == candidate)
+                .map(|(rev, _)| rev.to_string())
+        })
+        .ok_or_else(|| format_err!("Did not find branch or tag '{}' in {}.", r#ref, url))
+}
+
+/// A git source that was moved to a new revision of its ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceUpdate {
+    /// The name of the source.
+    pub name: String,
+    /// The revision before the update.
+    pub old_rev: String,
+    /// The current revision of the ref.
+    pub new_rev: String,
+}
+
+/// Moves the git sources with a `ref` to the commit that the ref currently points
+/// to and prefetches them again. Only updates the source called `name` if given.
+///
+/// Returns the sources with a new revision.
+pub fn update_git_sources(
+    reporter: &dyn Reporter,
+    config: &mut config::Config,
+    name: Option<&str>,
+) -> Result<Vec<SourceUpdate>, Error> {
+    if let Some(name) = name {
+        match config.sources.get(name) {
+            Some(config::Source::Git { r#ref: Some(_), .. }) => {}
+            Some(_) => bail!("Source '{}' is not a git source with a ref.", name),
+            None => bail!("Source '{}' not found.", name),
+        }
+    }
+
+    let mut updates = Vec::new();
+    for (source_name, source) in config.sources.iter_mut() {
+        if name.is_some_and(|name| name != source_name) {
+            continue;
+        }
+        let config::Source::Git {
+            url,
+            rev,
+            r#ref: Some(r#ref),
+            ..
+        } = source
+        else {
+            continue;
+        };
+        let new_rev = resolve_git_ref(url, r#ref)?;
+        if new_rev == *rev {
+            continue;
+        }
+        updates.push(SourceUpdate {
+            name: source_name.clone(),
+            old_rev: rev.clone(),
+            new_rev: new_rev.clone(),
+        });
+        *source = git_io_source(reporter, url.clone(), new_rev, Some(r#ref.clone()))?;
+    }
+    Ok(updates)
 }
 
 /// Operations on assmebling out-of-tree sources via nix.
 pub struct FetchedSources<'a> {
     crate2nix_json_path: Cow<'a, Path>,
+    info: crate::GenerateInfo,
+    reporter: Arc<dyn Reporter>,
 }
 
 const FETCHED_SOURCES: &str = "crate2nix-sources";
@@ -86,9 +235,21 @@ impl<'a> FetchedSources<'a> {
     pub fn new<P: Into<Cow<'a, Path>>>(path: P) -> FetchedSources<'a> {
         FetchedSources {
             crate2nix_json_path: path.into(),
+            info: crate::GenerateInfo::default(),
+            reporter: crate::report::default_reporter(),
         }
     }
 
+    /// Uses the given info for the generated `crate2nix-sources.nix`.
+    pub fn with_generate_info(self, info: crate::GenerateInfo) -> FetchedSources<'a> {
+        FetchedSources { info, ..self }
+    }
+
+    /// Reports the progress of fetching to the given reporter.
+    pub fn with_reporter(self, reporter: Arc<dyn Reporter>) -> FetchedSources<'a> {
+        FetchedSources { reporter, ..self }
+    }
+
     fn project_dir(&self) -> PathBuf {
         self.crate2nix_json_path
             .parent()
@@ -102,8 +263,6 @@ impl<'a> FetchedSources<'a> {
 
     /// Create a config-nix if it doesn't exist yet.
     pub fn regenerate_sources_nix(&self) -> Result<(), Error> {
-        let info = crate::GenerateInfo::default();
-
         if !self.crate2nix_json_path.exists() {
             bail!(
                 "Did not find config at '{}'.",
@@ -118,61 +277,108 @@ impl<'a> FetchedSources<'a> {
                     .unwrap_or(false)
             });
             if !generated {
-                bail!("Cowardly refusing to overwrite sources.nix without generated marker.");
+                return Err(crate::Error::OverwriteGuard {
+                    path: self.sources_nix(),
+                }
+                .into());
             }
         }
 
-        crate::render::SOURCES_NIX.write_to_file(self.sources_nix(), &info)?;
+        crate::render::SOURCES_NIX.write_to_file(self.sources_nix(), &self.info)?;
 
         Ok(())
     }
 
-    /// Fetches the sources via nix.
+    /// Fetches the sources into a directory with a symlink to each source by
+    /// its name.
+    ///
+    /// Sources with a hash are fetched by crate2nix itself into the source cache
+    /// in the user's cache directory, which is shared between projects. Only
+    /// `Nix` sources are built with `nix build`.
     pub fn fetch(&self) -> Result<PathBuf, Error> {
+        self.reporter.report(&Event::PhaseStarted {
+            phase: Phase::FetchSources,
+        });
         self.regenerate_sources_nix()
             .context("while regenerating crate2nix-sources.nix")?;
 
-        let fetched_sources_symlink = self.project_dir().join(FETCHED_SOURCES);
-        download_and_link_out_of_tree_sources(
-            self.project_dir(),
-            self.sources_nix(),
-            &fetched_sources_symlink,
-            "fetchedSources",
-        )
-        .context("while building crate2nix-sources directory")?;
-
-        Ok(fetched_sources_symlink)
-    }
-
-    /// Fetches the sources via nix and returns the paths to their Cargo.tomls.
-    pub fn get_cargo_tomls(&self) -> Result<Vec<PathBuf>, Error> {
-        let fetched_sources_symlink = self.project_dir().join(FETCHED_SOURCES);
-        let last_modified: fn(&std::path::Path) -> Option<SystemTime> = |f: &std::path::Path| {
-            std::fs::symlink_metadata(f)
-                .ok()
-                .and_then(|m| m.modified().ok())
+        let fetched_sources = self.project_dir().join(FETCHED_SOURCES);
+        let cache = match SourceCache::in_user_cache_dir() {
+            Some(cache) => cache,
+            None => {
+                download_and_link_out_of_tree_sources(
+                    &*self.reporter,
+                    self.project_dir(),
+                    self.sources_nix(),
+                    &fetched_sources,
+                    "fetchedSources",
+                )
+                .context("while building crate2nix-sources directory")?;
+                self.reporter.report(&Event::PhaseFinished {
+                    phase: Phase::FetchSources,
+                });
+                return Ok(fetched_sources);
+            }
         };
 
-        let has_nix_sources = {
-            let config = crate::config::Config::read_from_or_default(&self.crate2nix_json_path)?;
-            config
-                .sources
-                .values()
-                .any(|s| matches!(s, config::Source::Nix { .. }))
-        };
-        let outdated = || {
-            let symlink_generated =
-                last_modified(&fetched_sources_symlink).unwrap_or(SystemTime::UNIX_EPOCH);
-            let sources_modified =
-                last_modified(&self.crate2nix_json_path).unwrap_or_else(SystemTime::now);
-            symlink_generated < sources_modified
-        };
-        if has_nix_sources || outdated() {
-            eprintln!("Fetching sources.");
-            self.fetch()?;
+        // Older versions of crate2nix created a symlink to a nix-built directory.
+        match std::fs::symlink_metadata(&fetched_sources) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(&fetched_sources)?,
+            Ok(_) => std::fs::remove_file(&fetched_sources)?,
+            Err(_) => {}
         }
+        std::fs::create_dir_all(&fetched_sources)?;
 
-        let workspace_member_dir = fetched_sources_symlink;
+        let config = config::Config::read_from_or_default(&self.crate2nix_json_path)?;
+        for (name, source) in &config.sources {
+            let link = fetched_sources.join(name);
+            let source_dir = match source {
+                config::Source::Nix { .. } => {
+                    download_and_link_out_of_tree_sources(
+                        &*self.reporter,
+                        self.project_dir(),
+                        self.sources_nix(),
+                        &link,
+                        &format!("fetchedSourcesByName.\"{}\"", name),
+                    )
+                    .map_err(|e| crate::Error::source_fetch(name, e))?;
+                    continue;
+                }
+                config::Source::Path { path } => {
+                    let path = self.project_dir().join(path);
+                    path.canonicalize().map_err(|e| {
+                        crate::Error::source_fetch(
+                            name,
+                            format_err!("while resolving {}: {}", path.to_string_lossy(), e),
+                        )
+                    })?
+                }
+                _ => {
+                    let caption = format!("Fetching {}", name);
+                    self.reporter.report(&Event::TaskStarted {
+                        caption: caption.clone(),
+                    });
+                    let source_dir = cache.get_or_fetch(source);
+                    self.reporter.report(&Event::TaskFinished {
+                        caption,
+                        success: source_dir.is_ok(),
+                    });
+                    source_dir.map_err(|e| crate::Error::source_fetch(name, e))?
+                }
+            };
+            std::os::unix::fs::symlink(&source_dir, &link)
+                .with_context(|| format!("while linking {}", link.to_string_lossy()))?;
+        }
+        self.reporter.report(&Event::PhaseFinished {
+            phase: Phase::FetchSources,
+        });
+
+        Ok(fetched_sources)
+    }
+
+    /// Fetches the sources and returns the paths to their Cargo.tomls.
+    pub fn get_cargo_tomls(&self) -> Result<Vec<PathBuf>, Error> {
+        let workspace_member_dir = self.fetch()?;
         let mut cargo_tomls: Vec<PathBuf> = Vec::new();
         for entry in std::fs::read_dir(&workspace_member_dir).map_err(|e| {
             format_err!(
@@ -192,19 +398,19 @@ impl<'a> FetchedSources<'a> {
             if path.is_dir() {
                 let cargo_toml = path.join("Cargo.toml");
                 if !cargo_toml.exists() {
-                    eprintln!(
-                        "WARNING: No Cargo.toml found in {}.\n\
-                               This will lead to later failures.",
+                    self.reporter.report(&Event::warning(format!(
+                        "No Cargo.toml found in {}.\n\
+                         This will lead to later failures.",
                         path.to_string_lossy()
-                    );
+                    )));
                 }
                 let cargo_lock = path.join("Cargo.lock");
                 if !cargo_lock.exists() {
-                    eprintln!(
-                        "WARNING: No Cargo.lock found in {}.\n\
-                               This will lead to later failures.",
+                    self.reporter.report(&Event::warning(format!(
+                        "No Cargo.lock found in {}.\n\
+                         This will lead to later failures.",
                         path.to_string_lossy()
-                    );
+                    )));
                 }
                 cargo_tomls.push(cargo_toml);
             }
@@ -215,6 +421,7 @@ impl<'a> FetchedSources<'a> {
 }
 
 fn download_and_link_out_of_tree_sources(
+    reporter: &dyn Reporter,
     project_dir: impl AsRef<Path>,
     sources_nix: impl AsRef<Path>,
     generated_sources_symlink: impl AsRef<Path>,
@@ -224,6 +431,7 @@ fn download_and_link_out_of_tree_sources(
     let sources_nix = sources_nix.as_ref().to_string_lossy().to_string();
     let caption = format!("Fetching sources via {} {}", sources_nix, nix_attr);
     crate::command::run(
+        reporter,
         &caption,
         Command::new("nix").current_dir(&project_dir).args([
             "--show-trace",
@@ -241,3 +449,42 @@ fn download_and_link_out_of_tree_sources(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn resolves_branches_and_tags_of_local_repos() {
+        let repo = TempDir::new("crate2nix_git_ref").unwrap();
+        git(repo.path(), &["init", "-q", "-b", "main"]);
+        git(
+            repo.path(),
+            &["commit", "-q", "--allow-empty", "-m", "first"],
+        );
+        let first = git(repo.path(), &["rev-parse", "HEAD"]);
+        git(repo.path(), &["tag", "-a", "v1", "-m", "v1"]);
+        git(
+            repo.path(),
+            &["commit", "-q", "--allow-empty", "-m", "second"],
+        );
+        let second = git(repo.path(), &["rev-parse", "HEAD"]);
+
+        let url = Url::from_directory_path(repo.path()).unwrap();
+        assert_eq!(resolve_git_ref(&url, "main").unwrap(), second);
+        assert_eq!(resolve_git_ref(&url, "v1").unwrap(), first);
+        assert!(resolve_git_ref(&url, "missing").is_err());
+    }
+}
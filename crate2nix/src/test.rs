@@ -16,7 +16,16 @@ pub fn generate_config() -> crate::GenerateConfig {
         output: "Cargo.nix".into(),
         use_cargo_lock_checksums: true,
         read_crate_hashes: true,
+        use_metadata_cache: false,
         registry_hashes_json: "registry-hashes.json".into(),
+        crate2nix_json: "crate2nix.json".into(),
+        hash_format: Default::default(),
+        output_format: Default::default(),
+        packages: Vec::new(),
+        exclude: Vec::new(),
+        no_dev_dependencies: false,
+        crate_hashes: None,
+        reporter: crate::report::default_reporter(),
     }
 }
 
@@ -78,12 +87,34 @@ impl MetadataEnv {
         }
     }
 
+    /// The package and node of a package that was added before.
+    pub fn package_and_node<'a>(&'a mut self, name: &str) -> PackageAndNode<'a> {
+        let package_idx = self
+            .metadata
+            .packages
+            .iter()
+            .position(|p| p.name == name)
+            .expect("package was added before");
+        let id = self.metadata.packages[package_idx].id.clone();
+        let node_idx = self
+            .mut_resolve()
+            .nodes
+            .iter()
+            .position(|n| n.id == id)
+            .expect("node was added before");
+        PackageAndNode {
+            env: self,
+            package_idx,
+            node_idx,
+        }
+    }
+
     pub fn metadata(&self) -> Metadata {
         self.metadata.clone()
     }
 
     pub fn indexed_metadata(&self) -> crate::IndexedMetadata {
-        crate::IndexedMetadata::new_from(self.metadata()).unwrap()
+        crate::IndexedMetadata::new_from(self.metadata(), &crate::report::TerminalReporter).unwrap()
     }
 
     pub fn close(&mut self) {
@@ -14,3 +14,39 @@ pub fn find_duplicates<'a, T: Ord>(source: impl Iterator<Item = &'a T>) -> Vec<&
     let mut seen = BTreeSet::new();
     source.filter(|v| !seen.insert(*v)).collect()
 }
+
+/// The alphabet used by [`nix_base32::to_nix_base32`] (omitting "e", "o", "u" and "t").
+const NIX_BASE32_ALPHABET: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Decodes a nix base32 string as produced by [`nix_base32::to_nix_base32`].
+///
+/// Returns `None` for invalid characters or if the string does not encode a
+/// whole number of bytes.
+///
+/// ```
+/// use crate2nix::util::from_nix_base32;
+/// let bytes = (0..32).collect::<Vec<u8>>();
+/// assert_eq!(from_nix_base32(&nix_base32::to_nix_base32(&bytes)), Some(bytes));
+/// assert_eq!(from_nix_base32(""), Some(vec![]));
+/// assert_eq!(from_nix_base32("e"), None);
+/// ```
+pub fn from_nix_base32(s: &str) -> Option<Vec<u8>> {
+    let len = s.len() * 5 / 8;
+    if (len * 8).div_ceil(5) != s.len() {
+        return None;
+    }
+    let mut bytes = vec![0u8; len];
+    for (k, c) in s.bytes().enumerate() {
+        let digit = NIX_BASE32_ALPHABET.iter().position(|a| *a == c)? as u16;
+        let n = s.len() - 1 - k;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+        let shifted = digit << j;
+        bytes[i] |= shifted as u8;
+        match bytes.get_mut(i + 1) {
+            Some(next) => *next |= (shifted >> 8) as u8,
+            None if shifted >> 8 != 0 => return None,
+            None => {}
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,97 @@
+//! Polling files for changes, used by `crate2nix generate --watch`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Remembers the modification times of a set of files.
+#[derive(Debug)]
+pub struct FileWatcher {
+    mtimes: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    /// Watches the given files. Files that do not exist (yet) are watched for
+    /// their creation.
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> FileWatcher {
+        FileWatcher {
+            mtimes: files
+                .into_iter()
+                .map(|file| {
+                    let mtime = mtime(&file);
+                    (file, mtime)
+                })
+                .collect(),
+        }
+    }
+
+    /// The files which were created, deleted or modified since the last call.
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (file, last_mtime) in self.mtimes.iter_mut() {
+            let mtime = mtime(file);
+            if mtime != *last_mtime {
+                *last_mtime = mtime;
+                changed.push(file.clone());
+            }
+        }
+        changed
+    }
+
+    /// Blocks until at least one file changed and returns the changed files.
+    ///
+    /// After the first change, waits for one more interval so that editors
+    /// writing multiple files result in a single change.
+    pub fn wait_for_changes(&mut self, interval: Duration) -> Vec<PathBuf> {
+        loop {
+            std::thread::sleep(interval);
+            let mut changed = self.changed_files();
+            if !changed.is_empty() {
+                std::thread::sleep(interval);
+                changed.extend(self.changed_files());
+                changed.sort();
+                changed.dedup();
+                return changed;
+            }
+        }
+    }
+}
+
+fn mtime(file: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn reports_created_modified_and_deleted_files() {
+        let mut env = test::MetadataEnv::default();
+        let dir = env.temp_dir();
+        let existing = dir.join("Cargo.toml");
+        let created = dir.join("Cargo.lock");
+        std::fs::write(&existing, "").unwrap();
+        let mut watcher = FileWatcher::new(vec![existing.clone(), created.clone()]);
+
+        assert!(watcher.changed_files().is_empty());
+
+        std::fs::write(&created, "").unwrap();
+        assert_eq!(watcher.changed_files(), vec![created.clone()]);
+
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&existing)
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(watcher.changed_files(), vec![existing.clone()]);
+
+        std::fs::remove_file(&created).unwrap();
+        assert_eq!(watcher.changed_files(), vec![created]);
+        assert!(watcher.changed_files().is_empty());
+
+        env.close();
+    }
+}
@@ -17,11 +17,62 @@
 , targetFeatures ? [ ]
 , extraTargetFlags ? { }
 , release ? true
+, profile ? if release then "release" else "dev"
+, cargoProfiles ? { }
+, rustcVersion ? null
 ,
-}:
+}@callArgs:
 rec {
   # #}
 
+  /*
+    The selected cargo profile from `cargoProfiles`: whether it is based on
+    `release` and the rustc flags for its settings.
+  */
+  cargoProfile =
+    if cargoProfiles == { } then
+      { }
+    else
+      cargoProfiles.${profile} or (throw ''
+        Unknown cargo profile '${profile}', available: ${lib.concatStringsSep ", " (builtins.attrNames cargoProfiles)}
+      '');
+
+  /*
+    The version of the rustc that `buildRustCrateForPkgs` uses for `pkgs`, checked
+    against the `rust-version` of the crates: the given `rustcVersion`, the version
+    of `pkgs.rustc` for the default `buildRustCrateForPkgs` and null, i.e. no
+    check, for a custom one.
+  */
+  rustcVersionFor =
+    pkgs:
+    if callArgs ? rustcVersion then
+      rustcVersion
+    else if callArgs ? buildRustCrateForPkgs || (callArgs.buildRustCrate or null) != null then
+      null
+    else
+      pkgs.rustc.version or null;
+
+  /*
+    A `preBuild` hook for crates with `lintFlags`.
+
+    `buildRustCrate` calls rustc with `--cap-lints allow`, which would turn the
+    lint flags into no-ops, and rustc ignores a second `--cap-lints`.
+  */
+  uncapLints = ''
+    rustc() {
+      local args=()
+      while [ $# -gt 0 ]; do
+        if [ "$1" = --cap-lints ]; then
+          shift 2
+          continue
+        fi
+        args+=("$1")
+        shift
+      done
+      command rustc "''${args[@]}"
+    }
+  '';
+
   /*
     Target (platform) data for conditional dependencies.
     This corresponds roughly to what buildRustCrate is setting.
@@ -51,11 +102,24 @@ rec {
     , url
     , crate
     , version
-    , sha256
+    , sha256 ? null
+    , # The SRI hash, instead of sha256 for `--hash-format sri`.
+      hash ? null
     ,
     }:
     let
       dl = registries.${url}.dl;
+      # Cargo fills in the hex checksum, older nix versions cannot convert it.
+      checksum =
+        if builtins ? convertHash then
+          builtins.convertHash {
+            hash = if hash != null then hash else "sha256:${sha256}";
+            toHashFormat = "base16";
+          }
+        else if hash != null then
+          hash
+        else
+          sha256;
       tmpl = [
         "{crate}"
         "{version}"
@@ -80,7 +144,7 @@ rec {
         version
         prefix
         (lib.strings.toLower prefix)
-        sha256
+        checksum
       ]
     else
       "${dl}/${crate}/${version}/download";
@@ -261,14 +325,18 @@ rec {
                     defaultCrateOverrides = crateOverrides;
                   }
               );
+          # The rustc of a custom `buildRustCrateForPkgsFunc` is unknown.
+          rustcVersionForPkgs = if buildRustCrateForPkgsFunc != null then _: null else rustcVersionFor;
           builtRustCrates = builtRustCratesWithFeatures {
             inherit packageId features;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
+            inherit rustcVersionForPkgs;
             runTests = false;
           };
           builtTestRustCrates = builtRustCratesWithFeatures {
             inherit packageId features;
             buildRustCrateForPkgsFunc = buildRustCrateForPkgsFuncOverriden;
+            inherit rustcVersionForPkgs;
             runTests = true;
           };
           drv = builtRustCrates.crates.${packageId};
@@ -312,6 +380,8 @@ rec {
     , features
     , crateConfigs ? crates
     , buildRustCrateForPkgsFunc
+    , # The rustc version of buildRustCrateForPkgsFunc for pkgs, null to skip the check.
+      rustcVersionForPkgs ? rustcVersionFor
     , runTests
     , makeTarget ? makeDefaultTarget
     ,
@@ -356,6 +426,8 @@ rec {
             crateConfig = builtins.removeAttrs crateConfig' [
               "resolvedDefaultFeatures"
               "devDependencies"
+              "lintFlags"
+              "rustVersion"
             ];
             devDependencies = lib.optionals (runTests && packageId == rootPackageId) (
               crateConfig'.devDependencies or [ ]
@@ -400,6 +472,7 @@ rec {
             #    ];
             #    # ...
             # }
+            rustcVersion = rustcVersionForPkgs pkgs;
             crateRenames =
               let
                 grouped = lib.groupBy (dependency: dependency.name) dependenciesWithRenames;
@@ -415,30 +488,49 @@ rec {
               in
               lib.mapAttrs (name: builtins.map versionAndRename) grouped;
           in
+          assert lib.assertMsg
+            (
+              rustcVersion == null
+              || !(crateConfig' ? rustVersion)
+              || lib.versionAtLeast rustcVersion crateConfig'.rustVersion
+            )
+            "${crateConfig.crateName}-${crateConfig.version} requires rustc ${crateConfig'.rustVersion or ""} (rust-version) but rustc ${toString rustcVersion} is used. Use a newer toolchain or set `rustcVersion` if it is not detected correctly.";
           buildRustCrateForPkgsFunc pkgs (
             crateConfig
             // {
               src =
-                crateConfig.src or (fetchurl rec {
+                crateConfig.src or (fetchurl ({
                   name = "${crateConfig.crateName}-${crateConfig.version}.tar.gz";
                   # https://www.pietroalbini.org/blog/downloading-crates-io/
                   # Not rate-limited, CDN URL.
                   url = "https://static.crates.io/crates/${crateConfig.crateName}/${crateConfig.crateName}-${crateConfig.version}.crate";
-                  sha256 =
-                    assert (lib.assertMsg (crateConfig ? sha256) "Missing sha256 for ${name}");
-                    crateConfig.sha256;
-                });
+                } // (
+                  # SRI hashes are rendered as `hash`, see `--hash-format`.
+                  if crateConfig ? hash then
+                    { inherit (crateConfig) hash; }
+                  else
+                    {
+                      sha256 =
+                        assert (lib.assertMsg (crateConfig ? sha256) "Missing sha256 for ${crateConfig.crateName}-${crateConfig.version}");
+                        crateConfig.sha256;
+                    }
+                )));
               extraRustcOpts =
-                lib.lists.optional (targetFeatures != [ ])
+                (cargoProfile.crates.${packageId} or cargoProfile.rustcFlags or [ ])
+                ++ (crateConfig'.lintFlags or [ ])
+                ++ lib.lists.optional (targetFeatures != [ ])
                   "-C target-feature=${lib.concatMapStringsSep "," (x: "+${x}") targetFeatures}";
+              release = cargoProfile.release or release;
               inherit
                 features
                 dependencies
                 buildDependencies
                 crateRenames
-                release
                 ;
             }
+            // lib.optionalAttrs (crateConfig' ? lintFlags) {
+              preBuild = uncapLints + (crateConfig.preBuild or "");
+            }
           );
       in
       builtByPackageIdByPkgs;
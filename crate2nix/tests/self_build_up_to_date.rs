@@ -11,31 +11,22 @@ use std::str::FromStr;
 #[test]
 fn self_up_to_date() {
     let metadata = BuildInfo::for_config(
-        &GenerateInfo {
-            crate2nix_arguments: [
-                "generate",
-                "-n",
-                "../nix/nixpkgs.nix",
-                "-f",
-                "./crate2nix/Cargo.toml",
-                "-o",
-                "./crate2nix/Cargo.nix",
-            ]
-            .iter()
-            .map(|s| s.to_string())
-            .collect(),
-            ..GenerateInfo::default()
-        },
-        &GenerateConfig {
-            cargo_toml: vec![PathBuf::from("./Cargo.toml")],
-            output: PathBuf::from("./Cargo.nix"),
-            nixpkgs_path: "../nix/nixpkgs.nix".to_string(),
-            crate_hashes_json: PathBuf::from("./crate-hashes.json"),
-            other_metadata_options: vec![],
-            use_cargo_lock_checksums: true,
-            read_crate_hashes: true,
-            registry_hashes_json: PathBuf::from("./registry-hashes.json"),
-        },
+        &GenerateInfo::new([
+            "generate",
+            "-n",
+            "../nix/nixpkgs.nix",
+            "-f",
+            "./crate2nix/Cargo.toml",
+            "-o",
+            "./crate2nix/Cargo.nix",
+        ]),
+        &GenerateConfig::builder()
+            .cargo_toml("./Cargo.toml")
+            .output("./Cargo.nix")
+            .nixpkgs_path("../nix/nixpkgs.nix")
+            .metadata_options(Vec::<String>::new())
+            .build()
+            .unwrap(),
     )
     .unwrap();
     let rerendered_default_nix = render::CARGO_NIX.render(&metadata).unwrap();
@@ -78,34 +69,30 @@ fn assert_up_to_date(project_dir: &Path) {
     let cargo_toml = project_dir.join("Cargo.toml");
     let output = project_dir.join("Cargo.nix");
     println!("Checking pregenerated {}", output.to_str().unwrap());
-    let config = GenerateConfig {
-        cargo_toml: vec![PathBuf::from("../").join(cargo_toml.clone())],
-        output: PathBuf::from("../").join(output.clone()),
-        nixpkgs_path: "<nixpkgs>".to_string(),
-        crate_hashes_json: PathBuf::from("../")
-            .join(project_dir)
-            .join("./crate-hashes.json"),
-        other_metadata_options: vec![],
-        use_cargo_lock_checksums: true,
-        read_crate_hashes: true,
-        registry_hashes_json: PathBuf::from("../")
-            .join(project_dir)
-            .join("./registry-hashes.json"),
-    };
+    let config = GenerateConfig::builder()
+        .cargo_toml(PathBuf::from("../").join(cargo_toml.clone()))
+        .output(PathBuf::from("../").join(output.clone()))
+        .crate_hashes_json(
+            PathBuf::from("../")
+                .join(project_dir)
+                .join("./crate-hashes.json"),
+        )
+        .registry_hashes_json(
+            PathBuf::from("../")
+                .join(project_dir)
+                .join("./registry-hashes.json"),
+        )
+        .metadata_options(Vec::<String>::new())
+        .build()
+        .unwrap();
     let metadata = BuildInfo::for_config(
-        &GenerateInfo {
-            crate2nix_arguments: [
-                "generate",
-                "-f",
-                cargo_toml.to_str().unwrap(),
-                "-o",
-                output.to_str().unwrap(),
-            ]
-            .iter()
-            .map(|s| s.to_string())
-            .collect(),
-            ..GenerateInfo::default()
-        },
+        &GenerateInfo::new([
+            "generate",
+            "-f",
+            cargo_toml.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]),
         &config,
     )
     .unwrap();
@@ -24,11 +24,65 @@
   buildRustCrateForPkgs ? pkgs: pkgs.buildRustCrate
 , # Optional: default crate overrides
   defaultCrateOverrides ? pkgs.defaultCrateOverrides
+, # Optional: name of a feature profile declared in crate2nix.json
+  profile ? null
+, # Optional: the version of the rustc used by buildRustCrateForPkgs, checked
+  # against the rust-version of the crates. Defaults to the version of
+  # `pkgs.rustc` for the default buildRustCrateForPkgs, the check is disabled
+  # for a custom one. null disables the check.
+  rustcVersion ? null
 ,
-}:
+}@callArgs:
 
 let
-  resolved = builtins.fromJSON (builtins.readFile resolvedJson);
+  resolved =
+    let
+      json = builtins.fromJSON (builtins.readFile resolvedJson);
+    in
+    assert lib.assertMsg (!(json.resolvable or false))
+      "${toString resolvedJson} was generated with --resolvable; build-from-json.nix expects pre-resolved JSON.";
+    json;
+
+  # The crates of the selected feature profile: profiles only list crates
+  # that differ from the top-level ones.
+  resolvedCrates =
+    if profile == null then
+      resolved.crates
+    else
+      let
+        profiles = resolved.profiles or { };
+      in
+      assert lib.assertMsg (profiles ? ${profile})
+        "Unknown feature profile '${profile}', available: ${lib.concatStringsSep ", " (builtins.attrNames profiles)}";
+      resolved.crates // (profiles.${profile}.crates or { });
+
+  # The version of the rustc that buildRustCrateForPkgs uses for cratePkgs.
+  rustcVersionFor =
+    cratePkgs:
+    if callArgs ? rustcVersion then
+      rustcVersion
+    else if callArgs ? buildRustCrateForPkgs then
+      null
+    else
+      cratePkgs.rustc.version or null;
+
+  # A `preBuild` hook for crates with lint flags: buildRustCrate calls rustc
+  # with `--cap-lints allow`, which would turn them into no-ops, and rustc
+  # ignores a second `--cap-lints`.
+  uncapLints = ''
+    rustc() {
+      local args=()
+      while [ $# -gt 0 ]; do
+        if [ "$1" = --cap-lints ]; then
+          shift 2
+          continue
+        fi
+        args+=("$1")
+        shift
+      done
+      command rustc "''${args[@]}"
+    }
+  '';
 
   # Source resolution: given a crate's source info, produce a src path.
   # Local paths in the JSON are relative to workspace root.
@@ -43,20 +97,55 @@ let
       if relPath == "." then src else src + "/${relPath}"
     else if sourceType == "crates-io" then
       pkgs.fetchurl
-        {
+        ({
           name = "${crateInfo.crateName}-${crateInfo.version}.tar.gz";
           url = "https://static.crates.io/crates/${crateInfo.crateName}/${crateInfo.crateName}-${crateInfo.version}.crate";
-          sha256 = crateInfo.sha256;
-        }
+        } // (
+          # Generated with `--hash-format sri`.
+          if crateInfo ? hash then { inherit (crateInfo) hash; } else { inherit (crateInfo) sha256; }
+        ))
     else if sourceType == "git" then
-      builtins.fetchGit
-        {
-          url = source.url;
-          rev = source.rev;
-        }
+      let
+        # Pinned by hash like in Cargo.nix. JSON generated by older versions
+        # of crate2nix has no hash.
+        checkout =
+          if source ? hash || source ? sha256 then
+            pkgs.fetchgit
+              ({
+                url = source.url;
+                rev = source.rev;
+                fetchSubmodules = source.submodules or false;
+              } // (if source ? hash then { inherit (source) hash; } else { inherit (source) sha256; }))
+          else
+            builtins.fetchGit
+              ({
+                url = source.url;
+                rev = source.rev;
+                submodules = source.submodules or false;
+              } // lib.optionalAttrs (source ? ref) { inherit (source) ref; });
+      in
+      if source ? subdir then "${checkout}/${source.subdir}" else checkout
+    else if sourceType == "tarball" then
+      let
+        unpacked = pkgs.fetchzip
+          ({
+            url = source.url;
+          } // (if crateInfo ? hash then { inherit (crateInfo) hash; } else { inherit (crateInfo) sha256; }));
+      in
+      if source ? stripPrefix then "${unpacked}/${source.stripPrefix}" else unpacked
     else
       src;
 
+  # The licenses of an SPDX license expression such as "MIT OR Apache-2.0",
+  # looked up in lib.licenses by their SPDX ID.
+  spdxLicenses =
+    expression:
+    let
+      tokens = lib.splitString " " (builtins.replaceStrings [ "(" ")" "/" ] [ " " " " " " ] expression);
+      ids = builtins.filter (t: t != "" && !(builtins.elem (lib.toUpper t) [ "AND" "OR" "WITH" ])) tokens;
+    in
+    map lib.meta.getLicenseFromSpdxId (lib.unique ids);
+
   # Platform matching: evaluate cfg() strings against stdenv.hostPlatform.
   # This is the cheap O(deps) filtering that stays in Nix.
   platformMatches =
@@ -186,7 +275,7 @@ let
               (
                 packageId: _: buildCrate self cratePkgs buildRustCrate testRootPackageId packageId
               )
-              resolved.crates;
+              resolvedCrates;
             build = go cratePkgs.buildPackages;
           };
         in
@@ -197,7 +286,7 @@ let
   buildCrate =
     self: cratePkgs: buildRustCrate: testRootPackageId: packageId:
     let
-      crateInfo = resolved.crates.${packageId};
+      crateInfo = resolvedCrates.${packageId};
       targetPlatform = cratePkgs.stdenv.hostPlatform;
       isTestRoot = testRootPackageId != null && packageId == testRootPackageId;
 
@@ -206,7 +295,7 @@ let
       depDrv =
         dep:
         let
-          depCrateInfo = resolved.crates.${dep.packageId} or null;
+          depCrateInfo = resolvedCrates.${dep.packageId} or null;
         in
         if depCrateInfo != null && (depCrateInfo.procMacro or false) then
           self.build.crates.${dep.packageId}
@@ -236,13 +325,21 @@ let
           grouped = lib.groupBy (dep: dep.name) renamedDeps;
           versionAndRename = dep: {
             inherit (dep) rename;
-            version = (resolved.crates.${dep.packageId}).version;
+            version = (resolvedCrates.${dep.packageId}).version;
           };
         in
         lib.mapAttrs (_name: builtins.map versionAndRename) grouped;
 
       crateSrc = resolveSrc crateInfo;
+      rustcVersion = rustcVersionFor cratePkgs;
     in
+    assert lib.assertMsg
+      (
+        rustcVersion == null
+        || !(crateInfo ? rustVersion)
+        || lib.versionAtLeast rustcVersion crateInfo.rustVersion
+      )
+      "${crateInfo.crateName}-${crateInfo.version} requires rustc ${crateInfo.rustVersion or ""} (rust-version) but rustc ${toString rustcVersion} is used.";
     buildRustCrate (
       {
         crateName = crateInfo.crateName;
@@ -255,6 +352,13 @@ let
         features = crateInfo.resolvedDefaultFeatures or [ ];
         procMacro = crateInfo.procMacro or false;
         crateBin = crateInfo.crateBin or [ ];
+        meta = lib.filterAttrs (_: v: v != null) {
+          description = crateInfo.description or null;
+          homepage = crateInfo.homepage or crateInfo.repository or null;
+          license = if crateInfo ? license then spdxLicenses crateInfo.license else null;
+          mainProgram = crateInfo.mainProgram or null;
+          knownVulnerabilities = crateInfo.knownVulnerabilities or null;
+        };
       }
       // lib.optionalAttrs isTestRoot {
         buildTests = true;
@@ -274,6 +378,10 @@ let
       // lib.optionalAttrs (crateInfo.libCrateTypes or [ ] != [ ]) {
         type = crateInfo.libCrateTypes;
       }
+      // lib.optionalAttrs (crateInfo.lintFlags or [ ] != [ ]) {
+        extraRustcOpts = crateInfo.lintFlags;
+        preBuild = uncapLints;
+      }
     );
 
   builtCrates = mkBuiltByPackageIdByPkgs { };
@@ -285,7 +393,7 @@ let
     (mkBuiltByPackageIdByPkgs { testRootPackageId = packageId; }).crates.${packageId};
 
 in
-{
+rec {
   workspaceMembers = lib.mapAttrs
     (
       name: packageId: {
@@ -296,6 +404,17 @@ in
     )
     resolved.workspaceMembers;
 
+  # The workspace members that `cargo build` builds by default. Without
+  # `default-members`, cargo builds the root package or all members.
+  defaultMembers = lib.getAttrs
+    (resolved.defaultMembers or (
+      let names = builtins.attrNames resolved.workspaceMembers; in
+      if resolved.root != null
+      then builtins.filter (name: resolved.workspaceMembers.${name} == resolved.root) names
+      else names
+    ))
+    workspaceMembers;
+
   rootCrate =
     if resolved.root != null then
       {
@@ -0,0 +1,9 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+/// Expands to the greeting format string.
+#[proc_macro]
+pub fn greeting(_input: TokenStream) -> TokenStream {
+    "\"Hello, {}!\"".parse().unwrap()
+}
@@ -0,0 +1,6 @@
+//! Helpers for the app.
+
+/// The name to greet.
+pub fn name() -> &'static str {
+    "workspace_lints"
+}
@@ -0,0 +1,5 @@
+fn main() {
+    let greeting = "Hello, workspace_lints_violation!";
+    // Violates the forbidden `unsafe_code` lint, so the build must fail.
+    println!("{}", unsafe { greeting.get_unchecked(..) });
+}
@@ -0,0 +1,3 @@
+pub fn get_info() -> &'static str {
+    "workspace_with_default_members"
+}
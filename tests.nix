@@ -511,6 +511,32 @@ let
       derivationAttrPath = [ "workspaceMembers" "main" ];
     }
 
+    {
+      name = "cargo_profiles";
+      src = ./sample_projects/cargo_profiles;
+      expectedOutput = "Hello, cargo_profiles!";
+    }
+
+    {
+      name = "workspace_lints";
+      src = ./sample_projects/workspace_lints;
+      expectedOutput = "Hello, workspace_lints!";
+      derivationAttrPath = [ "workspaceMembers" "app" ];
+    }
+
+    {
+      name = "rust_version";
+      src = ./sample_projects/rust_version;
+      expectedOutput = "Hello, rust_version!";
+      derivationAttrPath = [ "workspaceMembers" "app" ];
+    }
+
+    {
+      name = "workspace_with_default_members";
+      src = ./sample_projects/workspace_with_default_members;
+      expectedOutput = "Hello, workspace_with_default_members";
+    }
+
     {
       name = "with_problematic_crates";
       src = ./sample_projects/with_problematic_crates;
@@ -789,6 +815,53 @@ in
           grep 'write_output_file' $out/test-list.log
         '';
       };
+
+    # The `[lints]` of workspace members apply despite the `--cap-lints allow`
+    # of buildRustCrate: a member that violates a forbidden lint fails to build.
+    workspace_lints_violation =
+      let
+        generatedCargoNix = tools.generatedCargoNix {
+          name = "workspace_lints_violation";
+          src = ./sample_projects/workspace_lints_violation;
+        };
+        app = (buildTestPkgs.callPackage generatedCargoNix { }).workspaceMembers.app.build;
+        failure = buildTestPkgs.testers.testBuildFailure app;
+      in
+      pkgs.runCommandNoCCLocal "workspace_lints_violation" { } ''
+        grep 'usage of an `unsafe` block' ${failure}/testBuildFailure.log
+        mkdir $out
+      '';
+
+    # The `rust-version` check fails the evaluation for a too old rustc and is
+    # skipped for a custom `buildRustCrateForPkgs` without a `rustcVersion`.
+    rust_version_check =
+      let
+        generatedCargoNix = tools.generatedCargoNix {
+          name = "rust_version_check";
+          src = ./sample_projects/rust_version;
+        };
+        evaluates =
+          args:
+          (builtins.tryEval
+            (buildTestPkgs.callPackage generatedCargoNix args).workspaceMembers.app.build.drvPath
+          ).success;
+        oldRustcPkgs = buildTestPkgs // {
+          rustc = buildTestPkgs.rustc // { version = "1.59.0"; };
+        };
+      in
+      assert lib.assertMsg (!evaluates { rustcVersion = "1.59.0"; })
+        "rustc 1.59.0 was accepted for a crate with rust-version 1.60";
+      assert lib.assertMsg (evaluates { rustcVersion = "1.60.0"; })
+        "rustc 1.60.0 was rejected for a crate with rust-version 1.60";
+      assert lib.assertMsg (!evaluates { pkgs = oldRustcPkgs; })
+        "the version of pkgs.rustc was not checked";
+      assert lib.assertMsg
+        (evaluates {
+          pkgs = oldRustcPkgs;
+          buildRustCrateForPkgs = pkgs: pkgs.buildRustCrate;
+        })
+        "the rust-version check used pkgs.rustc for a custom buildRustCrateForPkgs";
+      pkgs.runCommandNoCCLocal "rust_version_check" { } "mkdir $out";
   }
   // rec {
     #